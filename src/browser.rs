@@ -1,27 +1,399 @@
-#[derive(Debug)]
+use std::{collections::HashMap, sync::mpsc};
+
+use encoding_rs::Encoding;
+use reqwest::Url;
+
+use crate::{
+    engine::{
+        elem, layout_page, style_tree, text, AttrMap, EncodingSource, Media, Page, QuirksMode,
+    },
+    event::Event,
+    loader::{self, Downloaded, Loading, PageProgress, Rendered, Source, View},
+    palette::Palette,
+};
+
+/// The encodings a page can be forced into, in the order the override cycles
+/// through them before going back to sniffing.
+static OVERRIDE_ENCODINGS: [&str; 12] = [
+    "UTF-8",
+    "windows-1252",
+    "ISO-8859-2",
+    "ISO-8859-5",
+    "windows-1251",
+    "KOI8-R",
+    "Shift_JIS",
+    "EUC-JP",
+    "ISO-2022-JP",
+    "GBK",
+    "Big5",
+    "EUC-KR",
+];
+
+/// How many pages back and forward in the history of a tab are kept as they
+/// were left. Older ones are downloaded again.
+const CACHED_VISITS: usize = 10;
+
+/// The keys hint labels are made of, the easiest to reach first.
+const HINT_KEYS: &str = "sadfjklewcmpgh";
+
+#[derive(Debug, Default)]
 pub enum Screen {
     Exit,
+    #[default]
     Main,
     Edit,
+    /// Picking a link in view by typing the label drawn over it.
+    Hint,
 }
 
-impl Default for Screen {
-    fn default() -> Self {
-        Self::Main
+/// A label to type to follow a link or pick a form control in view, drawn
+/// at column `x` of line `y` of the content area.
+#[derive(Debug, PartialEq)]
+pub struct Hint {
+    pub label: String,
+    pub link: usize,
+    pub x: usize,
+    pub y: usize,
+}
+
+/// `count` labels of the same length made of [`HINT_KEYS`], so none of them
+/// starts another.
+fn hint_labels(count: usize) -> Vec<String> {
+    let keys: Vec<char> = HINT_KEYS.chars().collect();
+    let mut labels: Vec<String> = keys.iter().map(char::to_string).collect();
+    while labels.len() < count {
+        labels = labels
+            .iter()
+            .flat_map(|label| keys.iter().map(move |key| format!("{label}{key}")))
+            .collect();
     }
+    labels.truncate(count);
+    labels
 }
 
 #[derive(Debug, Default)]
 pub struct Tab {
     pub url: String,
     pub url_field: String,
-    pub content: String,
-    pub scroll: u16,
+    /// Where the page was loaded from, after any redirects.
+    pub document_url: Option<Url>,
+    /// What urls in the page are relative to: the `<base href>` of the
+    /// page, or else where it was loaded from.
+    pub base_url: Option<Url>,
+    /// The page laid out for the terminal.
+    pub page: Page,
+    pub scroll: usize,
+    /// How many columns the page is scrolled to the right, for lines too
+    /// long for the terminal like those of code blocks.
+    pub scroll_x: usize,
+    /// The load of the page, while it's still coming in.
+    pub loading: Option<Loading>,
+    /// The undecoded body of the page, kept to decode it again when the
+    /// encoding is overridden.
+    pub source: Vec<u8>,
+    pub content_type: Option<String>,
+    pub encoding: Option<(&'static Encoding, EncodingSource)>,
+    /// The encoding the page is to be forced into, or `None` to sniff it.
+    /// It's ahead of `encoding` while the page is decoded again with it.
+    encoding_override: Option<&'static Encoding>,
+    /// The linked and imported stylesheets of the page by url, or `None` for
+    /// the ones that couldn't be fetched.
+    pub stylesheets: HashMap<Url, Option<String>>,
+    /// Whether content the page hides is shown anyway.
+    pub show_hidden: bool,
+    /// Whether the lines of code blocks are numbered.
+    pub line_numbers: bool,
+    /// The link Enter follows, by its index on the page.
+    pub focused_link: Option<usize>,
+    /// What the media queries of the page are matched against.
+    pub media: Media,
+    /// The pages visited before this one, the last one most recently.
+    back: Vec<Visit>,
+    /// The pages gone back from, the last one most recently.
+    forward: Vec<Visit>,
+}
+
+/// A page in the history of a tab, kept as it was left so going back to it
+/// doesn't download it again.
+#[derive(Debug)]
+struct Visit {
+    url: String,
+    document_url: Option<Url>,
+    base_url: Option<Url>,
+    page: Page,
+    scroll: usize,
+    scroll_x: usize,
+    focused_link: Option<usize>,
+    source: Vec<u8>,
+    content_type: Option<String>,
+    encoding: Option<(&'static Encoding, EncodingSource)>,
+    stylesheets: HashMap<Url, Option<String>>,
+    /// How the page was laid out, to lay it out again if that changed while
+    /// it was away. `None` if it was left while being laid out again.
+    view: Option<View>,
+    /// Whether the whole page was in when it was left. Pages left while
+    /// loading are downloaded again.
+    loaded: bool,
+}
+
+impl Visit {
+    /// Drops what was kept of the page, so going back to it downloads it
+    /// again.
+    fn forget(&mut self) {
+        self.page = Page::default();
+        self.source = Vec::new();
+        self.stylesheets = HashMap::new();
+        self.encoding = None;
+        self.loaded = false;
+    }
 }
 
 impl Tab {
-    fn set_url(&mut self) {
-        self.url = self.url_field.clone();
+    /// Leaves the current page for `url`, which is still to be loaded.
+    fn navigate(&mut self, url: String) {
+        if !self.url.is_empty() {
+            let visit = self.take_visit();
+            self.back.push(visit);
+            self.trim_history();
+        }
+        self.forward.clear();
+        self.url_field = url.clone();
+        self.url = url;
+    }
+
+    /// Takes the current page out of the tab, to keep in its history.
+    fn take_visit(&mut self) -> Visit {
+        let loading = self.loading.take();
+        let relayout = loading.as_ref().is_some_and(|loading| loading.relayout);
+        Visit {
+            url: std::mem::take(&mut self.url),
+            document_url: self.document_url.take(),
+            base_url: self.base_url.take(),
+            page: std::mem::take(&mut self.page),
+            scroll: std::mem::take(&mut self.scroll),
+            scroll_x: std::mem::take(&mut self.scroll_x),
+            focused_link: self.focused_link.take(),
+            source: std::mem::take(&mut self.source),
+            content_type: self.content_type.take(),
+            encoding: self.encoding.take(),
+            stylesheets: std::mem::take(&mut self.stylesheets),
+            view: Some(self.view()).filter(|_| !relayout),
+            loaded: loading.is_none() || relayout,
+        }
+    }
+
+    /// Shows a page from the history of the tab again, laid out again only
+    /// if how it's shown changed since. Returns whether it has to be
+    /// downloaded again.
+    fn restore(&mut self, visit: Visit, events: &mpsc::Sender<Event>) -> bool {
+        let laid_out = visit.view == Some(self.view());
+        self.url = visit.url;
+        self.url_field = self.url.clone();
+        self.document_url = visit.document_url;
+        self.base_url = visit.base_url;
+        self.page = visit.page;
+        self.scroll = visit.scroll;
+        self.scroll_x = visit.scroll_x;
+        self.focused_link = visit.focused_link;
+        self.source = visit.source;
+        self.content_type = visit.content_type;
+        self.encoding = visit.encoding;
+        self.encoding_override = self.forced_encoding();
+        self.stylesheets = visit.stylesheets;
+        if visit.loaded && !laid_out {
+            self.refresh(events);
+        }
+        !visit.loaded
+    }
+
+    /// Goes back to the page before this one, if any. Returns whether it
+    /// has to be downloaded again.
+    fn go_back(&mut self, events: &mpsc::Sender<Event>) -> bool {
+        let Some(visit) = self.back.pop() else {
+            return false;
+        };
+        let current = self.take_visit();
+        self.forward.push(current);
+        self.trim_history();
+        self.restore(visit, events)
+    }
+
+    /// Goes forward to the page gone back from, if any. Returns whether it
+    /// has to be downloaded again.
+    fn go_forward(&mut self, events: &mpsc::Sender<Event>) -> bool {
+        let Some(visit) = self.forward.pop() else {
+            return false;
+        };
+        let current = self.take_visit();
+        self.back.push(current);
+        self.trim_history();
+        self.restore(visit, events)
+    }
+
+    /// Forgets the pages further back or forward than [`CACHED_VISITS`].
+    fn trim_history(&mut self) {
+        for history in [&mut self.back, &mut self.forward] {
+            let old = history.len().saturating_sub(CACHED_VISITS);
+            history[..old].iter_mut().for_each(Visit::forget);
+        }
+    }
+
+    /// Starts loading the page at the url of the tab on a worker, which
+    /// sends what it makes of it to `events`.
+    pub fn fetch(&mut self, events: &mpsc::Sender<Event>) {
+        self.start(Source::Url(self.url.clone()), events);
+    }
+
+    /// Starts loading the page read from `body`, which came from the
+    /// document url of the tab, on a worker that sends what it makes of it
+    /// to `events`.
+    #[cfg(test)]
+    pub fn load(
+        &mut self,
+        body: impl std::io::Read + Send + 'static,
+        content_type: Option<String>,
+        events: &mpsc::Sender<Event>,
+    ) {
+        let document_url = self.document_url.clone();
+        self.start(
+            Source::Body(Box::new(body), content_type, document_url),
+            events,
+        );
+    }
+
+    fn start(&mut self, source: Source, events: &mpsc::Sender<Event>) {
+        self.scroll = 0;
+        self.scroll_x = 0;
+        self.focused_link = None;
+        self.source.clear();
+        self.content_type = None;
+        self.encoding = None;
+        self.encoding_override = None;
+        self.stylesheets.clear();
+        self.loading = Some(loader::start(source, self.view(), events));
+    }
+
+    /// Shows what the worker loading the page has made of it so far.
+    fn progress(&mut self, progress: PageProgress, events: &mpsc::Sender<Event>) {
+        self.document_url = progress.document_url;
+        self.content_type = progress.content_type;
+        self.source.extend(progress.source);
+        self.show(progress.rendered);
+        if !progress.done {
+            return;
+        }
+
+        // What changed while the page loaded is caught up on now
+        self.stylesheets = progress.stylesheets;
+        let Some(loading) = self.loading.take() else {
+            return;
+        };
+        if loading.view != self.view() || self.forced_encoding() != self.encoding_override {
+            self.refresh(events);
+        }
+    }
+
+    fn show(&mut self, rendered: Rendered) {
+        self.page = rendered.page;
+        self.base_url = rendered.base_url;
+        self.encoding = rendered.encoding;
+    }
+
+    /// How the page is styled and laid out.
+    fn view(&self) -> View {
+        View {
+            media: self.media,
+            show_hidden: self.show_hidden,
+            line_numbers: self.line_numbers,
+        }
+    }
+
+    /// Shows `message` instead of a page, like when one couldn't be loaded.
+    pub fn show_text(&mut self, message: String) {
+        self.loading = None;
+        self.source.clear();
+        self.encoding = None;
+        self.encoding_override = None;
+        let root = elem("html".to_string(), AttrMap::new(), vec![text(message)]);
+        let styled = style_tree(&root, &[], QuirksMode::NoQuirks, &self.media);
+        self.page = layout_page(&styled, &self.media, false, false);
+    }
+
+    /// Decodes the page again with `encoding`, or with the sniffed encoding
+    /// if `None`, on a worker that sends the page to `events`. Pages that
+    /// are still loading are decoded again once they're in.
+    pub fn set_encoding(
+        &mut self,
+        encoding: Option<&'static Encoding>,
+        events: &mpsc::Sender<Event>,
+    ) {
+        self.encoding_override = encoding;
+        self.refresh(events);
+    }
+
+    /// The encoding the page was forced into, if it was.
+    fn forced_encoding(&self) -> Option<&'static Encoding> {
+        match self.encoding {
+            Some((encoding, EncodingSource::Override)) => Some(encoding),
+            _ => None,
+        }
+    }
+
+    /// Starts or stops showing the content the page hides, to help debug
+    /// why something is missing.
+    pub fn toggle_hidden(&mut self, events: &mpsc::Sender<Event>) {
+        self.show_hidden = !self.show_hidden;
+        self.refresh(events);
+    }
+
+    /// Starts or stops numbering the lines of code blocks.
+    pub fn toggle_line_numbers(&mut self, events: &mpsc::Sender<Event>) {
+        self.line_numbers = !self.line_numbers;
+        self.refresh(events);
+    }
+
+    /// Matches the media queries of the page against `media` from now on.
+    pub fn set_media(&mut self, media: Media, events: &mpsc::Sender<Event>) {
+        if self.media != media {
+            self.media = media;
+            self.refresh(events);
+        }
+    }
+
+    /// Parses and styles the page again on a worker, after something that
+    /// changes how it's shown. Pages that are still loading are laid out
+    /// again once they're in.
+    fn refresh(&mut self, events: &mpsc::Sender<Event>) {
+        if self.loading.is_some() || self.source.is_empty() {
+            return;
+        }
+        let page = Downloaded {
+            source: self.source.clone(),
+            content_type: self.content_type.clone(),
+            document_url: self.document_url.clone(),
+            stylesheets: self.stylesheets.clone(),
+            encoding: self.encoding_override,
+        };
+        let source = Source::Downloaded(Box::new(page));
+        self.loading = Some(loader::start(source, self.view(), events));
+    }
+
+    /// Moves the encoding override on to the next encoding, going back to
+    /// the sniffed one after the last.
+    pub fn cycle_encoding(&mut self, events: &mpsc::Sender<Event>) {
+        let current = self.encoding_override.and_then(|encoding| {
+            OVERRIDE_ENCODINGS
+                .iter()
+                .position(|label| Encoding::for_label(label.as_bytes()) == Some(encoding))
+        });
+        let next = match current {
+            None => Some(0),
+            Some(index) if index + 1 < OVERRIDE_ENCODINGS.len() => Some(index + 1),
+            Some(_) => None,
+        };
+        let encoding =
+            next.and_then(|index| Encoding::for_label(OVERRIDE_ENCODINGS[index].as_bytes()));
+        self.set_encoding(encoding, events);
     }
 
     pub fn scroll_up(&mut self) {
@@ -30,17 +402,70 @@ impl Tab {
         }
     }
 
+    /// Scrolls down until the last line is at the bottom of the view.
     pub fn scroll_down(&mut self, content_area_height: u16) {
-        if self.scroll.checked_add(2).is_some() {
-            let lines: u16 = self.content.lines().count().try_into().unwrap();
-            let limit = match lines.checked_sub(content_area_height) {
-                None => content_area_height,
-                Some(_) => lines - content_area_height,
-            };
-            let scroll = std::cmp::min(self.scroll + 2, limit);
-            self.scroll = scroll;
+        let limit = self
+            .page
+            .height
+            .saturating_sub(usize::from(content_area_height));
+        self.scroll = std::cmp::min(self.scroll.saturating_add(2), limit);
+    }
+
+    /// Where link `index` goes, if its `href` resolved to a url or it's a
+    /// button that submits a form.
+    pub fn link_url(&self, index: usize) -> Option<Url> {
+        let href = self.page.links.get(index)?.as_deref()?;
+        Url::parse(href).ok()
+    }
+
+    /// Moves the focus to the next link, or the previous one if not
+    /// `forward`, going around from the ends of the page. The link is
+    /// scrolled into view if it's out of it.
+    pub fn focus_link(&mut self, forward: bool, content_area_height: u16) {
+        let count = self.page.links.len();
+        if count == 0 {
+            return;
         }
+        let index = match (self.focused_link, forward) {
+            (None, true) => 0,
+            (None, false) => count - 1,
+            (Some(index), true) => (index + 1) % count,
+            (Some(index), false) => (index + count - 1) % count,
+        };
+        self.focused_link = Some(index);
+
+        let height = usize::from(content_area_height);
+        let line = self
+            .page
+            .runs
+            .iter()
+            .find(|run| run.style.link == Some(index))
+            .map(|run| run.y);
+        if let Some(line) = line {
+            if line < self.scroll || line >= self.scroll + height {
+                self.scroll = line.saturating_sub(height / 2);
+            }
+        }
+    }
+
+    pub fn scroll_left(&mut self) {
+        self.scroll_x = self.scroll_x.saturating_sub(4);
     }
+
+    /// Scrolls right until the end of the widest line is in view.
+    pub fn scroll_right(&mut self, content_area_width: u16) {
+        let limit = self
+            .page
+            .width
+            .saturating_sub(usize::from(content_area_width));
+        self.scroll_x = std::cmp::min(self.scroll_x.saturating_add(4), limit);
+    }
+}
+
+/// The tab waiting for load `id`, if any still is.
+fn loading_tab(tabs: &mut [Tab], id: u64) -> Option<&mut Tab> {
+    tabs.iter_mut()
+        .find(|tab| tab.loading.as_ref().is_some_and(|loading| loading.id == id))
 }
 
 #[derive(Debug)]
@@ -50,24 +475,74 @@ pub struct Browser {
     pub should_exit: bool,
     pub current_screen: Screen,
     pub currently_typing: bool,
+    pub palette: Palette,
+    /// Whether pages are styled for print, which often suits a terminal
+    /// better than their screen styles.
+    pub prefer_print: bool,
+    /// The number of the link being typed, to follow with Enter.
+    pub link_number: String,
+    /// What has been typed of a hint label.
+    pub hint_keys: String,
+    /// Whether the link picked with a hint opens in a new tab.
+    pub hint_new_tab: bool,
+    /// The width and height of the area pages are drawn in, in cells.
+    viewport: (u16, u16),
     content_area_height: u16,
+    content_area_width: u16,
+    /// Where the workers loading pages send what they make of them.
+    events: mpsc::Sender<Event>,
+    /// How many ticks have gone by, to animate the loading indicator.
+    pub ticks: usize,
 }
 
 impl Browser {
-    pub fn new() -> Self {
-        let tab = Tab::default();
-        Self {
-            tabs: vec![tab],
+    pub fn new(events: mpsc::Sender<Event>) -> Self {
+        let mut browser = Self {
+            tabs: vec![Tab::default()],
             active_tab: 0,
             should_exit: false,
             current_screen: Screen::Main,
             currently_typing: false,
+            palette: Palette::detect(),
+            prefer_print: false,
+            link_number: String::new(),
+            hint_keys: String::new(),
+            hint_new_tab: false,
+            viewport: (80, 24),
             content_area_height: 0,
+            content_area_width: 0,
+            events,
+            ticks: 0,
+        };
+        browser.update_media();
+        browser
+    }
+
+    pub fn tick(&mut self) {
+        self.ticks = self.ticks.wrapping_add(1);
+    }
+
+    /// Shows what a worker has made of a page so far in the tab waiting for
+    /// it, if any still is.
+    pub fn page_progress(&mut self, id: u64, progress: PageProgress) {
+        if let Some(tab) = loading_tab(&mut self.tabs, id) {
+            tab.progress(progress, &self.events);
         }
     }
 
-    pub fn tick(&self) {
-        todo!()
+    /// Shows why a page couldn't be loaded in the tab waiting for it, if any
+    /// still is.
+    pub fn page_failed(&mut self, id: u64, message: String) {
+        if let Some(tab) = loading_tab(&mut self.tabs, id) {
+            tab.show_text(message);
+        }
+    }
+
+    /// Stops loading the page in the active tab, keeping what is in of it.
+    /// A worker stuck on the network only gives up at its timeouts, but the
+    /// tab no longer waits for it.
+    pub fn stop_loading(&mut self) {
+        self.active_tab_mut().loading = None;
     }
 
     pub fn toggle_typing(&mut self) {
@@ -86,8 +561,40 @@ impl Browser {
         self.tabs.get(self.active_tab).unwrap()
     }
 
+    /// What pages are styled for.
+    fn media(&self) -> Media {
+        let (columns, rows) = self.viewport;
+        Media {
+            print: self.prefer_print,
+            dark: self.palette.background.luminance() < 0.5,
+            ..Media::for_terminal(columns, rows)
+        }
+    }
+
+    fn update_media(&mut self) {
+        let media = self.media();
+        for tab in &mut self.tabs {
+            tab.set_media(media, &self.events);
+        }
+    }
+
+    /// Styles pages for an area of `columns` by `rows` cells, after the
+    /// terminal is resized.
+    pub fn resize(&mut self, columns: u16, rows: u16) {
+        self.viewport = (columns, rows);
+        self.update_media();
+    }
+
+    pub fn toggle_print(&mut self) {
+        self.prefer_print = !self.prefer_print;
+        self.update_media();
+    }
+
     pub fn new_tab(&mut self) {
-        let new_tab = Tab::default();
+        let new_tab = Tab {
+            media: self.media(),
+            ..Default::default()
+        };
         let index = self.tabs.len();
         self.tabs.push(new_tab);
         self.active_tab = index;
@@ -117,23 +624,148 @@ impl Browser {
         }
     }
 
-    pub fn set_content(&mut self, value: String) {
-        self.active_tab_mut().content = value;
+    pub fn cycle_encoding(&mut self) {
+        self.tabs[self.active_tab].cycle_encoding(&self.events);
+    }
+
+    pub fn toggle_hidden(&mut self) {
+        self.tabs[self.active_tab].toggle_hidden(&self.events);
+    }
+
+    pub fn toggle_line_numbers(&mut self) {
+        self.tabs[self.active_tab].toggle_line_numbers(&self.events);
     }
 
     pub fn has_content(&self) -> bool {
-        !self.active_tab().content.is_empty()
+        !self.active_tab().page.runs.is_empty()
+    }
+
+    /// Leaves the page in the active tab for `url`.
+    pub fn open(&mut self, url: String) {
+        self.active_tab_mut().navigate(url);
+        self.load_url();
+    }
+
+    pub fn back(&mut self) {
+        if self.tabs[self.active_tab].go_back(&self.events) {
+            self.load_url();
+        }
+    }
+
+    pub fn forward(&mut self) {
+        if self.tabs[self.active_tab].go_forward(&self.events) {
+            self.load_url();
+        }
+    }
+
+    /// Loads the url of the active tab, or shows why it couldn't be.
+    pub fn load_url(&mut self) {
+        let tab = &mut self.tabs[self.active_tab];
+        tab.fetch(&self.events);
+    }
+
+    pub fn next_link(&mut self) {
+        let height = self.content_area_height;
+        self.active_tab_mut().focus_link(true, height);
+    }
+
+    pub fn prev_link(&mut self) {
+        let height = self.content_area_height;
+        self.active_tab_mut().focus_link(false, height);
+    }
+
+    /// Follows the link whose number was typed, or else the focused one.
+    pub fn follow_link(&mut self) {
+        let typed = std::mem::take(&mut self.link_number);
+        let index = match typed.parse::<usize>() {
+            Ok(number) => number.checked_sub(1),
+            Err(_) => self.active_tab().focused_link,
+        };
+        if let Some(index) = index {
+            self.open_link(index, false);
+        }
+    }
+
+    /// Loads where link `index` of the active tab goes, in a new tab if
+    /// `new_tab`. Form controls that go nowhere are focused instead.
+    fn open_link(&mut self, index: usize, new_tab: bool) {
+        let tab = self.active_tab_mut();
+        let Some(url) = tab.link_url(index) else {
+            if index < tab.page.links.len() {
+                tab.focused_link = Some(index);
+            }
+            return;
+        };
+        if new_tab {
+            self.new_tab();
+        }
+        self.open(url.to_string());
+    }
+
+    /// The labels to pick the links and form controls in view with, each
+    /// over where the link starts showing.
+    pub fn hints(&self) -> Vec<Hint> {
+        let tab = self.active_tab();
+        let (columns, rows) = self.viewport;
+        let columns = tab.scroll_x..tab.scroll_x + usize::from(columns);
+        let mut links: Vec<(usize, usize, usize)> = Vec::new();
+        for run in tab.page.lines(tab.scroll..tab.scroll + usize::from(rows)) {
+            let Some(link) = run.style.link else {
+                continue;
+            };
+            let x = run.x.max(columns.start);
+            let seen = links.iter().any(|&(seen, ..)| seen == link);
+            if !seen && columns.contains(&x) && x < run.x + run.width() {
+                links.push((link, x - columns.start, run.y - tab.scroll));
+            }
+        }
+        hint_labels(links.len())
+            .into_iter()
+            .zip(links)
+            .map(|(label, (link, x, y))| Hint { label, link, x, y })
+            .collect()
+    }
+
+    /// Starts picking a link in view to follow, in a new tab if `new_tab`.
+    pub fn start_hints(&mut self, new_tab: bool) {
+        if !self.hints().is_empty() {
+            self.hint_keys.clear();
+            self.hint_new_tab = new_tab;
+            self.current_screen = Screen::Hint;
+        }
+    }
+
+    pub fn stop_hints(&mut self) {
+        self.hint_keys.clear();
+        self.current_screen = Screen::Main;
     }
 
-    pub fn set_url(&mut self) {
-        self.active_tab_mut().set_url();
+    /// Types `key` of a hint label, following the link once a whole label
+    /// is typed. Keys no label goes on with stop picking.
+    pub fn type_hint(&mut self, key: char) {
+        self.hint_keys.push(key.to_ascii_lowercase());
+        let hints = self.hints();
+        if let Some(hint) = hints.iter().find(|hint| hint.label == self.hint_keys) {
+            let (link, new_tab) = (hint.link, self.hint_new_tab);
+            self.stop_hints();
+            self.open_link(link, new_tab);
+        } else if !hints
+            .iter()
+            .any(|hint| hint.label.starts_with(&self.hint_keys))
+        {
+            self.stop_hints();
+        }
     }
 
     pub fn set_content_area_height(&mut self, height: u16) {
         self.content_area_height = height;
     }
 
-    pub fn scroll(&self) -> &u16 {
+    pub fn set_content_area_width(&mut self, width: u16) {
+        self.content_area_width = width;
+    }
+
+    pub fn scroll(&self) -> &usize {
         &self.active_tab().scroll
     }
 
@@ -146,24 +778,66 @@ impl Browser {
         let active = self.active_tab_mut();
         active.scroll_down(height);
     }
+
+    pub fn scroll_left(&mut self) {
+        self.active_tab_mut().scroll_left();
+    }
+
+    pub fn scroll_right(&mut self) {
+        let width = self.content_area_width;
+        self.active_tab_mut().scroll_right(width);
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{io, time::Duration};
+
+    use encoding_rs::{UTF_8, WINDOWS_1252};
+
     use super::*;
+
+    fn new_browser() -> (Browser, mpsc::Receiver<Event>) {
+        let (sender, receiver) = mpsc::channel();
+        (Browser::new(sender), receiver)
+    }
+
+    /// Hands events from the workers to the browser until no tab is loading.
+    fn finish_loading(browser: &mut Browser, events: &mpsc::Receiver<Event>) {
+        while browser.tabs.iter().any(|tab| tab.loading.is_some()) {
+            let event = events.recv_timeout(Duration::from_secs(5));
+            dispatch(browser, event.expect("the page should load"));
+        }
+    }
+
+    fn dispatch(browser: &mut Browser, event: Event) {
+        match event {
+            Event::PageProgress(id, progress) => browser.page_progress(id, *progress),
+            Event::PageFailed(id, message) => browser.page_failed(id, message),
+            _ => {}
+        }
+    }
+
+    /// Loads `body` into the active tab and waits for it.
+    fn load(browser: &mut Browser, events: &mpsc::Receiver<Event>, body: &[u8]) {
+        let sender = browser.events.clone();
+        let body = io::Cursor::new(body.to_vec());
+        browser.active_tab_mut().load(body, None, &sender);
+        finish_loading(browser, events);
+    }
     #[test]
     fn instantiates_self_correctly() {
-        let browser = Browser::new();
+        let (browser, _events) = new_browser();
         assert_eq!(browser.active_tab, 0);
         assert_eq!(browser.tabs.len(), 1);
         assert_eq!(browser.active_tab().url_field, "".to_string());
         assert_eq!(browser.active_tab().url, "".to_string());
-        assert_eq!(browser.active_tab().content, "".to_string());
+        assert_eq!(browser.active_tab().page.text(), "".to_string());
     }
 
     #[test]
     fn can_not_scroll_below_zero() {
-        let mut browser = Browser::new();
+        let (mut browser, _events) = new_browser();
         assert_eq!(browser.active_tab().scroll, 0);
         browser.set_content_area_height(50);
         browser.scroll_up();
@@ -172,7 +846,9 @@ mod tests {
 
     #[test]
     fn scrolls_only_the_active_tab() {
-        let mut browser = Browser::new();
+        let (mut browser, events) = new_browser();
+        let body = format!("<pre>{}</pre>", "x\n".repeat(60));
+        load(&mut browser, &events, body.as_bytes());
         assert_eq!(browser.active_tab().scroll, 0);
         browser.set_content_area_height(50);
         browser.scroll_down();
@@ -181,16 +857,54 @@ mod tests {
         assert_eq!(browser.active_tab().scroll, 0);
     }
 
+    #[test]
+    fn scrolls_down_to_the_last_line_only() {
+        let (mut browser, events) = new_browser();
+        browser.set_content_area_height(10);
+        load(&mut browser, &events, b"<pre>short</pre>");
+        browser.scroll_down();
+        assert_eq!(browser.active_tab().scroll, 0);
+
+        let body = format!("<pre>{}end</pre>", "x\n".repeat(14));
+        load(&mut browser, &events, body.as_bytes());
+        for _ in 0..10 {
+            browser.scroll_down();
+        }
+        let tab = browser.active_tab();
+        assert_eq!(tab.scroll, 5);
+        let last = tab.page.lines(tab.scroll..tab.scroll + 10).last().unwrap();
+        assert_eq!(last.text, "end");
+    }
+
+    #[test]
+    fn scrolls_right_to_the_end_of_the_widest_line() {
+        let (mut browser, _events) = new_browser();
+        browser.active_tab_mut().show_text("x".repeat(30));
+        browser.set_content_area_width(80);
+        browser.scroll_right();
+        assert_eq!(browser.active_tab().scroll_x, 0);
+
+        browser.set_content_area_width(24);
+        for _ in 0..5 {
+            browser.scroll_right();
+        }
+        assert_eq!(browser.active_tab().scroll_x, 6);
+        browser.scroll_left();
+        browser.scroll_left();
+        browser.scroll_left();
+        assert_eq!(browser.active_tab().scroll_x, 0);
+    }
+
     #[test]
     fn sets_active_tab_content() {
-        let mut browser = Browser::new();
-        browser.set_content("Hello".to_string());
-        assert_eq!(browser.active_tab().content, "Hello".to_string());
+        let (mut browser, _events) = new_browser();
+        browser.active_tab_mut().show_text("Hello".to_string());
+        assert_eq!(browser.active_tab().page.text(), "Hello".to_string());
     }
 
     #[test]
     fn creates_new_tab() {
-        let mut browser = Browser::new();
+        let (mut browser, _events) = new_browser();
         browser.new_tab();
         assert_eq!(browser.active_tab, 1);
         assert_eq!(browser.tabs.len(), 2);
@@ -198,7 +912,7 @@ mod tests {
 
     #[test]
     fn switches_to_next_tab() {
-        let mut browser = Browser::new();
+        let (mut browser, _events) = new_browser();
         browser.new_tab();
         browser.new_tab();
         assert_eq!(browser.tabs.len(), 3);
@@ -214,7 +928,7 @@ mod tests {
 
     #[test]
     fn switches_to_previous_tab() {
-        let mut browser = Browser::new();
+        let (mut browser, _events) = new_browser();
         browser.new_tab();
         browser.new_tab();
         assert_eq!(browser.tabs.len(), 3);
@@ -230,7 +944,7 @@ mod tests {
 
     #[test]
     fn closes_non_last_tab_correctly() {
-        let mut browser = Browser::new();
+        let (mut browser, _events) = new_browser();
         browser.new_tab();
         browser.new_tab();
         browser.new_tab();
@@ -244,7 +958,7 @@ mod tests {
 
     #[test]
     fn closes_last_tab_correctly() {
-        let mut browser = Browser::new();
+        let (mut browser, _events) = new_browser();
         browser.new_tab();
         browser.new_tab();
         browser.new_tab();
@@ -254,4 +968,479 @@ mod tests {
         assert_eq!(browser.tabs.len(), 3);
         assert_eq!(browser.active_tab, 2);
     }
+
+    /// Hands out the parts sent to it as they arrive, ending once the
+    /// sender is dropped. It tells `dropped` when the worker reading it is
+    /// done with it.
+    struct ChannelBody {
+        parts: mpsc::Receiver<String>,
+        dropped: Option<mpsc::Sender<()>>,
+    }
+
+    fn channel_body() -> (mpsc::Sender<String>, ChannelBody) {
+        let (parts, receiver) = mpsc::channel();
+        let body = ChannelBody {
+            parts: receiver,
+            dropped: None,
+        };
+        (parts, body)
+    }
+
+    impl io::Read for ChannelBody {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.parts.recv() {
+                Ok(part) => {
+                    buf[..part.len()].copy_from_slice(part.as_bytes());
+                    Ok(part.len())
+                }
+                Err(_) => Ok(0),
+            }
+        }
+    }
+
+    impl Drop for ChannelBody {
+        fn drop(&mut self) {
+            if let Some(dropped) = &self.dropped {
+                let _ = dropped.send(());
+            }
+        }
+    }
+
+    #[test]
+    fn shows_the_top_of_a_page_while_it_loads() {
+        let (mut browser, events) = new_browser();
+        let (parts, body) = channel_body();
+        let sender = browser.events.clone();
+        browser.active_tab_mut().load(body, None, &sender);
+        // The parser holds back the end of what it has until more comes in
+        let top = format!("<p>top</p>{}", " ".repeat(2000));
+        parts.send(top).unwrap();
+        let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        dispatch(&mut browser, event);
+        assert!(browser.active_tab().loading.is_some());
+        assert_eq!(browser.active_tab().page.text(), "top");
+
+        parts.send("<p>bottom</p>".to_string()).unwrap();
+        drop(parts);
+        finish_loading(&mut browser, &events);
+        assert_eq!(browser.active_tab().page.text(), "top\n\nbottom");
+    }
+
+    #[test]
+    fn stops_loading_and_keeps_what_is_in() {
+        let (mut browser, events) = new_browser();
+        let (parts, mut body) = channel_body();
+        let (dropped, body_dropped) = mpsc::channel();
+        body.dropped = Some(dropped);
+        let sender = browser.events.clone();
+        browser.active_tab_mut().load(body, None, &sender);
+        let top = format!("<p>top</p>{}", " ".repeat(2000));
+        parts.send(top).unwrap();
+        let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        dispatch(&mut browser, event);
+        assert_eq!(browser.active_tab().page.text(), "top");
+
+        browser.stop_loading();
+        assert!(browser.active_tab().loading.is_none());
+        parts.send("<p>bottom</p>".to_string()).unwrap();
+        drop(parts);
+        // The worker lets go of the body once it has stopped, after anything
+        // it would have sent
+        body_dropped.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(events.try_recv().is_err());
+        assert_eq!(browser.active_tab().page.text(), "top");
+    }
+
+    #[test]
+    fn stops_loading_before_fetching_stylesheets() {
+        // Nothing answers here, so a fetch would hang until it timed out
+        let server = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        server.set_nonblocking(true).unwrap();
+        let href = format!("http://{}/style.css", server.local_addr().unwrap());
+
+        let (mut browser, events) = new_browser();
+        let (parts, mut body) = channel_body();
+        let (dropped, body_dropped) = mpsc::channel();
+        body.dropped = Some(dropped);
+        let sender = browser.events.clone();
+        browser.active_tab_mut().load(body, None, &sender);
+        let top = format!(
+            "<link rel=stylesheet href={href}><p>top</p>{}",
+            " ".repeat(2000)
+        );
+        parts.send(top).unwrap();
+        let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        dispatch(&mut browser, event);
+
+        browser.stop_loading();
+        drop(parts);
+        body_dropped.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(events.try_recv().is_err());
+        assert_eq!(
+            server.accept().unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+    }
+
+    #[test]
+    fn shows_why_a_page_failed_to_load() {
+        let (mut browser, events) = new_browser();
+        browser.open("http://[".to_string());
+        assert!(browser.active_tab().loading.is_some());
+        finish_loading(&mut browser, &events);
+        assert!(browser.active_tab().page.text().contains("invalid IPv6"));
+    }
+
+    #[test]
+    fn applies_the_page_styles() {
+        let (mut browser, events) = new_browser();
+        let body =
+            "<style>.ad { display: none }</style><p class=ad>buy<p style='display: none'>now<p>text"
+                .as_bytes()
+                .to_vec();
+        load(&mut browser, &events, &body);
+        assert_eq!(browser.active_tab().page.text(), "text");
+    }
+
+    #[test]
+    fn shows_hidden_content_when_asked() {
+        let (mut browser, events) = new_browser();
+        let body = b"<p hidden>secret<p>text".to_vec();
+        load(&mut browser, &events, &body);
+        assert_eq!(browser.active_tab().page.text(), "text");
+
+        browser.active_tab_mut().scroll = 1;
+        browser.toggle_hidden();
+        // The page stays as it is until the worker has laid it out again
+        let tab = browser.active_tab();
+        assert!(tab.loading.as_ref().is_some_and(|loading| loading.relayout));
+        assert_eq!(tab.page.text(), "text");
+        finish_loading(&mut browser, &events);
+        assert_eq!(browser.active_tab().page.text(), "secret\n\ntext");
+        assert_eq!(browser.active_tab().scroll, 1);
+        browser.toggle_hidden();
+        finish_loading(&mut browser, &events);
+        assert_eq!(browser.active_tab().page.text(), "text");
+    }
+
+    #[test]
+    fn restyles_pages_for_the_media() {
+        let (mut browser, events) = new_browser();
+        let body = b"<style>@media (max-width: 400px) { .wide { display: none } }
+                     @media print { .menu { display: none } }</style>
+              <p class=menu>menu<p class=wide>wide<p>text"
+            .to_vec();
+        load(&mut browser, &events, &body);
+        assert_eq!(browser.active_tab().page.text(), "menu\n\nwide\n\ntext");
+
+        browser.resize(50, 20);
+        finish_loading(&mut browser, &events);
+        assert_eq!(browser.active_tab().page.text(), "menu\n\ntext");
+        browser.toggle_print();
+        finish_loading(&mut browser, &events);
+        assert_eq!(browser.active_tab().page.text(), "text");
+        browser.resize(100, 20);
+        finish_loading(&mut browser, &events);
+        assert_eq!(browser.active_tab().page.text(), "wide\n\ntext");
+    }
+
+    #[test]
+    fn loads_deeply_nested_pages() {
+        let (mut browser, events) = new_browser();
+        let body = format!("{}deep", "<div>".repeat(600));
+        load(&mut browser, &events, body.as_bytes());
+        assert_eq!(browser.active_tab().page.text(), "deep");
+    }
+
+    #[test]
+    fn scrolls_pages_longer_than_a_u16() {
+        let (mut browser, events) = new_browser();
+        let body = format!("<pre>{}</pre>", "x\n".repeat(70_000)).into_bytes();
+        load(&mut browser, &events, &body);
+        assert_eq!(browser.active_tab().page.height, 70_000);
+
+        browser.set_content_area_height(50);
+        browser.active_tab_mut().scroll = 69_940;
+        for _ in 0..10 {
+            browser.scroll_down();
+        }
+        let tab = browser.active_tab();
+        assert_eq!(tab.scroll, 69_950);
+        assert_eq!(tab.page.lines(tab.scroll..tab.scroll + 50).len(), 50);
+    }
+
+    #[test]
+    fn focuses_and_resolves_links() {
+        let (mut browser, events) = new_browser();
+        browser.active_tab_mut().document_url = Url::parse("https://example.com/guide/intro").ok();
+        let body =
+            b"<a href=setup>Setup</a><p style='margin-top: 40em'><a href='/'>Home</a>".to_vec();
+        load(&mut browser, &events, &body);
+        let tab = browser.active_tab();
+        assert_eq!(
+            tab.link_url(0).map(String::from),
+            Some("https://example.com/guide/setup".to_string())
+        );
+        assert_eq!(
+            tab.link_url(1).map(String::from),
+            Some("https://example.com/".to_string())
+        );
+        assert_eq!(tab.link_url(2), None);
+
+        browser.set_content_area_height(20);
+        browser.prev_link();
+        assert_eq!(browser.active_tab().focused_link, Some(1));
+        assert_eq!(browser.active_tab().scroll, 31);
+        browser.next_link();
+        assert_eq!(browser.active_tab().focused_link, Some(0));
+        assert_eq!(browser.active_tab().scroll, 0);
+    }
+
+    #[test]
+    fn scrolls_to_focused_links_just_out_of_view() {
+        let (mut browser, events) = new_browser();
+        let body = format!(
+            "<pre>{}<a href=/last>last</a>\n<a href=/below>below</a></pre>",
+            "x\n".repeat(9)
+        );
+        load(&mut browser, &events, body.as_bytes());
+        browser.set_content_area_height(10);
+        browser.next_link();
+        assert_eq!(browser.active_tab().scroll, 0);
+        browser.next_link();
+        assert_eq!(browser.active_tab().scroll, 5);
+    }
+
+    #[test]
+    fn resolves_links_against_the_base_url() {
+        let (mut browser, events) = new_browser();
+        let links = [
+            "../up",
+            "/root",
+            "?q=1",
+            "#top",
+            "//other.org/x",
+            "mailto:a@b.c",
+        ];
+        let anchors: String = links
+            .iter()
+            .map(|href| format!("<a href='{href}'>link</a>"))
+            .collect();
+        browser.active_tab_mut().document_url = Url::parse("https://example.com/a/b/page?old").ok();
+        load(&mut browser, &events, anchors.as_bytes());
+        let resolved: Vec<String> = (0..links.len())
+            .filter_map(|index| browser.active_tab().link_url(index).map(String::from))
+            .collect();
+        assert_eq!(
+            resolved,
+            [
+                "https://example.com/a/up",
+                "https://example.com/root",
+                "https://example.com/a/b/page?q=1",
+                "https://example.com/a/b/page?old#top",
+                "https://other.org/x",
+                "mailto:a@b.c",
+            ]
+        );
+
+        let body = format!("<base href='/docs/v2/'><base href='/ignored/'>{anchors}");
+        load(&mut browser, &events, body.as_bytes());
+        let tab = browser.active_tab();
+        assert_eq!(
+            tab.base_url.as_ref().map(Url::as_str),
+            Some("https://example.com/docs/v2/")
+        );
+        assert_eq!(
+            tab.link_url(0).map(String::from),
+            Some("https://example.com/docs/up".to_string())
+        );
+    }
+
+    #[test]
+    fn makes_hint_labels_of_one_length() {
+        assert!(hint_labels(0).is_empty());
+        assert_eq!(hint_labels(3), ["s", "a", "d"]);
+        let labels = hint_labels(20);
+        assert_eq!(labels.len(), 20);
+        assert_eq!(&labels[..2], ["ss", "sa"]);
+        assert!(labels.iter().all(|label| label.len() == 2));
+    }
+
+    #[test]
+    fn hints_the_links_in_view() {
+        let (mut browser, events) = new_browser();
+        browser.resize(20, 4);
+        let body = b"<p style='margin: 0'>A <a href=one>one</a> and <a href=two>two</a>
+              <p style='margin: 0'>Here's <a href=three>three, which wraps</a>
+              <p><a href=four>four</a> <button>five</button>"
+            .to_vec();
+        load(&mut browser, &events, &body);
+        assert_eq!(
+            browser.hints(),
+            [
+                Hint {
+                    label: "s".to_string(),
+                    link: 0,
+                    x: 2,
+                    y: 0
+                },
+                Hint {
+                    label: "a".to_string(),
+                    link: 1,
+                    x: 13,
+                    y: 0
+                },
+                Hint {
+                    label: "d".to_string(),
+                    link: 2,
+                    x: 7,
+                    y: 1
+                },
+            ]
+        );
+
+        browser.start_hints(false);
+        assert!(matches!(browser.current_screen, Screen::Hint));
+        browser.type_hint('x');
+        assert!(matches!(browser.current_screen, Screen::Main));
+        assert!(browser.hint_keys.is_empty());
+
+        browser.active_tab_mut().scroll = 2;
+        let links: Vec<usize> = browser.hints().iter().map(|hint| hint.link).collect();
+        assert_eq!(links, [2, 3, 4]);
+
+        // The button goes nowhere, so picking it only focuses it
+        browser.start_hints(false);
+        browser.type_hint('d');
+        assert!(matches!(browser.current_screen, Screen::Main));
+        assert_eq!(browser.active_tab().focused_link, Some(4));
+        assert!(browser.active_tab().loading.is_none());
+    }
+
+    fn visit(browser: &mut Browser, events: &mpsc::Receiver<Event>, url: &str, body: &str) {
+        browser.active_tab_mut().navigate(url.to_string());
+        load(browser, events, body.as_bytes());
+    }
+
+    #[test]
+    fn goes_back_and_forward_without_downloading() {
+        let (mut browser, events) = new_browser();
+        visit(&mut browser, &events, "one.test", "<p>one");
+        browser.active_tab_mut().scroll = 3;
+        visit(&mut browser, &events, "two.test", "<p>two");
+        assert_eq!(browser.active_tab().scroll, 0);
+
+        browser.back();
+        let tab = browser.active_tab();
+        assert_eq!((tab.url.as_str(), tab.scroll), ("one.test", 3));
+        assert_eq!(tab.page.text(), "one");
+        browser.back();
+        assert_eq!(browser.active_tab().url, "one.test");
+
+        browser.forward();
+        assert_eq!(browser.active_tab().page.text(), "two");
+        browser.back();
+        visit(&mut browser, &events, "three.test", "<p>three");
+        browser.forward();
+        assert_eq!(browser.active_tab().page.text(), "three");
+        browser.back();
+        assert_eq!(browser.active_tab().page.text(), "one");
+
+        // Each tab has its own history
+        browser.new_tab();
+        browser.back();
+        assert_eq!(browser.active_tab().url, "");
+    }
+
+    #[test]
+    fn lays_pages_out_again_if_the_terminal_changed() {
+        let (mut browser, events) = new_browser();
+        let page = "<style>@media (max-width: 400px) { p { display: none } }</style><p>wide";
+        visit(&mut browser, &events, "one.test", page);
+        visit(&mut browser, &events, "two.test", "<p>two");
+        browser.resize(40, 20);
+        browser.back();
+        finish_loading(&mut browser, &events);
+        assert_eq!(browser.active_tab().page.text(), "");
+    }
+
+    #[test]
+    fn downloads_pages_left_while_loading_again() {
+        let (mut browser, events) = new_browser();
+        let (_parts, body) = channel_body();
+        let sender = browser.events.clone();
+        let tab = browser.active_tab_mut();
+        tab.navigate("one.test".to_string());
+        tab.load(body, None, &sender);
+        visit(&mut browser, &events, "two.test", "<p>two");
+        let tab = browser.active_tab_mut();
+        assert!(tab.go_back(&sender));
+        assert!(tab.loading.is_none());
+        assert!(!tab.go_forward(&sender));
+        assert_eq!(tab.page.text(), "two");
+    }
+
+    #[test]
+    fn keeps_only_recent_pages_as_they_were_left() {
+        let (mut browser, events) = new_browser();
+        for page in 0..CACHED_VISITS + 2 {
+            visit(&mut browser, &events, &format!("{page}.test"), "<p>page");
+        }
+        let sender = browser.events.clone();
+        let tab = browser.active_tab_mut();
+        let kept: Vec<bool> = tab.back.iter().map(|visit| visit.loaded).collect();
+        assert_eq!(kept.iter().filter(|&&loaded| loaded).count(), CACHED_VISITS);
+        assert!(!kept[0]);
+        assert!(tab.back[0].source.is_empty());
+
+        for _ in 0..CACHED_VISITS {
+            assert!(!tab.go_back(&sender));
+        }
+        assert!(tab.go_back(&sender));
+        assert_eq!(tab.url, "0.test");
+        // Going back pushed as many pages forward, so the furthest is gone
+        let kept = tab.forward.iter().filter(|visit| visit.loaded).count();
+        assert_eq!(kept, CACHED_VISITS);
+        assert!(!tab.forward[0].loaded);
+    }
+
+    #[test]
+    fn follows_only_links_that_exist() {
+        let (mut browser, _events) = new_browser();
+        browser
+            .active_tab_mut()
+            .show_text("No links here".to_string());
+        browser.link_number.push('3');
+        browser.follow_link();
+        assert!(browser.link_number.is_empty());
+        assert_eq!(browser.active_tab().page.text(), "No links here");
+    }
+
+    #[test]
+    fn overrides_the_encoding() {
+        let (mut browser, events) = new_browser();
+        // "Grüße" in windows-1252, served as UTF-8
+        let body = io::Cursor::new(b"<p>Gr\xFC\xDFe</p>".to_vec());
+        let content_type = Some("text/html; charset=utf-8".to_string());
+        let sender = browser.events.clone();
+        browser.active_tab_mut().load(body, content_type, &sender);
+        finish_loading(&mut browser, &events);
+        let tab = browser.active_tab();
+        assert_eq!(tab.encoding, Some((UTF_8, EncodingSource::ContentType)));
+        assert!(tab.page.text().contains("Gr\u{FFFD}\u{FFFD}e"));
+
+        browser.cycle_encoding();
+        browser.cycle_encoding();
+        finish_loading(&mut browser, &events);
+        let tab = browser.active_tab();
+        assert_eq!(tab.encoding, Some((WINDOWS_1252, EncodingSource::Override)));
+        assert!(tab.page.text().contains("Grüße"));
+
+        for _ in 1..OVERRIDE_ENCODINGS.len() {
+            browser.cycle_encoding();
+        }
+        finish_loading(&mut browser, &events);
+        let tab = browser.active_tab();
+        assert_eq!(tab.encoding, Some((UTF_8, EncodingSource::ContentType)));
+    }
 }
@@ -0,0 +1,365 @@
+use super::tokenizer::{tokenize, Token};
+use super::{split, trim, values, Value};
+
+/// How wide a terminal cell is taken to be, in CSS pixels.
+pub const CELL_WIDTH: f32 = 8.0;
+
+/// How tall a terminal cell is taken to be, in CSS pixels.
+pub const CELL_HEIGHT: f32 = 16.0;
+
+/// What media queries are matched against: the part of the terminal pages
+/// are drawn in, given in CSS pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Media {
+    pub print: bool,
+    pub width: f32,
+    pub height: f32,
+    /// Whether the terminal has a dark background, for
+    /// `prefers-color-scheme`.
+    pub dark: bool,
+}
+
+impl Media {
+    pub fn for_terminal(columns: u16, rows: u16) -> Self {
+        Self {
+            print: false,
+            width: columns as f32 * CELL_WIDTH,
+            height: rows as f32 * CELL_HEIGHT,
+            dark: true,
+        }
+    }
+}
+
+impl Default for Media {
+    fn default() -> Self {
+        Self::for_terminal(80, 24)
+    }
+}
+
+/// A comma separated list of media queries, which matches when any of them
+/// does. An empty list matches everything.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MediaList {
+    pub queries: Vec<MediaQuery>,
+}
+
+/// A media query like `screen and (min-width: 600px)`. Queries that can't be
+/// read are kept as ones that never match, as the spec asks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MediaQuery {
+    pub negated: bool,
+    /// The media type, absent when the query only tests features.
+    pub media_type: Option<String>,
+    pub conditions: Vec<Condition>,
+}
+
+/// A test of one media feature, with ranges like `400px < width < 800px`
+/// split into one condition for each side.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Condition {
+    /// The feature, without any `min-` or `max-` prefix.
+    pub feature: String,
+    /// How the feature compares to a value, absent for a plain `(feature)`
+    /// that tests if the feature is there at all.
+    pub test: Option<(Comparison, Value)>,
+}
+
+/// How a feature compares to a value, with the feature on the left.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Equal,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    /// The comparison with its sides swapped, for `600px < width`.
+    fn flip(self) -> Self {
+        match self {
+            Self::Equal => Self::Equal,
+            Self::Less => Self::Greater,
+            Self::LessOrEqual => Self::GreaterOrEqual,
+            Self::Greater => Self::Less,
+            Self::GreaterOrEqual => Self::LessOrEqual,
+        }
+    }
+}
+
+/// The value of a feature of the terminal.
+enum FeatureValue {
+    Number(f32),
+    Keyword(&'static str),
+}
+
+impl MediaList {
+    /// Parses a media list, as in the `media` attribute of a `<link>`.
+    pub fn parse(source: &str) -> Self {
+        Self::from_tokens(&tokenize(source))
+    }
+
+    pub(super) fn from_tokens(tokens: &[Token]) -> Self {
+        let tokens = trim(tokens);
+        if tokens.is_empty() {
+            return Self::default();
+        }
+        let queries = split(tokens, &Token::Comma)
+            .into_iter()
+            .map(|tokens| {
+                MediaQuery::parse(trim(tokens)).unwrap_or(MediaQuery {
+                    negated: true,
+                    media_type: Some("all".to_string()),
+                    conditions: Vec::new(),
+                })
+            })
+            .collect();
+        Self { queries }
+    }
+
+    pub fn matches(&self, media: &Media) -> bool {
+        self.queries.is_empty() || self.queries.iter().any(|query| query.matches(media))
+    }
+}
+
+impl MediaQuery {
+    fn parse(tokens: &[Token]) -> Option<Self> {
+        let mut words = tokens
+            .iter()
+            .filter(|token| **token != Token::Whitespace)
+            .peekable();
+        let mut query = Self {
+            negated: false,
+            media_type: None,
+            conditions: Vec::new(),
+        };
+
+        let keyword = |token: Option<&&Token>, word: &str| matches!(token, Some(Token::Ident(ident)) if ident.eq_ignore_ascii_case(word));
+        if keyword(words.peek(), "not") {
+            query.negated = true;
+            words.next();
+        } else if keyword(words.peek(), "only") {
+            words.next();
+        }
+        if let Some(Token::Ident(media_type)) = words.peek() {
+            query.media_type = Some(media_type.to_ascii_lowercase());
+            words.next();
+            if words.peek().is_some() {
+                // `and` has to lead on to a condition
+                if !keyword(words.next().as_ref(), "and") || words.peek().is_none() {
+                    return None;
+                }
+            }
+        } else if query.negated {
+            // `not` only goes with a media type
+            return None;
+        }
+
+        // What's left are `(feature)` blocks joined by `and`
+        let rest: Vec<Token> = words.cloned().collect();
+        let mut i = 0;
+        while i < rest.len() {
+            let Token::OpenParen = rest[i] else {
+                return None;
+            };
+            let end = rest[i..]
+                .iter()
+                .position(|token| *token == Token::CloseParen)?
+                + i;
+            query
+                .conditions
+                .extend(Condition::parse(&rest[i + 1..end])?);
+            i = end + 1;
+            if i < rest.len() {
+                if !keyword(Some(&&rest[i]), "and") {
+                    return None;
+                }
+                i += 1;
+                if i == rest.len() {
+                    return None;
+                }
+            }
+        }
+        (query.media_type.is_some() || !query.conditions.is_empty()).then_some(query)
+    }
+
+    fn matches(&self, media: &Media) -> bool {
+        let type_matches = match self.media_type.as_deref() {
+            None | Some("all") => true,
+            Some("screen") => !media.print,
+            Some("print") => media.print,
+            Some(_) => false,
+        };
+        let matches = type_matches && self.conditions.iter().all(|c| c.matches(media));
+        matches != self.negated
+    }
+}
+
+impl Condition {
+    /// Parses the inside of a `(...)`, which the whitespace has been taken
+    /// out of.
+    fn parse(tokens: &[Token]) -> Option<Vec<Self>> {
+        let comparison = |tokens: &[Token]| match tokens {
+            [Token::Delim('='), ..] => Some((Comparison::Equal, 1)),
+            [Token::Delim('<'), Token::Delim('='), ..] => Some((Comparison::LessOrEqual, 2)),
+            [Token::Delim('<'), ..] => Some((Comparison::Less, 1)),
+            [Token::Delim('>'), Token::Delim('='), ..] => Some((Comparison::GreaterOrEqual, 2)),
+            [Token::Delim('>'), ..] => Some((Comparison::Greater, 1)),
+            _ => None,
+        };
+        let value = |tokens: &[Token]| match values(tokens)?.as_slice() {
+            [value] => Some(value.clone()),
+            // Ratios like `16/9` are left out
+            _ => None,
+        };
+        let feature = |name: &str| Self {
+            feature: name.to_ascii_lowercase(),
+            test: None,
+        };
+
+        match tokens {
+            [Token::Ident(name)] => Some(vec![feature(name)]),
+            [Token::Ident(name), Token::Colon, rest @ ..] => {
+                let name = name.to_ascii_lowercase();
+                let (feature, comparison) =
+                    match (name.strip_prefix("min-"), name.strip_prefix("max-")) {
+                        (Some(feature), _) => (feature, Comparison::GreaterOrEqual),
+                        (_, Some(feature)) => (feature, Comparison::LessOrEqual),
+                        _ => (name.as_str(), Comparison::Equal),
+                    };
+                Some(vec![Self {
+                    feature: feature.to_string(),
+                    test: Some((comparison, value(rest)?)),
+                }])
+            }
+            // `width >= 600px`
+            [Token::Ident(name), rest @ ..] => {
+                let (comparison, len) = comparison(rest)?;
+                Some(vec![Self {
+                    test: Some((comparison, value(&rest[len..])?)),
+                    ..feature(name)
+                }])
+            }
+            // `600px < width` or `400px < width <= 800px`
+            [first, rest @ ..] => {
+                let (left, len) = comparison(rest)?;
+                let Some(Token::Ident(name)) = rest.get(len) else {
+                    return None;
+                };
+                let mut conditions = vec![Self {
+                    test: Some((left.flip(), value(std::slice::from_ref(first))?)),
+                    ..feature(name)
+                }];
+                let rest = &rest[len + 1..];
+                if !rest.is_empty() {
+                    let (right, len) = comparison(rest)?;
+                    conditions.push(Self {
+                        test: Some((right, value(&rest[len..])?)),
+                        ..feature(name)
+                    });
+                }
+                Some(conditions)
+            }
+            [] => None,
+        }
+    }
+
+    fn matches(&self, media: &Media) -> bool {
+        let Some(actual) = feature_value(&self.feature, media) else {
+            return false;
+        };
+        let Some((comparison, expected)) = &self.test else {
+            return match actual {
+                FeatureValue::Number(number) => number != 0.0,
+                FeatureValue::Keyword(keyword) => keyword != "none",
+            };
+        };
+        match (actual, expected) {
+            (FeatureValue::Keyword(actual), Value::Keyword(expected)) => {
+                *comparison == Comparison::Equal && actual == expected
+            }
+            (FeatureValue::Number(actual), expected) => {
+                let Some(expected) = expected.to_px(media) else {
+                    return false;
+                };
+                match comparison {
+                    Comparison::Equal => actual == expected,
+                    Comparison::Less => actual < expected,
+                    Comparison::LessOrEqual => actual <= expected,
+                    Comparison::Greater => actual > expected,
+                    Comparison::GreaterOrEqual => actual >= expected,
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+fn feature_value(feature: &str, media: &Media) -> Option<FeatureValue> {
+    let value = match feature {
+        "width" | "device-width" => FeatureValue::Number(media.width),
+        "height" | "device-height" => FeatureValue::Number(media.height),
+        "orientation" => match media.height > media.width {
+            true => FeatureValue::Keyword("portrait"),
+            false => FeatureValue::Keyword("landscape"),
+        },
+        "color" => FeatureValue::Number(8.0),
+        "monochrome" | "grid" => FeatureValue::Number(0.0),
+        "hover" | "any-hover" | "pointer" | "any-pointer" | "scripting" => {
+            FeatureValue::Keyword("none")
+        }
+        "prefers-reduced-motion" => FeatureValue::Keyword("reduce"),
+        "prefers-color-scheme" => match media.dark {
+            true => FeatureValue::Keyword("dark"),
+            false => FeatureValue::Keyword("light"),
+        },
+        _ => return None,
+    };
+    Some(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Media, MediaList};
+
+    #[test]
+    fn matches_media_types() {
+        let screen = Media::default();
+        let print = Media {
+            print: true,
+            ..screen
+        };
+        let matches = |list: &str, media| MediaList::parse(list).matches(media);
+        assert!(matches("", &screen));
+        assert!(matches("all", &print));
+        assert!(matches("screen, print", &print));
+        assert!(!matches("print", &screen));
+        assert!(matches("not print", &screen));
+        assert!(!matches("only screen", &print));
+        assert!(!matches("tv", &screen));
+        assert!(!matches("screen and", &screen));
+        assert!(!matches("garbage (", &screen));
+    }
+
+    #[test]
+    fn matches_media_features() {
+        // 640 by 384 pixels
+        let media = Media::default();
+        let matches = |list: &str| MediaList::parse(list).matches(&media);
+        assert!(matches("(max-width: 640px)"));
+        assert!(!matches("(min-width: 641px)"));
+        assert!(matches(
+            "screen and (min-width: 40em) and (orientation: landscape)"
+        ));
+        assert!(matches("(width >= 600px)"));
+        assert!(!matches("(width < 600px)"));
+        assert!(matches("(600px < width <= 800px)"));
+        assert!(!matches("(700px < width <= 800px)"));
+        assert!(matches("(max-height: 400px)"));
+        assert!(matches("(prefers-color-scheme: dark)"));
+        assert!(!matches("(hover)"));
+        assert!(matches("(hover: none)"));
+        assert!(!matches("(min-resolution: 2dppx)"));
+        assert!(matches("(min-resolution: 2dppx), (color)"));
+    }
+}
@@ -0,0 +1,913 @@
+pub use self::color::Color;
+pub use self::media::{Media, MediaList, CELL_HEIGHT, CELL_WIDTH};
+use self::tokenizer::{tokenize, Token};
+
+mod color;
+mod media;
+mod tokenizer;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Stylesheet {
+    pub imports: Vec<Import>,
+    pub rules: Vec<Rule>,
+}
+
+/// A stylesheet `@import`ed at the top of another.
+#[derive(Debug, PartialEq)]
+pub struct Import {
+    /// The url as written, to be resolved against that of the stylesheet.
+    pub url: String,
+    pub media: MediaList,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Rule {
+    pub selectors: Vec<Selector>,
+    pub declarations: Vec<Declaration>,
+    /// The media lists of the `@media` rules and stylesheets the rule is
+    /// in, from the outside in. All of them have to match for it to apply.
+    pub media: Vec<MediaList>,
+}
+
+/// A complex selector: compound selectors joined by combinators, e.g.
+/// `ul > li.item a`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Selector {
+    /// The compound selectors from left to right.
+    pub compounds: Vec<CompoundSelector>,
+    /// `combinators[i]` joins `compounds[i]` and `compounds[i + 1]`.
+    pub combinators: Vec<Combinator>,
+}
+
+/// How specific a selector is: its ids, then its classes, attributes and
+/// pseudo-classes, then its types and pseudo-elements.
+pub type Specificity = (usize, usize, usize);
+
+impl Selector {
+    pub fn specificity(&self) -> Specificity {
+        let parts = self.compounds.iter().flat_map(|compound| &compound.parts);
+        let (mut a, mut b, mut c) = (0, 0, 0);
+        for part in parts {
+            match part {
+                SimpleSelector::Universal => {}
+                SimpleSelector::Id(_) => a += 1,
+                SimpleSelector::Class(_)
+                | SimpleSelector::Attribute(_)
+                | SimpleSelector::PseudoClass(_) => b += 1,
+                SimpleSelector::Type(_) | SimpleSelector::PseudoElement(_) => c += 1,
+            }
+        }
+        (a, b, c)
+    }
+}
+
+/// Simple selectors that all have to match the same element, e.g. `a.x[href]`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompoundSelector {
+    pub parts: Vec<SimpleSelector>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SimpleSelector {
+    Universal,
+    Type(String),
+    Id(String),
+    Class(String),
+    Attribute(AttributeSelector),
+    /// A pseudo-class, with the arguments of functional ones like
+    /// `nth-child(2n+1)` kept as written.
+    PseudoClass(String),
+    PseudoElement(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttributeSelector {
+    pub name: String,
+    /// How the value is compared and what to, absent for `[name]`.
+    pub value: Option<(AttributeOperator, String)>,
+    pub case_insensitive: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AttributeOperator {
+    /// `=`
+    Equals,
+    /// `~=`, one of a whitespace separated list
+    Includes,
+    /// `|=`, the value or the value followed by `-`
+    DashMatch,
+    /// `^=`
+    Prefix,
+    /// `$=`
+    Suffix,
+    /// `*=`
+    Substring,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Combinator {
+    /// Whitespace
+    Descendant,
+    /// `>`
+    Child,
+    /// `+`
+    NextSibling,
+    /// `~`
+    SubsequentSibling,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Declaration {
+    pub name: String,
+    pub value: Vec<Value>,
+    pub important: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Keyword(String),
+    Length(f32, Unit),
+    Percentage(f32),
+    Number(f32),
+    Color(Color),
+    String(String),
+    Url(String),
+    Function(String, Vec<Value>),
+    /// Separators like the `,` in a font list or the `/` in `font`.
+    Delim(char),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Unit {
+    Px,
+    Em,
+    Rem,
+    Ex,
+    Ch,
+    Vw,
+    Vh,
+    Pt,
+    Pc,
+    In,
+    Cm,
+    Mm,
+    Other(String),
+}
+
+impl Value {
+    /// A length or number as CSS pixels. Font relative units use the 16px
+    /// default font size, and viewport units the size of `media`.
+    pub fn to_px(&self, media: &Media) -> Option<f32> {
+        let px = match self {
+            Self::Number(number) => *number,
+            Self::Length(length, unit) => match unit {
+                Unit::Px => *length,
+                Unit::Em | Unit::Rem => length * 16.0,
+                Unit::Ex | Unit::Ch => length * 8.0,
+                Unit::Vw => length * media.width / 100.0,
+                Unit::Vh => length * media.height / 100.0,
+                Unit::Pt => length * 96.0 / 72.0,
+                Unit::Pc => length * 16.0,
+                Unit::In => length * 96.0,
+                Unit::Cm => length * 96.0 / 2.54,
+                Unit::Mm => length * 96.0 / 25.4,
+                Unit::Other(_) => return None,
+            },
+            _ => return None,
+        };
+        Some(px)
+    }
+}
+
+impl Unit {
+    fn new(name: &str) -> Self {
+        match name.to_ascii_lowercase().as_str() {
+            "px" => Self::Px,
+            "em" => Self::Em,
+            "rem" => Self::Rem,
+            "ex" => Self::Ex,
+            "ch" => Self::Ch,
+            "vw" => Self::Vw,
+            "vh" => Self::Vh,
+            "pt" => Self::Pt,
+            "pc" => Self::Pc,
+            "in" => Self::In,
+            "cm" => Self::Cm,
+            "mm" => Self::Mm,
+            other => Self::Other(other.to_string()),
+        }
+    }
+}
+
+/// Parses a stylesheet. Rules with invalid selectors, declarations that
+/// can't be read and unknown at-rules are skipped, as the spec asks.
+pub fn parse(source: &str) -> Stylesheet {
+    let tokens = tokenize(source);
+    let mut parser = Parser {
+        tokens: &tokens,
+        pos: 0,
+    };
+    parser.stylesheet()
+}
+
+/// Parses a list of declarations, as found in a `style` attribute.
+pub fn parse_declarations(source: &str) -> Vec<Declaration> {
+    declarations(&tokenize(source))
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn consume(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.pos)?;
+        self.pos += 1;
+        Some(token)
+    }
+
+    /// Consumes a token along with the rest of the block or function it
+    /// opens, if any.
+    fn consume_component(&mut self) {
+        let mut depth = 0usize;
+        while let Some(token) = self.consume() {
+            match token {
+                Token::OpenCurly | Token::OpenSquare | Token::OpenParen | Token::Function(_) => {
+                    depth += 1
+                }
+                Token::CloseCurly | Token::CloseSquare | Token::CloseParen => {
+                    depth = depth.saturating_sub(1)
+                }
+                _ => {}
+            }
+            if depth == 0 {
+                return;
+            }
+        }
+    }
+
+    /// The tokens from here up to (not including) the first of `ends` found
+    /// outside any block.
+    fn consume_until(&mut self, ends: &[Token]) -> &'a [Token] {
+        let start = self.pos;
+        while self.peek().is_some_and(|token| !ends.contains(token)) {
+            self.consume_component();
+        }
+        &self.tokens[start..self.pos]
+    }
+
+    /// The contents of the `{}` block starting here.
+    fn consume_block(&mut self) -> &'a [Token] {
+        let start = self.pos + 1;
+        self.consume_component();
+        let end = match self.tokens[..self.pos].last() {
+            Some(Token::CloseCurly) if self.pos > start => self.pos - 1,
+            _ => self.pos,
+        };
+        &self.tokens[start..end]
+    }
+
+    fn stylesheet(&mut self) -> Stylesheet {
+        let mut stylesheet = Stylesheet::default();
+        // `@import` only counts before any other rule but `@charset`
+        let mut imports_allowed = true;
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Whitespace | Token::Cdo | Token::Cdc => self.pos += 1,
+                Token::AtKeyword(name) => {
+                    let (prelude, block) = self.at_rule();
+                    match name.to_ascii_lowercase().as_str() {
+                        "charset" => {}
+                        "import" if imports_allowed => stylesheet.imports.extend(import(prelude)),
+                        "media" => {
+                            imports_allowed = false;
+                            let Some(block) = block else {
+                                continue;
+                            };
+                            let media = MediaList::from_tokens(prelude);
+                            let mut parser = Parser {
+                                tokens: block,
+                                pos: 0,
+                            };
+                            for mut rule in parser.stylesheet().rules {
+                                rule.media.insert(0, media.clone());
+                                stylesheet.rules.push(rule);
+                            }
+                        }
+                        _ => imports_allowed = false,
+                    }
+                }
+                _ => {
+                    imports_allowed = false;
+                    let prelude = self.consume_until(&[Token::OpenCurly]);
+                    if self.peek().is_none() {
+                        break;
+                    }
+                    let block = self.consume_block();
+                    if let Some(selectors) = selectors(prelude) {
+                        stylesheet.rules.push(Rule {
+                            selectors,
+                            declarations: declarations(block),
+                            media: Vec::new(),
+                        });
+                    }
+                }
+            }
+        }
+        stylesheet
+    }
+
+    /// Consumes an at-rule and returns its prelude and block, if it has one.
+    fn at_rule(&mut self) -> (&'a [Token], Option<&'a [Token]>) {
+        self.pos += 1;
+        let prelude = self.consume_until(&[Token::Semicolon, Token::OpenCurly]);
+        let block = match self.peek() {
+            Some(Token::OpenCurly) => Some(self.consume_block()),
+            Some(_) => {
+                self.pos += 1;
+                None
+            }
+            None => None,
+        };
+        (prelude, block)
+    }
+}
+
+/// Reads the url of an `@import` and the media it's for.
+fn import(prelude: &[Token]) -> Option<Import> {
+    let (url, media) = match trim(prelude) {
+        [Token::Url(url) | Token::String(url), rest @ ..] => (url, rest),
+        [Token::Function(name), Token::String(url), rest @ ..]
+            if name.eq_ignore_ascii_case("url") =>
+        {
+            let rest = trim(rest);
+            let [Token::CloseParen, rest @ ..] = rest else {
+                return None;
+            };
+            (url, rest)
+        }
+        _ => return None,
+    };
+    Some(Import {
+        url: url.clone(),
+        media: MediaList::from_tokens(media),
+    })
+}
+
+fn trim(mut tokens: &[Token]) -> &[Token] {
+    while let [Token::Whitespace, rest @ ..] = tokens {
+        tokens = rest;
+    }
+    while let [rest @ .., Token::Whitespace] = tokens {
+        tokens = rest;
+    }
+    tokens
+}
+
+/// Splits `tokens` on the top level occurrences of `separator`.
+fn split<'a>(tokens: &'a [Token], separator: &Token) -> Vec<&'a [Token]> {
+    let mut parser = Parser { tokens, pos: 0 };
+    let mut parts = vec![parser.consume_until(std::slice::from_ref(separator))];
+    while parser.consume().is_some() {
+        parts.push(parser.consume_until(std::slice::from_ref(separator)));
+    }
+    parts
+}
+
+/// Parses a selector list. A single invalid selector invalidates the list.
+fn selectors(tokens: &[Token]) -> Option<Vec<Selector>> {
+    split(tokens, &Token::Comma)
+        .into_iter()
+        .map(|tokens| selector(trim(tokens)))
+        .collect()
+}
+
+fn selector(tokens: &[Token]) -> Option<Selector> {
+    let mut selector = Selector {
+        compounds: vec![CompoundSelector::default()],
+        combinators: Vec::new(),
+    };
+    let mut combinator = None;
+    let mut i = 0;
+    while i < tokens.len() {
+        let current = selector.compounds.last_mut().unwrap();
+        match &tokens[i] {
+            Token::Whitespace => {
+                combinator = combinator.or(Some(Combinator::Descendant));
+                i += 1;
+                continue;
+            }
+            Token::Delim(c @ ('>' | '+' | '~')) => {
+                if combinator.is_some_and(|c| c != Combinator::Descendant) {
+                    return None;
+                }
+                combinator = Some(match c {
+                    '>' => Combinator::Child,
+                    '+' => Combinator::NextSibling,
+                    _ => Combinator::SubsequentSibling,
+                });
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        if let Some(combinator) = combinator.take() {
+            if current.parts.is_empty() {
+                return None;
+            }
+            selector.combinators.push(combinator);
+            selector.compounds.push(CompoundSelector::default());
+        }
+        let current = selector.compounds.last_mut().unwrap();
+        let (part, len) = simple_selector(&tokens[i..])?;
+        // Type selectors have to come first in a compound selector
+        if matches!(part, SimpleSelector::Type(_) | SimpleSelector::Universal)
+            && !current.parts.is_empty()
+        {
+            return None;
+        }
+        current.parts.push(part);
+        i += len;
+    }
+
+    let valid = combinator.is_none() && selector.compounds.iter().all(|c| !c.parts.is_empty());
+    valid.then_some(selector)
+}
+
+/// Parses the simple selector at the start of `tokens`, returning it with the
+/// number of tokens it took.
+fn simple_selector(tokens: &[Token]) -> Option<(SimpleSelector, usize)> {
+    let selector = match tokens {
+        [Token::Ident(name), ..] => (SimpleSelector::Type(name.to_ascii_lowercase()), 1),
+        [Token::Delim('*'), ..] => (SimpleSelector::Universal, 1),
+        [Token::Hash(name, true), ..] => (SimpleSelector::Id(name.clone()), 1),
+        [Token::Delim('.'), Token::Ident(name), ..] => (SimpleSelector::Class(name.clone()), 2),
+        [Token::Colon, Token::Colon, Token::Ident(name), ..] => {
+            (SimpleSelector::PseudoElement(name.to_ascii_lowercase()), 3)
+        }
+        [Token::Colon, Token::Ident(name), ..] => {
+            let name = name.to_ascii_lowercase();
+            // Pseudo-elements from CSS 2 can be written with one colon
+            match matches!(
+                name.as_str(),
+                "before" | "after" | "first-line" | "first-letter"
+            ) {
+                true => (SimpleSelector::PseudoElement(name), 2),
+                false => (SimpleSelector::PseudoClass(name), 2),
+            }
+        }
+        [Token::Colon, Token::Function(name), rest @ ..] => {
+            let mut parser = Parser {
+                tokens: &tokens[1..],
+                pos: 0,
+            };
+            parser.consume_component();
+            if parser.pos < 2 || tokens[parser.pos] != Token::CloseParen {
+                return None;
+            }
+            let arguments = serialize(trim(&rest[..parser.pos - 2]));
+            let name = format!("{}({})", name.to_ascii_lowercase(), arguments);
+            (SimpleSelector::PseudoClass(name), parser.pos + 1)
+        }
+        [Token::OpenSquare, rest @ ..] => {
+            let end = rest.iter().position(|t| *t == Token::CloseSquare)?;
+            let attribute = attribute_selector(trim(&rest[..end]))?;
+            (SimpleSelector::Attribute(attribute), end + 2)
+        }
+        _ => return None,
+    };
+    Some(selector)
+}
+
+fn attribute_selector(tokens: &[Token]) -> Option<AttributeSelector> {
+    let [Token::Ident(name), rest @ ..] = tokens else {
+        return None;
+    };
+    let mut attribute = AttributeSelector {
+        name: name.to_ascii_lowercase(),
+        value: None,
+        case_insensitive: false,
+    };
+    let rest = trim(rest);
+    if rest.is_empty() {
+        return Some(attribute);
+    }
+
+    let (operator, rest) = match rest {
+        [Token::Delim('='), rest @ ..] => (AttributeOperator::Equals, rest),
+        [Token::Delim(c), Token::Delim('='), rest @ ..] => {
+            let operator = match c {
+                '~' => AttributeOperator::Includes,
+                '|' => AttributeOperator::DashMatch,
+                '^' => AttributeOperator::Prefix,
+                '$' => AttributeOperator::Suffix,
+                '*' => AttributeOperator::Substring,
+                _ => return None,
+            };
+            (operator, rest)
+        }
+        _ => return None,
+    };
+    let (value, rest) = match trim(rest) {
+        [Token::Ident(value) | Token::String(value), rest @ ..] => (value.clone(), rest),
+        _ => return None,
+    };
+    match trim(rest) {
+        [] => {}
+        [Token::Ident(flag)] if flag.eq_ignore_ascii_case("i") => attribute.case_insensitive = true,
+        [Token::Ident(flag)] if flag.eq_ignore_ascii_case("s") => {}
+        _ => return None,
+    }
+    attribute.value = Some((operator, value));
+    Some(attribute)
+}
+
+/// Writes tokens back out as CSS, for keeping pseudo-class arguments.
+fn serialize(tokens: &[Token]) -> String {
+    let mut out = String::new();
+    for (i, token) in tokens.iter().enumerate() {
+        // Two numbers in a row like the `2n` and `+1` of `2n+1` were split
+        // on the sign, so it has to be written back
+        let signed = i > 0
+            && matches!(
+                tokens[i - 1],
+                Token::Number(_) | Token::Dimension(..) | Token::Ident(_)
+            );
+        match token {
+            Token::Ident(name) => out.push_str(name),
+            Token::Function(name) => {
+                out.push_str(name);
+                out.push('(');
+            }
+            Token::Hash(name, _) => {
+                out.push('#');
+                out.push_str(name);
+            }
+            Token::String(value) => out.push_str(&format!("{value:?}")),
+            Token::Number(number) if signed => out.push_str(&format!("{number:+}")),
+            Token::Number(number) => out.push_str(&number.to_string()),
+            Token::Percentage(number) => out.push_str(&format!("{number}%")),
+            Token::Dimension(number, unit) if signed => out.push_str(&format!("{number:+}{unit}")),
+            Token::Dimension(number, unit) => out.push_str(&format!("{number}{unit}")),
+            Token::Delim(c) => out.push(*c),
+            Token::Whitespace => out.push(' '),
+            Token::Colon => out.push(':'),
+            Token::Comma => out.push(','),
+            Token::OpenSquare => out.push('['),
+            Token::CloseSquare => out.push(']'),
+            Token::OpenParen => out.push('('),
+            Token::CloseParen => out.push(')'),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Parses the declarations in a block, skipping nested rules and anything
+/// that isn't a `name: value` pair.
+fn declarations(tokens: &[Token]) -> Vec<Declaration> {
+    split(tokens, &Token::Semicolon)
+        .into_iter()
+        .filter_map(|tokens| declaration(trim(tokens)))
+        .collect()
+}
+
+fn declaration(tokens: &[Token]) -> Option<Declaration> {
+    let [Token::Ident(name), rest @ ..] = tokens else {
+        return None;
+    };
+    let [Token::Colon, value @ ..] = trim(rest) else {
+        return None;
+    };
+
+    let mut value = trim(value);
+    let mut important = false;
+    if let [rest @ .., Token::Delim('!'), Token::Ident(word)] = value {
+        if word.eq_ignore_ascii_case("important") {
+            important = true;
+            value = trim(rest);
+        }
+    } else if let [rest @ .., Token::Delim('!'), Token::Whitespace, Token::Ident(word)] = value {
+        if word.eq_ignore_ascii_case("important") {
+            important = true;
+            value = trim(rest);
+        }
+    }
+
+    // Custom properties keep their case
+    let name = match name.starts_with("--") {
+        true => name.clone(),
+        false => name.to_ascii_lowercase(),
+    };
+    Some(Declaration {
+        name,
+        value: values(value)?,
+        important,
+    })
+}
+
+/// Converts the tokens of a declaration value. Blocks and bad tokens make
+/// the whole declaration invalid.
+fn values(tokens: &[Token]) -> Option<Vec<Value>> {
+    let mut parsed = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let value = match &tokens[i] {
+            Token::Whitespace => {
+                i += 1;
+                continue;
+            }
+            Token::Ident(name) => Value::Keyword(name.to_ascii_lowercase()),
+            Token::Number(number) => Value::Number(*number),
+            Token::Percentage(number) => Value::Percentage(*number),
+            Token::Dimension(number, unit) => Value::Length(*number, Unit::new(unit)),
+            Token::Hash(hex, _) => Value::Color(Color::from_hex(hex)?),
+            Token::String(value) => Value::String(value.clone()),
+            Token::Url(url) => Value::Url(url.clone()),
+            Token::Comma => Value::Delim(','),
+            Token::Delim(c) => Value::Delim(*c),
+            Token::Function(name) => {
+                let mut parser = Parser {
+                    tokens: &tokens[i..],
+                    pos: 0,
+                };
+                parser.consume_component();
+                let end = i + parser.pos;
+                let arguments = match tokens[end - 1] {
+                    Token::CloseParen => &tokens[i + 1..end - 1],
+                    _ => &tokens[i + 1..end],
+                };
+                let name = name.to_ascii_lowercase();
+                // `url("x")` is a url like `url(x)`
+                let value = match (name.as_str(), trim(arguments)) {
+                    ("url", [Token::String(url)]) => Value::Url(url.clone()),
+                    _ => Value::Function(name, values(arguments)?),
+                };
+                parsed.push(value);
+                i = end;
+                continue;
+            }
+            _ => return None,
+        };
+        parsed.push(value);
+        i += 1;
+    }
+    Some(parsed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        parse, parse_declarations, AttributeOperator, AttributeSelector, Color, Combinator,
+        CompoundSelector, Declaration, MediaList, Selector, SimpleSelector, Unit, Value,
+    };
+
+    fn compound(parts: Vec<SimpleSelector>) -> CompoundSelector {
+        CompoundSelector { parts }
+    }
+
+    fn keyword(name: &str) -> Value {
+        Value::Keyword(name.to_string())
+    }
+
+    #[test]
+    fn parses_simple_selectors() {
+        let sheet = parse("h1, .note, #main, * { display: block }");
+        let selectors = &sheet.rules[0].selectors;
+        let parts: Vec<&SimpleSelector> =
+            selectors.iter().map(|s| &s.compounds[0].parts[0]).collect();
+        assert_eq!(
+            parts,
+            [
+                &SimpleSelector::Type("h1".to_string()),
+                &SimpleSelector::Class("note".to_string()),
+                &SimpleSelector::Id("main".to_string()),
+                &SimpleSelector::Universal,
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_compound_selectors() {
+        let sheet = parse("A.x.y#z[href^='http' i]:first-child::before {}");
+        assert_eq!(
+            sheet.rules[0].selectors,
+            [Selector {
+                compounds: vec![compound(vec![
+                    SimpleSelector::Type("a".to_string()),
+                    SimpleSelector::Class("x".to_string()),
+                    SimpleSelector::Class("y".to_string()),
+                    SimpleSelector::Id("z".to_string()),
+                    SimpleSelector::Attribute(AttributeSelector {
+                        name: "href".to_string(),
+                        value: Some((AttributeOperator::Prefix, "http".to_string())),
+                        case_insensitive: true,
+                    }),
+                    SimpleSelector::PseudoClass("first-child".to_string()),
+                    SimpleSelector::PseudoElement("before".to_string()),
+                ])],
+                combinators: vec![],
+            }]
+        );
+    }
+
+    #[test]
+    fn computes_specificity() {
+        let specificity =
+            |source: &str| parse(&format!("{source} {{}}")).rules[0].selectors[0].specificity();
+        assert_eq!(specificity("*"), (0, 0, 0));
+        assert_eq!(specificity("li"), (0, 0, 1));
+        assert_eq!(specificity("ul li::before"), (0, 0, 3));
+        assert_eq!(specificity("ul > li.red:first-child"), (0, 2, 2));
+        assert_eq!(specificity("#nav a[href]"), (1, 1, 1));
+    }
+
+    #[test]
+    fn parses_combinators() {
+        let sheet = parse("div  ul>li + p ~ span:nth-child( 2n+1 ) {}");
+        let selector = &sheet.rules[0].selectors[0];
+        assert_eq!(
+            selector.combinators,
+            [
+                Combinator::Descendant,
+                Combinator::Child,
+                Combinator::NextSibling,
+                Combinator::SubsequentSibling,
+            ]
+        );
+        assert_eq!(
+            selector.compounds[4],
+            compound(vec![
+                SimpleSelector::Type("span".to_string()),
+                SimpleSelector::PseudoClass("nth-child(2n+1)".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn drops_rules_with_invalid_selectors() {
+        let sheet = parse("p > > a { color: red } a, 3 { color: red } .ok { color: red } p.{} a >");
+        assert_eq!(sheet.rules.len(), 1);
+        assert_eq!(
+            sheet.rules[0].selectors[0].compounds[0],
+            compound(vec![SimpleSelector::Class("ok".to_string())])
+        );
+    }
+
+    #[test]
+    fn parses_declarations() {
+        let sheet = parse(
+            "p { Color: #f00; margin: 0 auto -2.5EM 10%; font: 12px/1.5 'Fira Code', monospace;
+                 background: url( a.png ) rgba(0, 0, 0, .5) !important; }",
+        );
+        assert_eq!(
+            sheet.rules[0].declarations,
+            [
+                Declaration {
+                    name: "color".to_string(),
+                    value: vec![Value::Color(Color {
+                        r: 255,
+                        g: 0,
+                        b: 0,
+                        a: 255
+                    })],
+                    important: false,
+                },
+                Declaration {
+                    name: "margin".to_string(),
+                    value: vec![
+                        Value::Number(0.0),
+                        keyword("auto"),
+                        Value::Length(-2.5, Unit::Em),
+                        Value::Percentage(10.0),
+                    ],
+                    important: false,
+                },
+                Declaration {
+                    name: "font".to_string(),
+                    value: vec![
+                        Value::Length(12.0, Unit::Px),
+                        Value::Delim('/'),
+                        Value::Number(1.5),
+                        Value::String("Fira Code".to_string()),
+                        Value::Delim(','),
+                        keyword("monospace"),
+                    ],
+                    important: false,
+                },
+                Declaration {
+                    name: "background".to_string(),
+                    value: vec![
+                        Value::Url("a.png".to_string()),
+                        Value::Function(
+                            "rgba".to_string(),
+                            vec![
+                                Value::Number(0.0),
+                                Value::Delim(','),
+                                Value::Number(0.0),
+                                Value::Delim(','),
+                                Value::Number(0.0),
+                                Value::Delim(','),
+                                Value::Number(0.5),
+                            ]
+                        ),
+                    ],
+                    important: true,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_invalid_declarations() {
+        let declarations =
+            parse_declarations("color red; ; width: {1px}; color: #abcd; top: 1px ! important");
+        assert_eq!(
+            declarations,
+            [
+                Declaration {
+                    name: "color".to_string(),
+                    value: vec![Value::Color(Color {
+                        r: 0xaa,
+                        g: 0xbb,
+                        b: 0xcc,
+                        a: 0xdd
+                    })],
+                    important: false,
+                },
+                Declaration {
+                    name: "top".to_string(),
+                    value: vec![Value::Length(1.0, Unit::Px)],
+                    important: true,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_unknown_at_rules() {
+        let sheet = parse(
+            "@charset 'utf-8'; @font-face { font-family: x; src: url(x.woff) }
+             @unknown foo { a { b: c } } @page :first { margin: 1in } <!-- p { color: red } -->",
+        );
+        assert_eq!(sheet.rules.len(), 1);
+        assert_eq!(
+            sheet.rules[0].selectors[0].compounds[0],
+            compound(vec![SimpleSelector::Type("p".to_string())])
+        );
+    }
+
+    #[test]
+    fn collects_imports() {
+        let sheet = parse(
+            "@charset 'utf-8'; @import 'a.css'; @import url(b.css) print; @IMPORT url('c.css');
+             p {} @import 'late.css';",
+        );
+        let urls: Vec<&str> = sheet
+            .imports
+            .iter()
+            .map(|import| import.url.as_str())
+            .collect();
+        assert_eq!(urls, ["a.css", "b.css", "c.css"]);
+        assert_eq!(sheet.imports[1].media, MediaList::parse("print"));
+        assert_eq!(sheet.rules.len(), 1);
+    }
+
+    #[test]
+    fn nests_rules_in_media_rules() {
+        let sheet = parse(
+            "@media screen { a {} @media (min-width: 40em) { b {} } } c {} @media print { d {}",
+        );
+        let media: Vec<&[MediaList]> = sheet
+            .rules
+            .iter()
+            .map(|rule| rule.media.as_slice())
+            .collect();
+        assert_eq!(
+            media,
+            [
+                &[MediaList::parse("screen")][..],
+                &[
+                    MediaList::parse("screen"),
+                    MediaList::parse("(min-width: 40em)")
+                ],
+                &[],
+                &[MediaList::parse("print")],
+            ]
+        );
+    }
+
+    #[test]
+    fn recovers_from_unbalanced_blocks() {
+        let sheet = parse("a { color: red; } b { color: (blue; } c { color: green }");
+        assert_eq!(sheet.rules.len(), 2);
+        let sheet = parse("a { color: red");
+        assert_eq!(sheet.rules[0].declarations[0].value, [keyword("red")]);
+    }
+}
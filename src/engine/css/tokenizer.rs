@@ -0,0 +1,415 @@
+/// A token of the CSS Syntax spec, produced by [`tokenize`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Ident(String),
+    Function(String),
+    AtKeyword(String),
+    /// A `#` followed by a name, and whether the name would be a valid
+    /// identifier (which an id selector needs).
+    Hash(String, bool),
+    String(String),
+    BadString,
+    Url(String),
+    BadUrl,
+    Delim(char),
+    Number(f32),
+    Percentage(f32),
+    Dimension(f32, String),
+    Whitespace,
+    Cdo,
+    Cdc,
+    Colon,
+    Semicolon,
+    Comma,
+    OpenSquare,
+    CloseSquare,
+    OpenParen,
+    CloseParen,
+    OpenCurly,
+    CloseCurly,
+}
+
+struct Tokenizer {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+/// Splits a stylesheet into tokens. Comments are dropped and never fail the
+/// tokenizer; anything malformed comes out as a bad or delimiter token.
+pub fn tokenize(source: &str) -> Vec<Token> {
+    // The spec preprocesses newlines and NULs before tokenizing
+    let source = source
+        .replace("\r\n", "\n")
+        .replace(['\r', '\x0C'], "\n")
+        .replace('\0', "\u{FFFD}");
+    let mut tokenizer = Tokenizer {
+        chars: source.chars().collect(),
+        pos: 0,
+    };
+    let mut tokens = Vec::new();
+    while let Some(token) = tokenizer.next_token() {
+        tokens.push(token);
+    }
+    tokens
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || !c.is_ascii()
+}
+
+fn is_name(c: char) -> bool {
+    is_name_start(c) || c.is_ascii_digit() || c == '-'
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n')
+}
+
+impl Tokenizer {
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn consume(&mut self) -> Option<char> {
+        let c = self.peek(0)?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn is_valid_escape(&self, offset: usize) -> bool {
+        self.peek(offset) == Some('\\') && self.peek(offset + 1).is_some_and(|c| c != '\n')
+    }
+
+    fn starts_identifier(&self, offset: usize) -> bool {
+        match self.peek(offset) {
+            Some('-') => {
+                self.peek(offset + 1)
+                    .is_some_and(|c| is_name_start(c) || c == '-')
+                    || self.is_valid_escape(offset + 1)
+            }
+            Some('\\') => self.is_valid_escape(offset),
+            Some(c) => is_name_start(c),
+            None => false,
+        }
+    }
+
+    fn starts_number(&self) -> bool {
+        let digit = |offset| self.peek(offset).is_some_and(|c: char| c.is_ascii_digit());
+        match self.peek(0) {
+            Some('+' | '-') => digit(1) || (self.peek(1) == Some('.') && digit(2)),
+            Some('.') => digit(1),
+            Some(c) => c.is_ascii_digit(),
+            None => false,
+        }
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        // Comments
+        while self.peek(0) == Some('/') && self.peek(1) == Some('*') {
+            self.pos += 2;
+            while self.pos < self.chars.len()
+                && !(self.peek(0) == Some('*') && self.peek(1) == Some('/'))
+            {
+                self.pos += 1;
+            }
+            self.pos = (self.pos + 2).min(self.chars.len());
+        }
+
+        let c = self.consume()?;
+        let token = match c {
+            c if is_whitespace(c) => {
+                while self.peek(0).is_some_and(is_whitespace) {
+                    self.pos += 1;
+                }
+                Token::Whitespace
+            }
+            '"' | '\'' => self.consume_string(c),
+            '#' => {
+                if self.peek(0).is_some_and(is_name) || self.is_valid_escape(0) {
+                    let is_id = self.starts_identifier(0);
+                    Token::Hash(self.consume_name(), is_id)
+                } else {
+                    Token::Delim('#')
+                }
+            }
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            '[' => Token::OpenSquare,
+            ']' => Token::CloseSquare,
+            '{' => Token::OpenCurly,
+            '}' => Token::CloseCurly,
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            ';' => Token::Semicolon,
+            '+' | '.' => {
+                self.pos -= 1;
+                if self.starts_number() {
+                    self.consume_numeric()
+                } else {
+                    self.pos += 1;
+                    Token::Delim(c)
+                }
+            }
+            '-' => {
+                self.pos -= 1;
+                if self.starts_number() {
+                    self.consume_numeric()
+                } else if self.peek(1) == Some('-') && self.peek(2) == Some('>') {
+                    self.pos += 3;
+                    Token::Cdc
+                } else if self.starts_identifier(0) {
+                    self.consume_ident_like()
+                } else {
+                    self.pos += 1;
+                    Token::Delim('-')
+                }
+            }
+            '<' if self.peek(0) == Some('!')
+                && self.peek(1) == Some('-')
+                && self.peek(2) == Some('-') =>
+            {
+                self.pos += 3;
+                Token::Cdo
+            }
+            '@' if self.starts_identifier(0) => Token::AtKeyword(self.consume_name()),
+            '\\' if self.peek(0).is_some_and(|c| c != '\n') => {
+                self.pos -= 1;
+                self.consume_ident_like()
+            }
+            c if c.is_ascii_digit() => {
+                self.pos -= 1;
+                self.consume_numeric()
+            }
+            c if is_name_start(c) => {
+                self.pos -= 1;
+                self.consume_ident_like()
+            }
+            c => Token::Delim(c),
+        };
+        Some(token)
+    }
+
+    fn consume_escape(&mut self) -> char {
+        let Some(c) = self.consume() else {
+            return char::REPLACEMENT_CHARACTER;
+        };
+        if !c.is_ascii_hexdigit() {
+            return c;
+        }
+        let mut value = c.to_digit(16).unwrap();
+        for _ in 0..5 {
+            match self.peek(0).and_then(|c| c.to_digit(16)) {
+                Some(digit) => {
+                    value = value * 16 + digit;
+                    self.pos += 1;
+                }
+                None => break,
+            }
+        }
+        if self.peek(0).is_some_and(is_whitespace) {
+            self.pos += 1;
+        }
+        match value {
+            0 | 0xD800..=0xDFFF => char::REPLACEMENT_CHARACTER,
+            value => char::from_u32(value).unwrap_or(char::REPLACEMENT_CHARACTER),
+        }
+    }
+
+    fn consume_name(&mut self) -> String {
+        let mut name = String::new();
+        loop {
+            match self.peek(0) {
+                Some(c) if is_name(c) => {
+                    name.push(c);
+                    self.pos += 1;
+                }
+                Some('\\') if self.is_valid_escape(0) => {
+                    self.pos += 1;
+                    name.push(self.consume_escape());
+                }
+                _ => return name,
+            }
+        }
+    }
+
+    fn consume_string(&mut self, quote: char) -> Token {
+        let mut value = String::new();
+        loop {
+            match self.consume() {
+                None => return Token::String(value),
+                Some(c) if c == quote => return Token::String(value),
+                Some('\n') => {
+                    self.pos -= 1;
+                    return Token::BadString;
+                }
+                Some('\\') => match self.peek(0) {
+                    None => {}
+                    Some('\n') => self.pos += 1,
+                    Some(_) => value.push(self.consume_escape()),
+                },
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn consume_number(&mut self) -> f32 {
+        let start = self.pos;
+        if matches!(self.peek(0), Some('+' | '-')) {
+            self.pos += 1;
+        }
+        let digits = |tokenizer: &mut Self| {
+            while tokenizer.peek(0).is_some_and(|c| c.is_ascii_digit()) {
+                tokenizer.pos += 1;
+            }
+        };
+        digits(self);
+        if self.peek(0) == Some('.') && self.peek(1).is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+            digits(self);
+        }
+        if matches!(self.peek(0), Some('e' | 'E')) {
+            let sign = usize::from(matches!(self.peek(1), Some('+' | '-')));
+            if self.peek(1 + sign).is_some_and(|c| c.is_ascii_digit()) {
+                self.pos += 1 + sign;
+                digits(self);
+            }
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        text.parse().unwrap_or(0.0)
+    }
+
+    fn consume_numeric(&mut self) -> Token {
+        let number = self.consume_number();
+        if self.starts_identifier(0) {
+            Token::Dimension(number, self.consume_name())
+        } else if self.peek(0) == Some('%') {
+            self.pos += 1;
+            Token::Percentage(number)
+        } else {
+            Token::Number(number)
+        }
+    }
+
+    fn consume_ident_like(&mut self) -> Token {
+        let name = self.consume_name();
+        if self.peek(0) != Some('(') {
+            return Token::Ident(name);
+        }
+        self.pos += 1;
+        if !name.eq_ignore_ascii_case("url") {
+            return Token::Function(name);
+        }
+
+        // `url(` followed by a quoted string is an ordinary function
+        let mut offset = 0;
+        while self.peek(offset).is_some_and(is_whitespace) {
+            offset += 1;
+        }
+        if matches!(self.peek(offset), Some('"' | '\'')) {
+            return Token::Function(name);
+        }
+        self.pos += offset;
+        self.consume_url()
+    }
+
+    fn consume_url(&mut self) -> Token {
+        let mut url = String::new();
+        loop {
+            match self.consume() {
+                None | Some(')') => return Token::Url(url),
+                Some(c) if is_whitespace(c) => {
+                    while self.peek(0).is_some_and(is_whitespace) {
+                        self.pos += 1;
+                    }
+                    return match self.consume() {
+                        None | Some(')') => Token::Url(url),
+                        Some(_) => self.consume_bad_url(),
+                    };
+                }
+                Some('"' | '\'' | '(') => return self.consume_bad_url(),
+                Some('\\') if self.peek(0).is_some_and(|c| c != '\n') => {
+                    url.push(self.consume_escape());
+                }
+                Some('\\') => return self.consume_bad_url(),
+                Some(c) => url.push(c),
+            }
+        }
+    }
+
+    fn consume_bad_url(&mut self) -> Token {
+        loop {
+            match self.consume() {
+                None | Some(')') => return Token::BadUrl,
+                Some('\\') if self.peek(0).is_some_and(|c| c != '\n') => {
+                    self.consume_escape();
+                }
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{tokenize, Token};
+
+    #[test]
+    fn tokenizes_a_rule() {
+        assert_eq!(
+            tokenize("p.a > #b{margin:-1.5em 10%}"),
+            vec![
+                Token::Ident("p".to_string()),
+                Token::Delim('.'),
+                Token::Ident("a".to_string()),
+                Token::Whitespace,
+                Token::Delim('>'),
+                Token::Whitespace,
+                Token::Hash("b".to_string(), true),
+                Token::OpenCurly,
+                Token::Ident("margin".to_string()),
+                Token::Colon,
+                Token::Dimension(-1.5, "em".to_string()),
+                Token::Whitespace,
+                Token::Percentage(10.0),
+                Token::CloseCurly,
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenizes_strings_urls_and_escapes() {
+        assert_eq!(
+            tokenize(r#"'a\'b' "c\"" url( x.png ) url("y") \31 0 /* gone */ -->"#),
+            vec![
+                Token::String("a'b".to_string()),
+                Token::Whitespace,
+                Token::String("c\"".to_string()),
+                Token::Whitespace,
+                Token::Url("x.png".to_string()),
+                Token::Whitespace,
+                Token::Function("url".to_string()),
+                Token::String("y".to_string()),
+                Token::CloseParen,
+                Token::Whitespace,
+                Token::Ident("10".to_string()),
+                Token::Whitespace,
+                Token::Whitespace,
+                Token::Cdc,
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenizes_bad_strings_and_urls() {
+        assert_eq!(
+            tokenize("'a\nb url(a b)"),
+            vec![
+                Token::BadString,
+                Token::Whitespace,
+                Token::Ident("b".to_string()),
+                Token::Whitespace,
+                Token::BadUrl,
+            ]
+        );
+    }
+}
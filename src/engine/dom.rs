@@ -1,15 +1,119 @@
-use std::collections::{HashMap, HashSet};
+use std::{collections::HashMap, fmt};
 
-#[derive(Debug)]
+use encoding_rs::Encoding;
+
+#[derive(Debug, PartialEq)]
 pub struct Node {
     pub node_type: NodeType,
     pub children: Vec<Node>,
+    pub span: Span,
+}
+
+/// Where something came from in the page source. `start` and `end` are byte
+/// offsets, `line` and `column` count from one and point at `start`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self {
+            start,
+            end,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum NodeType {
     Text(String),
     Element(ElementData),
+    Comment(String),
+    Doctype(DoctypeData),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DoctypeData {
+    pub name: String,
+    pub public_id: Option<String>,
+    pub system_id: Option<String>,
+}
+
+/// How closely the page asked to follow the standards, picked from its doctype.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum QuirksMode {
+    #[default]
+    NoQuirks,
+    LimitedQuirks,
+    Quirks,
+}
+
+/// Where the encoding of a page came from, in the order they are tried.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EncodingSource {
+    Override,
+    ByteOrderMark,
+    ContentType,
+    Meta,
+    Detected,
+}
+
+impl fmt::Display for EncodingSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Override => "override",
+            Self::ByteOrderMark => "BOM",
+            Self::ContentType => "Content-Type",
+            Self::Meta => "meta",
+            Self::Detected => "detected",
+        })
+    }
+}
+
+/// A parsed page: the top level nodes (doctype, comments and the `html`
+/// element) along with the mode the page renders in, the encoding it was
+/// decoded with if it came in as bytes, and the errors the parser recovered
+/// from.
+#[derive(Debug)]
+pub struct Document {
+    pub children: Vec<Node>,
+    pub mode: QuirksMode,
+    pub encoding: Option<(&'static Encoding, EncodingSource)>,
+    pub errors: Vec<ParseError>,
+}
+
+impl Document {
+    /// Takes the `html` element out of the document.
+    pub fn into_root(self) -> Node {
+        self.children
+            .into_iter()
+            .find(|node| matches!(node.node_type, NodeType::Element(_)))
+            .unwrap_or_else(|| elem("html".to_string(), AttrMap::new(), vec![]))
+    }
+}
+
+impl Node {
+    /// The `href` of the first `<base>` element that has one, which urls in
+    /// the page are relative to.
+    pub fn base_href(&self) -> Option<&str> {
+        if let NodeType::Element(data) = &self.node_type {
+            if let Some(href) = data.attrs.get("href").filter(|_| data.tag == "base") {
+                return Some(href);
+            }
+        }
+        self.children.iter().find_map(Node::base_href)
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -23,11 +127,12 @@ impl ElementData {
         self.attrs.get("id")
     }
 
-    pub fn classes(&self) -> HashSet<&str> {
-        match self.attrs.get("class") {
-            Some(class_list) => class_list.split(' ').collect(),
-            None => HashSet::new(),
-        }
+    /// The classes of the element, which can be split by any whitespace.
+    pub fn classes(&self) -> impl Iterator<Item = &str> {
+        self.attrs
+            .get("class")
+            .into_iter()
+            .flat_map(|class_list| class_list.split_ascii_whitespace())
     }
 }
 
@@ -37,6 +142,7 @@ pub fn elem(name: String, attrs: AttrMap, children: Vec<Node>) -> Node {
     Node {
         children,
         node_type: NodeType::Element(ElementData { tag: name, attrs }),
+        span: Span::default(),
     }
 }
 
@@ -44,5 +150,22 @@ pub fn text(data: String) -> Node {
     Node {
         children: Vec::new(),
         node_type: NodeType::Text(data),
+        span: Span::default(),
+    }
+}
+
+pub fn comment(data: String) -> Node {
+    Node {
+        children: Vec::new(),
+        node_type: NodeType::Comment(data),
+        span: Span::default(),
+    }
+}
+
+pub fn doctype(data: DoctypeData) -> Node {
+    Node {
+        children: Vec::new(),
+        node_type: NodeType::Doctype(data),
+        span: Span::default(),
     }
 }
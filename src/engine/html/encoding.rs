@@ -0,0 +1,272 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252, X_USER_DEFINED};
+
+use crate::engine::dom::EncodingSource;
+
+/// How many bytes the `<meta>` prescan looks at.
+pub const PRESCAN_LEN: usize = 1024;
+
+/// Picks the encoding of a page following the WHATWG encoding sniffing
+/// algorithm: a byte order mark, then the `charset` of the `Content-Type`
+/// header, then a `<meta>` prescan of `bytes` and finally a guess based on
+/// the bytes themselves.
+pub fn sniff(bytes: &[u8], content_type: Option<&str>) -> (&'static Encoding, EncodingSource) {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return (encoding, EncodingSource::ByteOrderMark);
+    }
+    if let Some(encoding) = content_type.and_then(|value| charset(value.as_bytes())) {
+        return (encoding, EncodingSource::ContentType);
+    }
+    if let Some(encoding) = prescan(&bytes[..bytes.len().min(PRESCAN_LEN)]) {
+        return (encoding, EncodingSource::Meta);
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    (detector.guess(None, true), EncodingSource::Detected)
+}
+
+/// Finds the `charset` parameter in a `Content-Type` value or the `content`
+/// attribute of a `<meta http-equiv>`.
+fn charset(value: &[u8]) -> Option<&'static Encoding> {
+    let mut rest = value;
+    loop {
+        let index = rest
+            .windows(7)
+            .position(|window| window.eq_ignore_ascii_case(b"charset"))?;
+        rest = trim_start(&rest[index + 7..]);
+        if let Some(after) = rest.strip_prefix(b"=") {
+            rest = trim_start(after);
+            break;
+        }
+    }
+
+    let label = match rest.first() {
+        Some(&quote @ (b'"' | b'\'')) => {
+            let end = rest[1..].iter().position(|&b| b == quote)?;
+            &rest[1..end + 1]
+        }
+        _ => {
+            let end = rest
+                .iter()
+                .position(|&b| b == b';' || is_whitespace(b))
+                .unwrap_or(rest.len());
+            &rest[..end]
+        }
+    };
+    Encoding::for_label(label)
+}
+
+fn is_whitespace(b: u8) -> bool {
+    matches!(b, b'\t' | b'\n' | b'\x0C' | b'\r' | b' ')
+}
+
+fn trim_start(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|&b| !is_whitespace(b))
+        .unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+/// Looks for a `<meta charset>` or `<meta http-equiv=content-type>` at the
+/// start of a page, as the spec's prescan does.
+fn prescan(bytes: &[u8]) -> Option<&'static Encoding> {
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let rest = &bytes[pos..];
+        if rest.starts_with(b"<!--") {
+            let end = rest[2..].windows(3).position(|w| w == b"-->")?;
+            pos += 2 + end + 3;
+        } else if rest.len() > 5
+            && rest[..5].eq_ignore_ascii_case(b"<meta")
+            && (is_whitespace(rest[5]) || rest[5] == b'/')
+        {
+            pos += 6;
+            if let Some(encoding) = meta_encoding(bytes, &mut pos) {
+                return Some(encoding);
+            }
+        } else if rest.len() > 2
+            && rest[0] == b'<'
+            && (rest[1].is_ascii_alphabetic() || rest[1] == b'/' && rest[2].is_ascii_alphabetic())
+        {
+            pos += rest
+                .iter()
+                .position(|&b| is_whitespace(b) || b == b'>')
+                .unwrap_or(rest.len());
+            while attribute(bytes, &mut pos).is_some() {}
+        } else if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            pos += rest.iter().position(|&b| b == b'>').unwrap_or(rest.len());
+        } else {
+            pos += 1;
+        }
+    }
+    None
+}
+
+/// Reads the attributes of a `<meta>` tag and works out the encoding it
+/// declares, if any.
+fn meta_encoding(bytes: &[u8], pos: &mut usize) -> Option<&'static Encoding> {
+    let mut seen = Vec::new();
+    let mut got_pragma = false;
+    let mut need_pragma = None;
+    let mut encoding = None;
+
+    while let Some((name, value)) = attribute(bytes, pos) {
+        if seen.contains(&name) {
+            continue;
+        }
+        match name.as_slice() {
+            b"http-equiv" => got_pragma |= value.eq_ignore_ascii_case(b"content-type"),
+            b"content" if encoding.is_none() => {
+                if let Some(found) = charset(&value) {
+                    encoding = Some(found);
+                    need_pragma = Some(true);
+                }
+            }
+            b"charset" if encoding.is_none() => {
+                encoding = Encoding::for_label(&value);
+                need_pragma = Some(false);
+            }
+            _ => {}
+        }
+        seen.push(name);
+    }
+
+    match need_pragma {
+        None => return None,
+        Some(true) if !got_pragma => return None,
+        _ => {}
+    }
+    // A page can't really be UTF-16 if its meta tag could be read as ASCII
+    match encoding? {
+        encoding if encoding == UTF_16BE || encoding == UTF_16LE => Some(UTF_8),
+        encoding if encoding == X_USER_DEFINED => Some(WINDOWS_1252),
+        encoding => Some(encoding),
+    }
+}
+
+/// The prescan's "get an attribute" algorithm. Names are lowercased.
+fn attribute(bytes: &[u8], pos: &mut usize) -> Option<(Vec<u8>, Vec<u8>)> {
+    let next = |pos: &usize| bytes.get(*pos).copied();
+    while next(pos).is_some_and(|b| is_whitespace(b) || b == b'/') {
+        *pos += 1;
+    }
+    if next(pos)? == b'>' {
+        return None;
+    }
+
+    let mut name = Vec::new();
+    let mut value = Vec::new();
+    loop {
+        match next(pos)? {
+            b'=' if !name.is_empty() => {
+                *pos += 1;
+                break;
+            }
+            b if is_whitespace(b) => {
+                while next(pos).is_some_and(is_whitespace) {
+                    *pos += 1;
+                }
+                if next(pos)? != b'=' {
+                    return Some((name, value));
+                }
+                *pos += 1;
+                break;
+            }
+            b'/' | b'>' => return Some((name, value)),
+            b => {
+                name.push(b.to_ascii_lowercase());
+                *pos += 1;
+            }
+        }
+    }
+
+    while next(pos).is_some_and(is_whitespace) {
+        *pos += 1;
+    }
+    match next(pos)? {
+        quote @ (b'"' | b'\'') => {
+            *pos += 1;
+            loop {
+                let b = next(pos)?;
+                *pos += 1;
+                if b == quote {
+                    return Some((name, value));
+                }
+                value.push(b.to_ascii_lowercase());
+            }
+        }
+        b'>' => Some((name, value)),
+        _ => {
+            while let Some(b) = next(pos).filter(|&b| !is_whitespace(b) && b != b'>') {
+                value.push(b.to_ascii_lowercase());
+                *pos += 1;
+            }
+            Some((name, value))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use encoding_rs::{SHIFT_JIS, UTF_16LE, UTF_8, WINDOWS_1251, WINDOWS_1252};
+
+    use super::sniff;
+    use crate::engine::EncodingSource;
+
+    #[test]
+    fn prefers_a_byte_order_mark() {
+        let bytes = b"\xFF\xFE<\x00p\x00>\x00";
+        assert_eq!(
+            sniff(bytes, Some("text/html; charset=shift_jis")),
+            (UTF_16LE, EncodingSource::ByteOrderMark)
+        );
+    }
+
+    #[test]
+    fn reads_the_content_type_charset() {
+        let sniffed = |value| sniff(b"<meta charset=utf-8>", Some(value));
+        assert_eq!(
+            sniffed("text/html; charset=Shift_JIS"),
+            (SHIFT_JIS, EncodingSource::ContentType)
+        );
+        assert_eq!(
+            sniffed("text/html;charset=\"windows-1251\""),
+            (WINDOWS_1251, EncodingSource::ContentType)
+        );
+        assert_eq!(sniffed("text/html"), (UTF_8, EncodingSource::Meta));
+    }
+
+    #[test]
+    fn prescans_meta_tags() {
+        let meta = |html: &str| sniff(html.as_bytes(), None);
+        assert_eq!(
+            meta("<!-- <meta charset=utf-8> --><META CHARSET='shift_jis'>"),
+            (SHIFT_JIS, EncodingSource::Meta)
+        );
+        assert_eq!(
+            meta(r#"<meta http-equiv="Content-Type" content="text/html; charset=windows-1251">"#),
+            (WINDOWS_1251, EncodingSource::Meta)
+        );
+        assert_eq!(
+            meta(r#"<title x=">"></title><meta charset="utf-16">"#),
+            (UTF_8, EncodingSource::Meta)
+        );
+        // `content` only counts along with `http-equiv`
+        assert_eq!(
+            meta("<meta content='text/html; charset=shift_jis'>").1,
+            EncodingSource::Detected
+        );
+    }
+
+    #[test]
+    fn guesses_from_the_bytes() {
+        // "café crème" in windows-1252
+        let bytes = b"<p>caf\xE9 cr\xE8me, d\xE9j\xE0 vu, \xE9t\xE9 \xE0 la fran\xE7aise</p>";
+        assert_eq!(sniff(bytes, None), (WINDOWS_1252, EncodingSource::Detected));
+        // "日本語のテキスト" in Shift_JIS
+        let bytes = b"<p>\x93\xfa\x96\x7b\x8c\xea\x82\xcc\x83\x65\x83\x4c\x83\x58\x83\x67</p>";
+        assert_eq!(sniff(bytes, None), (SHIFT_JIS, EncodingSource::Detected));
+    }
+}
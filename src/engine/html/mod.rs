@@ -0,0 +1,891 @@
+use encoding_rs::{CoderResult, Decoder, Encoding};
+
+use self::encoding::{sniff, PRESCAN_LEN};
+use self::tokenizer::{Token, Tokenizer};
+use self::tree_builder::TreeBuilder;
+
+use super::dom::{Document, EncodingSource, Node, Span};
+
+mod encoding;
+mod entities;
+mod tokenizer;
+mod tree_builder;
+
+/// An incremental HTML parser. Input is fed in as it arrives, from a reader or
+/// as chunks received from elsewhere, and the tree built so far can be looked
+/// at any time, so a page can be shown while it loads.
+///
+/// Bytes are decoded with the encoding [`sniff`] picks once enough of them
+/// have arrived for the `<meta>` prescan, unless an override is set.
+///
+/// Follows the HTML5 error handling rules, so any input produces a tree with
+/// `head` and `body` elements. The doctype and top level comments are kept,
+/// every node records its span in the decoded source, and the errors the
+/// parser recovered from are kept in source order.
+pub struct Parser {
+    tokenizer: Tokenizer,
+    builder: TreeBuilder,
+    content_type: Option<String>,
+    encoding: Option<(&'static Encoding, EncodingSource)>,
+    decoder: Option<Decoder>,
+    /// Bytes held back until the encoding is known.
+    undecoded: Vec<u8>,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self {
+            tokenizer: Tokenizer::new(),
+            builder: TreeBuilder::new(),
+            content_type: None,
+            encoding: None,
+            decoder: None,
+            undecoded: Vec::new(),
+        }
+    }
+
+    /// Uses the `charset` of a `Content-Type` header when sniffing.
+    pub fn set_content_type(&mut self, value: &str) {
+        self.content_type = Some(value.to_string());
+    }
+
+    /// Decodes the input with `encoding` instead of sniffing it.
+    pub fn set_encoding(&mut self, encoding: &'static Encoding) {
+        self.encoding = Some((encoding, EncodingSource::Override));
+    }
+
+    /// Feeds the next chunk of bytes. Sequences that are invalid in the
+    /// encoding become U+FFFD.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        if self.decoder.is_none() {
+            self.undecoded.extend_from_slice(bytes);
+            if self.encoding.is_some() || self.undecoded.len() >= PRESCAN_LEN {
+                self.start_decoding();
+            }
+            return;
+        }
+        self.decode(bytes, false);
+    }
+
+    fn start_decoding(&mut self) {
+        let undecoded = std::mem::take(&mut self.undecoded);
+        let (encoding, source) = *self
+            .encoding
+            .get_or_insert_with(|| sniff(&undecoded, self.content_type.as_deref()));
+        // A byte order mark always wins, and is left out of the text
+        self.decoder = Some(match source {
+            EncodingSource::ByteOrderMark => encoding.new_decoder_with_bom_removal(),
+            _ => encoding.new_decoder_without_bom_handling(),
+        });
+        self.decode(&undecoded, false);
+    }
+
+    fn decode(&mut self, bytes: &[u8], last: bool) {
+        let decoder = self.decoder.as_mut().unwrap();
+        let mut text = String::new();
+        let mut bytes = bytes;
+        loop {
+            let needed = decoder
+                .max_utf8_buffer_length(bytes.len())
+                .unwrap_or(bytes.len());
+            text.reserve(needed);
+            let (result, read, _) = decoder.decode_to_string(bytes, &mut text, last);
+            bytes = &bytes[read..];
+            if result == CoderResult::InputEmpty {
+                break;
+            }
+        }
+        self.feed_str(&text);
+    }
+
+    /// Feeds the next chunk of already decoded input.
+    pub fn feed_str(&mut self, text: &str) {
+        self.tokenizer.feed(text);
+        self.pump();
+    }
+
+    /// The document as parsed so far.
+    pub fn document(&self) -> Document {
+        let mut document = self.builder.snapshot();
+        document.encoding = self.encoding;
+        add_diagnostics(&self.tokenizer, &mut document);
+        document
+    }
+
+    /// Parses whatever input is left and returns the complete document.
+    pub fn finish(mut self) -> Document {
+        if self.decoder.is_none() && !self.undecoded.is_empty() {
+            self.start_decoding();
+        }
+        if self.decoder.is_some() {
+            self.decode(&[], true);
+        }
+        self.tokenizer.end();
+        self.pump();
+
+        let mut document = self.builder.finish();
+        document.encoding = self.encoding;
+        add_diagnostics(&self.tokenizer, &mut document);
+        document
+    }
+
+    /// Hands every token the input so far allows to the tree builder.
+    fn pump(&mut self) {
+        while let Some((token, span)) = self.tokenizer.next_token() {
+            let eof = token == Token::Eof;
+            let is_tag = matches!(token, Token::StartTag(_) | Token::EndTag(_));
+            self.builder.process_token(token, span);
+            if let Some(state) = self.builder.take_tokenizer_state() {
+                self.tokenizer.set_state(state);
+            }
+            if is_tag {
+                self.tokenizer
+                    .set_cdata_allowed(self.builder.in_foreign_content());
+            }
+            if eof {
+                break;
+            }
+        }
+    }
+}
+
+/// Merges in the tokenizer's errors and fills in line and column numbers.
+fn add_diagnostics(tokenizer: &Tokenizer, document: &mut Document) {
+    document.errors.extend_from_slice(tokenizer.errors());
+    document.errors.sort_by_key(|error| error.span.start);
+
+    let lines = LineIndex::new(tokenizer.input());
+    for error in &mut document.errors {
+        lines.locate(&mut error.span);
+    }
+    for node in &mut document.children {
+        locate_node(&lines, node);
+    }
+}
+
+/// Byte offsets where each line of the source starts, for turning spans into
+/// line and column numbers.
+struct LineIndex<'a> {
+    source: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(source: &'a str) -> Self {
+        let mut starts = vec![0];
+        let bytes = source.as_bytes();
+        for (i, &b) in bytes.iter().enumerate() {
+            let newline = b == b'\n' || (b == b'\r' && bytes.get(i + 1) != Some(&b'\n'));
+            if newline {
+                starts.push(i + 1);
+            }
+        }
+        Self { source, starts }
+    }
+
+    fn locate(&self, span: &mut Span) {
+        let line = self.starts.partition_point(|&start| start <= span.start);
+        let line_start = self.starts[line - 1];
+        span.line = line;
+        span.column = self.source[line_start..span.start].chars().count() + 1;
+    }
+}
+
+fn locate_node(lines: &LineIndex, node: &mut Node) {
+    lines.locate(&mut node.span);
+    for child in &mut node.children {
+        locate_node(lines, child);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use encoding_rs::{SHIFT_JIS, UTF_8};
+
+    use super::Parser;
+    use crate::engine::{
+        DoctypeData, Document, ElementData, EncodingSource, Node, NodeType, QuirksMode,
+    };
+
+    fn parse_document(source: String) -> Document {
+        let mut parser = Parser::new();
+        parser.feed_str(&source);
+        parser.finish()
+    }
+
+    fn parse(source: String) -> Node {
+        parse_document(source).into_root()
+    }
+
+    fn element(tag: &str) -> NodeType {
+        NodeType::Element(ElementData {
+            tag: tag.to_string(),
+            attrs: HashMap::new(),
+        })
+    }
+
+    fn head(node: &Node) -> &Node {
+        &node.children[0]
+    }
+
+    fn body(node: &Node) -> &Node {
+        &node.children[1]
+    }
+
+    /// Compact markup for the body's children, used to compare tree shapes.
+    fn serialize(node: &Node, out: &mut String) {
+        match &node.node_type {
+            NodeType::Text(text) => out.push_str(text),
+            NodeType::Comment(text) => out.push_str(&format!("<!--{}-->", text)),
+            NodeType::Doctype(data) => out.push_str(&format!("<!DOCTYPE {}>", data.name)),
+            NodeType::Element(data) => {
+                out.push_str(&format!("<{}>", data.tag));
+                for child in &node.children {
+                    serialize(child, out);
+                }
+                out.push_str(&format!("</{}>", data.tag));
+            }
+        }
+    }
+
+    fn parse_body(input: &str) -> String {
+        let node = parse(input.to_string());
+        let mut out = String::new();
+        for child in &body(&node).children {
+            serialize(child, &mut out);
+        }
+        out
+    }
+
+    #[test]
+    fn parses_simple_text() {
+        let input = String::from("Some text");
+        let node = parse(input.clone());
+        assert_eq!(node.node_type, element("html"));
+        assert_eq!(body(&node).children[0].node_type, NodeType::Text(input));
+    }
+
+    #[test]
+    fn parses_simple_paragraph() {
+        let input = String::from("<p>Some text</p>");
+        let node = parse(input);
+        let p = &body(&node).children[0];
+        assert_eq!(p.node_type, element("p"));
+        assert_eq!(p.children.len(), 1);
+        assert_eq!(
+            p.children.first().unwrap().node_type,
+            NodeType::Text("Some text".to_string())
+        );
+    }
+
+    #[test]
+    fn infers_html_tag() {
+        let input = String::from("<!DOCTYPE html>");
+        let node = parse(input);
+        assert_eq!(node.node_type, element("html"));
+        assert_eq!(node.children.len(), 2);
+        assert_eq!(head(&node).node_type, element("head"));
+        assert_eq!(body(&node).node_type, element("body"));
+        assert_eq!(body(&node).children.len(), 0);
+    }
+
+    #[test]
+    fn parses_nested_elements() {
+        let input = String::from(
+            "
+         <!DOCTYPE html>
+        <html>
+        <head>
+        <title>Title of the document</title>
+        </head>
+        </html> 
+                                 ",
+        );
+        let node = parse(input);
+        assert_eq!(node.node_type, element("html"));
+        assert_eq!(node.children.len(), 3);
+        let head = head(&node);
+
+        assert_eq!(head.node_type, element("head"));
+        let title = head
+            .children
+            .iter()
+            .find(|child| child.node_type == element("title"))
+            .unwrap();
+        assert_eq!(title.children.len(), 1);
+
+        let text = title.children.first().unwrap();
+
+        assert_eq!(
+            text.node_type,
+            NodeType::Text("Title of the document".to_string())
+        );
+        assert_eq!(text.children.len(), 0);
+    }
+
+    #[test]
+    fn parses_meta_tag() {
+        let input = String::from(
+            r#"
+              <meta name="viewport" content="width=device-width, initial-scale=1.0">
+            "#,
+        );
+        let node = parse(input);
+        let meta = &head(&node).children[0];
+        assert_eq!(
+            meta.node_type,
+            NodeType::Element(ElementData {
+                tag: "meta".to_string(),
+                attrs: HashMap::from([
+                    ("name".to_string(), "viewport".to_string()),
+                    (
+                        "content".to_string(),
+                        "width=device-width, initial-scale=1.0".to_string()
+                    )
+                ])
+            })
+        );
+        assert_eq!(meta.children.len(), 0);
+    }
+
+    #[test]
+    fn parses_meta_tag_with_closing_slash() {
+        let input = String::from(
+            r#"
+              <meta charset="UTF-8"/>
+            "#,
+        );
+        let node = parse(input);
+        let meta = &head(&node).children[0];
+        assert_eq!(
+            meta.node_type,
+            NodeType::Element(ElementData {
+                tag: "meta".to_string(),
+                attrs: HashMap::from([("charset".to_string(), "UTF-8".to_string())])
+            })
+        );
+        assert_eq!(meta.children.len(), 0);
+    }
+
+    #[test]
+    fn closes_unclosed_paragraphs() {
+        assert_eq!(parse_body("<p>one<p>two"), "<p>one</p><p>two</p>");
+        assert_eq!(
+            parse_body("<p>text<div>block</div>"),
+            "<p>text</p><div>block</div>"
+        );
+    }
+
+    #[test]
+    fn closes_unclosed_list_items() {
+        assert_eq!(
+            parse_body("<ul><li>a<li>b</ul>"),
+            "<ul><li>a</li><li>b</li></ul>"
+        );
+        assert_eq!(
+            parse_body("<dl><dt>a<dd>b<dt>c</dl>"),
+            "<dl><dt>a</dt><dd>b</dd><dt>c</dt></dl>"
+        );
+    }
+
+    #[test]
+    fn closes_unclosed_table_cells() {
+        assert_eq!(
+            parse_body("<table><tr><td>a<td>b<tr><td>c</table>"),
+            "<table><tbody><tr><td>a</td><td>b</td></tr><tr><td>c</td></tr></tbody></table>"
+        );
+    }
+
+    #[test]
+    fn foster_parents_text_in_tables() {
+        assert_eq!(
+            parse_body("<table>oops<tr><td>a</td></tr></table>"),
+            "oops<table><tbody><tr><td>a</td></tr></tbody></table>"
+        );
+    }
+
+    #[test]
+    fn adopts_misnested_formatting_elements() {
+        assert_eq!(parse_body("<b><i>a</b>b</i>"), "<b><i>a</i></b><i>b</i>");
+        assert_eq!(parse_body("<b>1<p>2</b>3</p>"), "<b>1</b><p><b>2</b>3</p>");
+    }
+
+    #[test]
+    fn ignores_stray_end_tags() {
+        assert_eq!(parse_body("</div>a</span>b"), "ab");
+        assert_eq!(parse_body("a</p>"), "a<p></p>");
+    }
+
+    #[test]
+    fn decodes_named_character_references() {
+        assert_eq!(parse_body("a &amp; b &lt;p&gt;"), "a & b <p>");
+        assert_eq!(parse_body("x&nbsp;y&mdash;z"), "x\u{a0}y\u{2014}z");
+        assert_eq!(parse_body("&NotNestedGreaterGreater;"), "\u{2aa2}\u{338}");
+    }
+
+    #[test]
+    fn decodes_numeric_character_references() {
+        assert_eq!(parse_body("&#8212;&#x2014;&#X41"), "\u{2014}\u{2014}A");
+        assert_eq!(parse_body("&#150;&#0;&#xD800;"), "\u{2013}\u{fffd}\u{fffd}");
+        assert_eq!(parse_body("&#99999999999;"), "\u{fffd}");
+        assert_eq!(parse_body("&#;&#x;"), "&#;&#x;");
+    }
+
+    #[test]
+    fn decodes_legacy_references_without_semicolon() {
+        assert_eq!(parse_body("&copy 2024 &amp"), "\u{a9} 2024 &");
+        assert_eq!(parse_body("&notit;"), "\u{ac}it;");
+        assert_eq!(parse_body("&bogus; &"), "&bogus; &");
+    }
+
+    #[test]
+    fn decodes_references_in_attribute_values() {
+        let node = parse(
+            r#"<a href="/?a=1&amp;b=2&copy=3&lang=en" title='&quot;hi&quot;' alt=x&lt;y>"#
+                .to_string(),
+        );
+        let a = &body(&node).children[0];
+        let NodeType::Element(data) = &a.node_type else {
+            panic!("expected an element");
+        };
+        assert_eq!(data.attrs["href"], "/?a=1&b=2&copy=3&lang=en");
+        assert_eq!(data.attrs["title"], "\"hi\"");
+        assert_eq!(data.attrs["alt"], "x<y");
+    }
+
+    #[test]
+    fn keeps_script_and_style_contents_as_text() {
+        let node = parse(
+            "<style>p > a { color: red }</style><script>if (a < b) { x = '</p>'; }</script><p>hi</p>"
+                .to_string(),
+        );
+        let mut out = String::new();
+        serialize(head(&node), &mut out);
+        assert_eq!(
+            out,
+            "<head><style>p > a { color: red }</style><script>if (a < b) { x = '</p>'; }</script></head>"
+        );
+        assert_eq!(parse_body("<p>hi</p>"), "<p>hi</p>");
+    }
+
+    #[test]
+    fn parses_rcdata_elements_as_text() {
+        let node = parse("<title>A <b>bold</b> &amp; title</title>".to_string());
+        let title = &head(&node).children[0];
+        assert_eq!(
+            title.children[0].node_type,
+            NodeType::Text("A <b>bold</b> & title".to_string())
+        );
+        assert_eq!(
+            parse_body("<textarea>\n<p>x</p>&lt;</textarea>"),
+            "<textarea><p>x</p><</textarea>"
+        );
+    }
+
+    #[test]
+    fn parses_raw_text_elements() {
+        assert_eq!(
+            parse_body("<xmp><b>&amp;</b></xmp><noembed><i></noembed>"),
+            "<xmp><b>&amp;</b></xmp><noembed><i></noembed>"
+        );
+        assert_eq!(
+            parse_body("<plaintext></plaintext>"),
+            "<plaintext></plaintext></plaintext>"
+        );
+    }
+
+    #[test]
+    fn parses_noscript_as_markup() {
+        let node =
+            parse("<noscript><link rel=a></noscript>x<noscript><p>on</p></noscript>".to_string());
+        let mut out = String::new();
+        serialize(head(&node), &mut out);
+        assert_eq!(out, "<head><noscript><link></link></noscript></head>");
+        let mut out = String::new();
+        serialize(body(&node), &mut out);
+        assert_eq!(out, "<body>x<noscript><p>on</p></noscript></body>");
+    }
+
+    #[test]
+    fn drops_leading_newline_in_pre() {
+        assert_eq!(parse_body("<pre>\n  a\n b</pre>"), "<pre>  a\n b</pre>");
+    }
+
+    fn body_attrs(input: &str) -> Vec<(String, String)> {
+        let node = parse(input.to_string());
+        let NodeType::Element(data) = &body(&node).children[0].node_type else {
+            panic!("expected an element in {input}");
+        };
+        let mut attrs: Vec<_> = data.attrs.clone().into_iter().collect();
+        attrs.sort();
+        attrs
+    }
+
+    #[test]
+    fn parses_odd_attribute_syntax() {
+        let fixtures: [(&str, &[(&str, &str)]); 12] = [
+            ("<hr width=100>", &[("width", "100")]),
+            (
+                "<img src=/a/b.png alt=x>",
+                &[("alt", "x"), ("src", "/a/b.png")],
+            ),
+            ("<a href=/x/>", &[("href", "/x/")]),
+            (
+                "<p class = 'a b' id =\"c\">",
+                &[("class", "a b"), ("id", "c")],
+            ),
+            ("<div data-foo_bar=1>", &[("data-foo_bar", "1")]),
+            (
+                "<button @click=go :bind=v>",
+                &[(":bind", "v"), ("@click", "go")],
+            ),
+            (
+                "<x-y ng.model=a v-on:x.stop=b>",
+                &[("ng.model", "a"), ("v-on:x.stop", "b")],
+            ),
+            (
+                "<input disabled checked=\"\">",
+                &[("checked", ""), ("disabled", "")],
+            ),
+            ("<p a=1 A=2 a=3>", &[("a", "1")]),
+            ("<p title=\"a\"b=c>", &[("b", "c"), ("title", "a")]),
+            (
+                "<p a='x\"y' b=\"x'y\" c=x'y>",
+                &[("a", "x\"y"), ("b", "x'y"), ("c", "x'y")],
+            ),
+            ("<p =x>", &[("=x", "")]),
+        ];
+        for (input, expected) in fixtures {
+            let expected: Vec<_> = expected
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+            assert_eq!(body_attrs(input), expected, "parsing {input}");
+        }
+    }
+
+    #[test]
+    fn keeps_first_duplicate_attribute_on_html_and_body() {
+        let node = parse("<body class=a><body class=b id=c>".to_string());
+        let NodeType::Element(data) = &body(&node).node_type else {
+            panic!("expected body");
+        };
+        assert_eq!(data.attrs["class"], "a");
+        assert_eq!(data.attrs["id"], "c");
+    }
+
+    #[test]
+    fn keeps_comments() {
+        assert_eq!(
+            parse_body("a<!-- x > y -- z -->b<!---->c<!--->d"),
+            "a<!-- x > y -- z -->b<!---->c<!---->d"
+        );
+        assert_eq!(parse_body("x<!-- unterminated"), "x<!-- unterminated-->");
+        assert_eq!(parse_body("<p>a</p><!--x--!>"), "<p>a</p><!--x-->");
+    }
+
+    #[test]
+    fn keeps_top_level_comments_and_doctype() {
+        let document =
+            parse_document("<!-- before --><!DOCTYPE html><html></html><!--after-->".to_string());
+        let mut out = String::new();
+        for child in &document.children {
+            if !matches!(child.node_type, NodeType::Element(_)) {
+                serialize(child, &mut out);
+            }
+        }
+        assert_eq!(out, "<!-- before --><!DOCTYPE html><!--after-->");
+    }
+
+    #[test]
+    fn parses_doctype_identifiers() {
+        let document = parse_document(
+            r#"<!DOCTYPE HTML PUBLIC "-//W3C//DTD HTML 4.01//EN" 'http://www.w3.org/TR/html4/strict.dtd'>"#
+                .to_string(),
+        );
+        assert_eq!(
+            document.children[0].node_type,
+            NodeType::Doctype(DoctypeData {
+                name: "html".to_string(),
+                public_id: Some("-//W3C//DTD HTML 4.01//EN".to_string()),
+                system_id: Some("http://www.w3.org/TR/html4/strict.dtd".to_string()),
+            })
+        );
+        assert_eq!(document.mode, QuirksMode::NoQuirks);
+    }
+
+    #[test]
+    fn picks_quirks_mode_from_doctype() {
+        let mode = |source: &str| parse_document(source.to_string()).mode;
+        assert_eq!(mode("<!DOCTYPE html>"), QuirksMode::NoQuirks);
+        assert_eq!(mode("<p>no doctype"), QuirksMode::Quirks);
+        assert_eq!(mode("<!DOCTYPE>"), QuirksMode::Quirks);
+        assert_eq!(
+            mode(r#"<!DOCTYPE html PUBLIC "-//W3C//DTD HTML 4.01 Transitional//EN">"#),
+            QuirksMode::Quirks
+        );
+        assert_eq!(
+            mode(
+                r#"<!DOCTYPE html PUBLIC "-//W3C//DTD HTML 4.01 Transitional//EN" "http://www.w3.org/TR/html4/loose.dtd">"#
+            ),
+            QuirksMode::LimitedQuirks
+        );
+        assert_eq!(
+            mode(r#"<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Transitional//EN" "x">"#),
+            QuirksMode::LimitedQuirks
+        );
+        assert_eq!(mode("<!DOCTYPE html SYSTEM bogus>"), QuirksMode::Quirks);
+    }
+
+    #[test]
+    fn nests_tables_in_paragraphs_in_quirks_mode() {
+        assert_eq!(parse_body("<p><table></table>"), "<p><table></table></p>");
+        assert_eq!(
+            parse_body("<!DOCTYPE html><p><table></table>"),
+            "<p></p><table></table>"
+        );
+    }
+
+    #[test]
+    fn treats_processing_instructions_and_html_cdata_as_comments() {
+        assert_eq!(
+            parse_body("a<?xml version=\"1.0\"?>"),
+            "a<!--?xml version=\"1.0\"?-->"
+        );
+        assert_eq!(parse_body("a<![CDATA[x]]>"), "a<!--[CDATA[x]]-->");
+        assert_eq!(parse_body("a</ b>c"), "a<!-- b-->c");
+    }
+
+    #[test]
+    fn parses_cdata_in_foreign_content() {
+        assert_eq!(parse_body("<svg><![CDATA[a<b]]]></svg>"), "<svg>a<b]</svg>");
+    }
+
+    #[test]
+    fn limits_tree_depth() {
+        let node = parse("<div>".repeat(2_000));
+        let mut depth = 0;
+        let mut current = &node;
+        while let Some(child) = current.children.last() {
+            depth += 1;
+            current = child;
+        }
+        assert!(depth <= 520);
+    }
+
+    #[test]
+    fn never_panics_on_malformed_input() {
+        let fragments = [
+            "<",
+            ">",
+            "</",
+            "<p",
+            "<a href=",
+            "\"",
+            "'",
+            "<b>",
+            "</b>",
+            "<table>",
+            "<tr>",
+            "<td>",
+            "</table>",
+            "<select>",
+            "<option>",
+            "<li>",
+            "</ul>",
+            "<!--",
+            "-->",
+            "<!",
+            "<script>",
+            "</script>",
+            "<title>",
+            "<textarea>",
+            "text",
+            " ",
+            "<html>",
+            "</body>",
+            "<caption>",
+            "<col>",
+            "<frameset>",
+            "<i>",
+            "<nobr>",
+            "</p>",
+            "<h1>",
+            "</h2>",
+            "&",
+        ];
+        let mut seed: u32 = 1;
+        for _ in 0..500 {
+            let mut input = String::new();
+            for _ in 0..30 {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                input.push_str(fragments[(seed >> 16) as usize % fragments.len()]);
+            }
+            let node = parse(input);
+            assert_eq!(node.node_type, element("html"));
+        }
+    }
+
+    #[test]
+    fn records_node_spans() {
+        let source = "<!DOCTYPE html>\n<p id=a>one\n<b>two</b></p>";
+        let document = parse_document(source.to_string());
+        let html = document.into_root();
+        let p = &body(&html).children[0];
+        assert_eq!((p.span.line, p.span.column), (2, 1));
+        assert_eq!(
+            &source[p.span.start..p.span.end],
+            "<p id=a>one\n<b>two</b></p>"
+        );
+
+        let text = &p.children[0];
+        assert_eq!(&source[text.span.start..text.span.end], "one\n");
+        let b = &p.children[1];
+        assert_eq!((b.span.line, b.span.column), (3, 1));
+        assert_eq!(&source[b.span.start..b.span.end], "<b>two</b>");
+    }
+
+    #[test]
+    fn ends_implied_elements_where_the_next_token_starts() {
+        let source = "<ul><li>a<li>b</ul>";
+        let node = parse(source.to_string());
+        let ul = &body(&node).children[0];
+        let spans: Vec<&str> = ul
+            .children
+            .iter()
+            .map(|li| &source[li.span.start..li.span.end])
+            .collect();
+        assert_eq!(spans, ["<li>a", "<li>b"]);
+        assert_eq!(&source[ul.span.start..ul.span.end], source);
+    }
+
+    #[test]
+    fn counts_columns_in_characters_after_crlf() {
+        let source = "<p>\r\n\u{e9}\u{e9}<b>x</b>";
+        let node = parse(source.to_string());
+        let b = &body(&node).children[0].children[1];
+        assert_eq!((b.span.line, b.span.column), (2, 3));
+        assert_eq!(&source[b.span.start..b.span.end], "<b>x</b>");
+        let text = &body(&node).children[0].children[0];
+        assert_eq!(text.node_type, NodeType::Text("\n\u{e9}\u{e9}".to_string()));
+    }
+
+    #[test]
+    fn collects_parse_errors_in_source_order() {
+        let source = "<p a=1 a=2>x</span>\n<div><b></div><section>";
+        let document = parse_document(source.to_string());
+        let errors: Vec<(&str, usize, usize)> = document
+            .errors
+            .iter()
+            .map(|e| (e.message.as_str(), e.span.line, e.span.column))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                ("missing-doctype", 1, 1),
+                ("duplicate-attribute", 1, 11),
+                ("unexpected-end-tag </span>", 1, 13),
+                ("end-tag-too-early, <b> is still open", 2, 9),
+                ("eof-in-element <section>", 2, 15),
+            ]
+        );
+    }
+
+    #[test]
+    fn well_formed_documents_have_no_errors() {
+        let document = parse_document(
+            "<!DOCTYPE html><html><head><title>t</title></head><body><p>x</p></body></html>"
+                .to_string(),
+        );
+        assert_eq!(document.errors, []);
+    }
+
+    #[test]
+    fn parses_the_same_in_any_chunks() {
+        let source = "<!DOCTYPE html>\r\n<title>A &amp; B</title><p class=x>caf\u{e9} &notit; \
+                      &#x41;<table><tr><td>1</table><svg><![CDATA[x]]></svg><!-- c -->";
+        let whole = parse_document(source.to_string());
+
+        let mut parser = Parser::new();
+        for byte in source.as_bytes() {
+            parser.feed(std::slice::from_ref(byte));
+        }
+        let chunked = parser.finish();
+        assert_eq!(chunked.children, whole.children);
+        assert_eq!(chunked.errors, whole.errors);
+    }
+
+    #[test]
+    fn replaces_invalid_utf8_across_chunks() {
+        let mut parser = Parser::new();
+        parser.set_content_type("text/html; charset=utf-8");
+        parser.feed(b"<p>a\xC3");
+        parser.feed(b"\xA9b\xFFc\xE2\x82");
+        let node = parser.finish().into_root();
+        let p = &body(&node).children[0];
+        assert_eq!(
+            p.children[0].node_type,
+            NodeType::Text("a\u{e9}b\u{fffd}c\u{fffd}".to_string())
+        );
+    }
+
+    #[test]
+    fn shows_the_document_parsed_so_far() {
+        let mut parser = Parser::new();
+        parser.feed_str(&format!("<h1>Top</h1><p>{}", "x".repeat(100)));
+        let partial = parser.document().into_root();
+        assert_eq!(
+            body(&partial).children[0].children[0].node_type,
+            NodeType::Text("Top".to_string())
+        );
+
+        parser.feed_str("</p><p>Bottom");
+        let node = parser.finish().into_root();
+        assert_eq!(body(&node).children.len(), 3);
+    }
+
+    #[test]
+    fn parses_a_long_document_in_chunks() {
+        let source = format!("<p>{}</p>", "long ".repeat(10_000));
+        let mut parser = Parser::new();
+        for chunk in source.as_bytes().chunks(1000) {
+            parser.feed(chunk);
+        }
+        assert_eq!(parser.finish().children, parse_document(source).children);
+    }
+
+    #[test]
+    fn decodes_the_sniffed_encoding() {
+        // "日本語" in Shift_JIS
+        let mut parser = Parser::new();
+        parser.feed(b"<meta charset=shift_jis><p>\x93\xfa\x96\x7b\x8c\xea");
+        let document = parser.finish();
+        assert_eq!(document.encoding, Some((SHIFT_JIS, EncodingSource::Meta)));
+        let node = document.into_root();
+        let p = &body(&node).children[0];
+        assert_eq!(
+            p.children[0].node_type,
+            NodeType::Text("日本語".to_string())
+        );
+    }
+
+    #[test]
+    fn removes_a_byte_order_mark() {
+        let mut parser = Parser::new();
+        parser.set_content_type("text/html; charset=windows-1252");
+        parser.feed(b"\xEF\xBB\xBF<p>\xC3\xA9");
+        let document = parser.finish();
+        assert_eq!(
+            document.encoding,
+            Some((UTF_8, EncodingSource::ByteOrderMark))
+        );
+        let node = document.into_root();
+        let p = &body(&node).children[0];
+        assert_eq!(
+            p.children[0].node_type,
+            NodeType::Text("\u{e9}".to_string())
+        );
+    }
+}
@@ -0,0 +1,1085 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::engine::dom::{DoctypeData, ParseError, Span};
+
+use super::entities::NAMED_ENTITIES;
+
+/// A token produced by the [`Tokenizer`] and consumed by the tree builder.
+#[derive(Debug, PartialEq)]
+pub enum Token {
+    Doctype(Doctype),
+    StartTag(Tag),
+    EndTag(Tag),
+    Comment(String),
+    Character(char),
+    Eof,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Doctype {
+    pub data: DoctypeData,
+    pub force_quirks: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tag {
+    pub name: String,
+    pub attrs: Vec<(String, String)>,
+    pub self_closing: bool,
+}
+
+impl Tag {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Tokenizer states, named after the states in the HTML5 spec.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum State {
+    Data,
+    RcData,
+    RawText,
+    ScriptData,
+    Plaintext,
+    /// The `<`, `</` and end tag name states shared by RCDATA, raw text and
+    /// script data, which all only end at the matching end tag.
+    TextLessThanSign,
+    TextEndTagOpen,
+    TextEndTagName,
+    TagOpen,
+    EndTagOpen,
+    TagName,
+    BeforeAttributeName,
+    AttributeName,
+    AfterAttributeName,
+    BeforeAttributeValue,
+    AttributeValueDoubleQuoted,
+    AttributeValueSingleQuoted,
+    AttributeValueUnquoted,
+    AfterAttributeValueQuoted,
+    SelfClosingStartTag,
+    BogusComment,
+    CommentStart,
+    CommentStartDash,
+    Comment,
+    CommentEndDash,
+    CommentEnd,
+    CommentEndBang,
+    Doctype,
+    BeforeDoctypeName,
+    DoctypeName,
+    AfterDoctypeName,
+    BeforeDoctypePublicIdentifier,
+    DoctypePublicIdentifierQuoted,
+    AfterDoctypePublicIdentifier,
+    BeforeDoctypeSystemIdentifier,
+    DoctypeSystemIdentifierQuoted,
+    AfterDoctypeSystemIdentifier,
+    BogusDoctype,
+    CdataSection,
+    CdataSectionBracket,
+    CdataSectionEnd,
+}
+
+pub struct Tokenizer {
+    input: String,
+    pos: usize,
+    /// Whether all of the input has been fed in.
+    ended: bool,
+    state: State,
+    return_state: State,
+    tag: Tag,
+    is_end_tag: bool,
+    comment: String,
+    doctype: Doctype,
+    quote: char,
+    cdata_allowed: bool,
+    last_start_tag: String,
+    temp_buffer: String,
+    pending: VecDeque<(Token, Span)>,
+    /// Where the token being built started.
+    token_start: usize,
+    /// Byte length of the last consumed character, which differs from its
+    /// UTF-8 length for a CRLF pair.
+    last_len: usize,
+    errors: Vec<ParseError>,
+}
+
+/// Code points 0x80 to 0x9F, which pages use as if they were windows-1252.
+static WINDOWS_1252: [char; 32] = [
+    '\u{20AC}', '\u{81}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{8D}', '\u{017D}', '\u{8F}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{9D}', '\u{017E}', '\u{0178}',
+];
+
+/// The longest entity name, including the semicolon.
+const MAX_ENTITY_LEN: usize = 32;
+
+/// How much input has to be buffered before a step can run while more is
+/// still on its way. Covers the longest lookahead: entity names, `[CDATA[`,
+/// the doctype keywords and a CRLF pair.
+const LOOKAHEAD: usize = 2 * MAX_ENTITY_LEN;
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0C' | ' ')
+}
+
+fn numeric_char(value: u32) -> char {
+    match value {
+        0x80..=0x9F => WINDOWS_1252[(value - 0x80) as usize],
+        0 | 0xD800..=0xDFFF => char::REPLACEMENT_CHARACTER,
+        value => char::from_u32(value).unwrap_or(char::REPLACEMENT_CHARACTER),
+    }
+}
+
+fn lookup_entity(name: &str) -> Option<&'static str> {
+    NAMED_ENTITIES
+        .binary_search_by(|(entity, _)| entity.cmp(&name))
+        .ok()
+        .map(|index| NAMED_ENTITIES[index].1)
+}
+
+impl Tokenizer {
+    pub fn new() -> Self {
+        Self {
+            input: String::new(),
+            pos: 0,
+            ended: false,
+            state: State::Data,
+            return_state: State::Data,
+            tag: Tag::default(),
+            is_end_tag: false,
+            comment: String::new(),
+            doctype: Doctype::default(),
+            quote: '"',
+            cdata_allowed: false,
+            last_start_tag: String::new(),
+            temp_buffer: String::new(),
+            pending: VecDeque::new(),
+            token_start: 0,
+            last_len: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    /// Appends the next chunk of the input.
+    pub fn feed(&mut self, chunk: &str) {
+        self.input.push_str(chunk);
+    }
+
+    /// Marks the input as complete, letting the tokenizer reach the end.
+    pub fn end(&mut self) {
+        self.ended = true;
+    }
+
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    fn error(&mut self, message: &str) {
+        let start = self.pos.saturating_sub(self.last_len);
+        self.errors.push(ParseError {
+            message: message.to_string(),
+            span: Span::new(start, self.pos),
+        });
+    }
+
+    /// Switches the state, used by the tree builder for raw text elements.
+    pub fn set_state(&mut self, state: State) {
+        self.state = state;
+    }
+
+    /// CDATA sections are only allowed inside SVG and MathML, which the tree
+    /// builder tracks.
+    pub fn set_cdata_allowed(&mut self, allowed: bool) {
+        self.cdata_allowed = allowed;
+    }
+
+    /// Consumes the next character, turning CRLF pairs and lone CRs into LF
+    /// as the spec's input stream preprocessing does.
+    fn consume_char(&mut self) -> Option<char> {
+        let c = self.input[self.pos..].chars().next()?;
+        self.last_len = c.len_utf8();
+        if c == '\r' {
+            if self.input[self.pos + 1..].starts_with('\n') {
+                self.last_len = 2;
+            }
+            self.pos += self.last_len;
+            return Some('\n');
+        }
+        self.pos += self.last_len;
+        Some(c)
+    }
+
+    fn reconsume(&mut self, c: Option<char>, state: State) {
+        if c.is_some() {
+            self.pos -= self.last_len;
+        }
+        self.state = state;
+    }
+
+    fn emit(&mut self, token: Token) {
+        let span = Span::new(self.token_start, self.pos);
+        self.pending.push_back((token, span));
+    }
+
+    fn emit_str(&mut self, s: &str) {
+        for c in s.chars() {
+            self.emit(Token::Character(c));
+        }
+    }
+
+    fn start_tag(&mut self, is_end_tag: bool) {
+        self.tag = Tag::default();
+        self.is_end_tag = is_end_tag;
+    }
+
+    fn start_attr(&mut self) {
+        self.tag.attrs.push((String::new(), String::new()));
+    }
+
+    fn attr_name(&mut self) -> &mut String {
+        &mut self.tag.attrs.last_mut().unwrap().0
+    }
+
+    fn attr_value(&mut self) -> &mut String {
+        &mut self.tag.attrs.last_mut().unwrap().1
+    }
+
+    fn emit_comment(&mut self) {
+        let comment = std::mem::take(&mut self.comment);
+        self.emit(Token::Comment(comment));
+    }
+
+    fn emit_doctype(&mut self) {
+        let doctype = std::mem::take(&mut self.doctype);
+        self.emit(Token::Doctype(doctype));
+    }
+
+    fn emit_quirky_doctype(&mut self) {
+        self.doctype.force_quirks = true;
+        self.emit_doctype();
+    }
+
+    /// Consumes `s` if the input continues with it, ignoring ASCII case.
+    fn consume_keyword(&mut self, s: &str, ignore_case: bool) -> bool {
+        let matches = self.input[self.pos..]
+            .get(..s.len())
+            .is_some_and(|next| match ignore_case {
+                true => next.eq_ignore_ascii_case(s),
+                false => next == s,
+            });
+        if matches {
+            self.pos += s.len();
+        }
+        matches
+    }
+
+    /// Picks between a comment, doctype or CDATA section after `<!`.
+    fn markup_declaration_open(&mut self) {
+        self.comment.clear();
+        if self.consume_keyword("--", false) {
+            self.state = State::CommentStart;
+        } else if self.consume_keyword("DOCTYPE", true) {
+            self.state = State::Doctype;
+        } else if self.cdata_allowed && self.consume_keyword("[CDATA[", false) {
+            self.state = State::CdataSection;
+        } else {
+            if self.input[self.pos..].starts_with("[CDATA[") {
+                self.error("cdata-in-html-content");
+            } else {
+                self.error("incorrectly-opened-comment");
+            }
+            self.state = State::BogusComment;
+        }
+    }
+
+    fn emit_tag(&mut self) {
+        let mut tag = std::mem::take(&mut self.tag);
+        // When an attribute is repeated the first one wins
+        let mut seen = HashSet::new();
+        let count = tag.attrs.len();
+        tag.attrs.retain(|(name, _)| seen.insert(name.clone()));
+        if tag.attrs.len() != count {
+            self.error("duplicate-attribute");
+        }
+        if self.is_end_tag {
+            if !tag.attrs.is_empty() {
+                self.error("end-tag-with-attributes");
+            }
+            if tag.self_closing {
+                self.error("end-tag-with-trailing-solidus");
+            }
+            self.emit(Token::EndTag(tag));
+        } else {
+            self.last_start_tag = tag.name.clone();
+            self.emit(Token::StartTag(tag));
+        }
+    }
+
+    /// Consumes a character reference following a `&`, returning the text it
+    /// stands for, or `None` if the `&` should be taken literally.
+    fn consume_char_ref(&mut self, in_attribute: bool) -> Option<String> {
+        let rest = &self.input[self.pos..];
+        if rest.starts_with('#') {
+            return self.consume_numeric_char_ref();
+        }
+
+        let name_len = rest
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len())
+            .min(MAX_ENTITY_LEN);
+        if name_len == 0 {
+            return None;
+        }
+
+        // Prefer the full name with its semicolon, then fall back to the
+        // longest legacy entity that prefixes the name
+        let terminated_name = rest[name_len..]
+            .starts_with(';')
+            .then(|| &rest[..name_len + 1]);
+        let legacy = |len: usize| lookup_entity(&rest[..len]).map(|value| (len, value));
+        let (len, value) = match terminated_name.and_then(lookup_entity) {
+            Some(value) => (name_len + 1, value),
+            None => match (1..=name_len).rev().find_map(legacy) {
+                Some(entity) => entity,
+                None => {
+                    if terminated_name.is_some() {
+                        self.error("unknown-named-character-reference");
+                    }
+                    return None;
+                }
+            },
+        };
+
+        let terminated = rest[..len].ends_with(';');
+        let next = rest[len..].chars().next();
+        if in_attribute
+            && !terminated
+            && next.is_some_and(|c| c == '=' || c.is_ascii_alphanumeric())
+        {
+            return None;
+        }
+
+        self.pos += len;
+        if !terminated {
+            self.error("missing-semicolon-after-character-reference");
+        }
+        Some(value.to_string())
+    }
+
+    fn consume_numeric_char_ref(&mut self) -> Option<String> {
+        let rest = &self.input[self.pos + 1..];
+        let (radix, prefix_len) = match rest.chars().next() {
+            Some('x') | Some('X') => (16, 1),
+            _ => (10, 0),
+        };
+        let digits = &rest[prefix_len..];
+        let digits_len = digits
+            .find(|c: char| !c.is_digit(radix))
+            .unwrap_or(digits.len());
+        if digits_len == 0 {
+            self.error("absence-of-digits-in-numeric-character-reference");
+            return None;
+        }
+
+        let value = digits[..digits_len].chars().fold(0u32, |value, c| {
+            value
+                .saturating_mul(radix)
+                .saturating_add(c.to_digit(radix).unwrap())
+        });
+        let terminated = digits[digits_len..].starts_with(';');
+        self.pos += 1 + prefix_len + digits_len + usize::from(terminated);
+        if !terminated {
+            self.error("missing-semicolon-after-character-reference");
+        }
+        match value {
+            0 => self.error("null-character-reference"),
+            0xD800..=0xDFFF => self.error("surrogate-character-reference"),
+            0x80..=0x9F => self.error("control-character-reference"),
+            0x110000.. => self.error("character-reference-outside-unicode-range"),
+            _ => {}
+        }
+        Some(numeric_char(value).to_string())
+    }
+
+    fn push_char_ref_to_attr(&mut self) {
+        match self.consume_char_ref(true) {
+            Some(value) => self.attr_value().push_str(&value),
+            None => self.attr_value().push('&'),
+        }
+    }
+
+    fn is_appropriate_end_tag(&self) -> bool {
+        !self.last_start_tag.is_empty() && self.tag.name == self.last_start_tag
+    }
+
+    /// The next token, or `None` if more input is needed to produce it.
+    pub fn next_token(&mut self) -> Option<(Token, Span)> {
+        loop {
+            if let Some(token) = self.pending.pop_front() {
+                return Some(token);
+            }
+            if !self.ended && self.input.len() - self.pos < LOOKAHEAD {
+                return None;
+            }
+            self.step();
+        }
+    }
+
+    fn eof_error(&self) -> Option<&'static str> {
+        match self.state {
+            State::Data
+            | State::RcData
+            | State::RawText
+            | State::ScriptData
+            | State::Plaintext
+            | State::TextLessThanSign
+            | State::TextEndTagOpen
+            | State::TextEndTagName => None,
+            State::TagOpen | State::EndTagOpen => Some("eof-before-tag-name"),
+            State::BogusComment => None,
+            State::CommentStart
+            | State::CommentStartDash
+            | State::Comment
+            | State::CommentEndDash
+            | State::CommentEnd
+            | State::CommentEndBang => Some("eof-in-comment"),
+            State::Doctype
+            | State::BeforeDoctypeName
+            | State::DoctypeName
+            | State::AfterDoctypeName
+            | State::BeforeDoctypePublicIdentifier
+            | State::DoctypePublicIdentifierQuoted
+            | State::AfterDoctypePublicIdentifier
+            | State::BeforeDoctypeSystemIdentifier
+            | State::DoctypeSystemIdentifierQuoted
+            | State::AfterDoctypeSystemIdentifier
+            | State::BogusDoctype => Some("eof-in-doctype"),
+            State::CdataSection | State::CdataSectionBracket | State::CdataSectionEnd => {
+                Some("eof-in-cdata")
+            }
+            _ => Some("eof-in-tag"),
+        }
+    }
+
+    fn step(&mut self) {
+        if matches!(
+            self.state,
+            State::Data
+                | State::RcData
+                | State::RawText
+                | State::ScriptData
+                | State::Plaintext
+                | State::CdataSection
+        ) {
+            self.token_start = self.pos;
+        }
+
+        let c = self.consume_char();
+        if c.is_none() {
+            if let Some(message) = self.eof_error() {
+                self.error(message);
+            }
+        } else if c == Some('\0') {
+            self.error("unexpected-null-character");
+        }
+
+        match self.state {
+            State::Data => match c {
+                Some('<') => self.state = State::TagOpen,
+                Some('&') => match self.consume_char_ref(false) {
+                    Some(value) => self.emit_str(&value),
+                    None => self.emit(Token::Character('&')),
+                },
+                Some(c) => self.emit(Token::Character(c)),
+                None => self.emit(Token::Eof),
+            },
+            State::RcData => match c {
+                Some('<') => {
+                    self.return_state = State::RcData;
+                    self.state = State::TextLessThanSign;
+                }
+                Some('&') => match self.consume_char_ref(false) {
+                    Some(value) => self.emit_str(&value),
+                    None => self.emit(Token::Character('&')),
+                },
+                Some(c) => self.emit(Token::Character(c)),
+                None => self.emit(Token::Eof),
+            },
+            State::RawText | State::ScriptData => match c {
+                Some('<') => {
+                    self.return_state = self.state;
+                    self.state = State::TextLessThanSign;
+                }
+                Some(c) => self.emit(Token::Character(c)),
+                None => self.emit(Token::Eof),
+            },
+            State::Plaintext => match c {
+                Some(c) => self.emit(Token::Character(c)),
+                None => self.emit(Token::Eof),
+            },
+            State::TextLessThanSign => match c {
+                Some('/') => {
+                    self.temp_buffer.clear();
+                    self.state = State::TextEndTagOpen;
+                }
+                _ => {
+                    self.emit(Token::Character('<'));
+                    self.reconsume(c, self.return_state);
+                }
+            },
+            State::TextEndTagOpen => match c {
+                Some(c) if c.is_ascii_alphabetic() => {
+                    self.start_tag(true);
+                    self.reconsume(Some(c), State::TextEndTagName);
+                }
+                _ => {
+                    self.emit_str("</");
+                    self.reconsume(c, self.return_state);
+                }
+            },
+            State::TextEndTagName => match c {
+                Some(ch) if is_whitespace(ch) && self.is_appropriate_end_tag() => {
+                    self.state = State::BeforeAttributeName;
+                }
+                Some('/') if self.is_appropriate_end_tag() => {
+                    self.state = State::SelfClosingStartTag;
+                }
+                Some('>') if self.is_appropriate_end_tag() => {
+                    self.state = State::Data;
+                    self.emit_tag();
+                }
+                Some(ch) if ch.is_ascii_alphabetic() => {
+                    self.tag.name.push(ch.to_ascii_lowercase());
+                    self.temp_buffer.push(ch);
+                }
+                _ => {
+                    let buffer = std::mem::take(&mut self.temp_buffer);
+                    self.emit_str("</");
+                    self.emit_str(&buffer);
+                    self.reconsume(c, self.return_state);
+                }
+            },
+            State::TagOpen => match c {
+                Some('!') => self.markup_declaration_open(),
+                Some('?') => {
+                    self.error("unexpected-question-mark-instead-of-tag-name");
+                    self.comment.clear();
+                    self.reconsume(c, State::BogusComment);
+                }
+                Some('/') => self.state = State::EndTagOpen,
+                Some(ch) if ch.is_ascii_alphabetic() => {
+                    self.start_tag(false);
+                    self.reconsume(c, State::TagName);
+                }
+                _ => {
+                    if c.is_some() {
+                        self.error("invalid-first-character-of-tag-name");
+                    }
+                    self.emit(Token::Character('<'));
+                    self.reconsume(c, State::Data);
+                }
+            },
+            State::EndTagOpen => match c {
+                Some(ch) if ch.is_ascii_alphabetic() => {
+                    self.start_tag(true);
+                    self.reconsume(c, State::TagName);
+                }
+                Some('>') => {
+                    self.error("missing-end-tag-name");
+                    self.state = State::Data;
+                }
+                None => {
+                    self.emit_str("</");
+                    self.emit(Token::Eof);
+                }
+                _ => {
+                    self.error("invalid-first-character-of-tag-name");
+                    self.comment.clear();
+                    self.reconsume(c, State::BogusComment);
+                }
+            },
+            State::TagName => match c {
+                Some(ch) if is_whitespace(ch) => self.state = State::BeforeAttributeName,
+                Some('/') => self.state = State::SelfClosingStartTag,
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_tag();
+                }
+                Some(ch) => self.tag.name.push(ch.to_ascii_lowercase()),
+                None => self.emit(Token::Eof),
+            },
+            State::BeforeAttributeName => match c {
+                Some(ch) if is_whitespace(ch) => {}
+                Some('/') | Some('>') | None => self.reconsume(c, State::AfterAttributeName),
+                Some('=') => {
+                    self.error("unexpected-equals-sign-before-attribute-name");
+                    self.start_attr();
+                    self.attr_name().push('=');
+                    self.state = State::AttributeName;
+                }
+                Some(_) => {
+                    self.start_attr();
+                    self.reconsume(c, State::AttributeName);
+                }
+            },
+            State::AttributeName => match c {
+                Some(ch) if is_whitespace(ch) => self.state = State::AfterAttributeName,
+                Some('/') | Some('>') | None => self.reconsume(c, State::AfterAttributeName),
+                Some('=') => self.state = State::BeforeAttributeValue,
+                Some(ch) => {
+                    if matches!(ch, '"' | '\'' | '<') {
+                        self.error("unexpected-character-in-attribute-name");
+                    }
+                    self.attr_name().push(ch.to_ascii_lowercase());
+                }
+            },
+            State::AfterAttributeName => match c {
+                Some(ch) if is_whitespace(ch) => {}
+                Some('/') => self.state = State::SelfClosingStartTag,
+                Some('=') => self.state = State::BeforeAttributeValue,
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_tag();
+                }
+                Some(_) => {
+                    self.start_attr();
+                    self.reconsume(c, State::AttributeName);
+                }
+                None => self.emit(Token::Eof),
+            },
+            State::BeforeAttributeValue => match c {
+                Some(ch) if is_whitespace(ch) => {}
+                Some('"') => self.state = State::AttributeValueDoubleQuoted,
+                Some('\'') => self.state = State::AttributeValueSingleQuoted,
+                Some('>') => {
+                    self.error("missing-attribute-value");
+                    self.state = State::Data;
+                    self.emit_tag();
+                }
+                _ => self.reconsume(c, State::AttributeValueUnquoted),
+            },
+            State::AttributeValueDoubleQuoted => match c {
+                Some('"') => self.state = State::AfterAttributeValueQuoted,
+                Some('&') => self.push_char_ref_to_attr(),
+                Some(ch) => self.attr_value().push(ch),
+                None => self.emit(Token::Eof),
+            },
+            State::AttributeValueSingleQuoted => match c {
+                Some('\'') => self.state = State::AfterAttributeValueQuoted,
+                Some('&') => self.push_char_ref_to_attr(),
+                Some(ch) => self.attr_value().push(ch),
+                None => self.emit(Token::Eof),
+            },
+            State::AttributeValueUnquoted => match c {
+                Some(ch) if is_whitespace(ch) => self.state = State::BeforeAttributeName,
+                Some('&') => self.push_char_ref_to_attr(),
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_tag();
+                }
+                Some(ch) => {
+                    if matches!(ch, '"' | '\'' | '<' | '=' | '`') {
+                        self.error("unexpected-character-in-unquoted-attribute-value");
+                    }
+                    self.attr_value().push(ch);
+                }
+                None => self.emit(Token::Eof),
+            },
+            State::AfterAttributeValueQuoted => match c {
+                Some(ch) if is_whitespace(ch) => self.state = State::BeforeAttributeName,
+                Some('/') => self.state = State::SelfClosingStartTag,
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_tag();
+                }
+                None => self.emit(Token::Eof),
+                Some(_) => {
+                    self.error("missing-whitespace-between-attributes");
+                    self.reconsume(c, State::BeforeAttributeName);
+                }
+            },
+            State::SelfClosingStartTag => match c {
+                Some('>') => {
+                    self.tag.self_closing = true;
+                    self.state = State::Data;
+                    self.emit_tag();
+                }
+                None => self.emit(Token::Eof),
+                Some(_) => {
+                    self.error("unexpected-solidus-in-tag");
+                    self.reconsume(c, State::BeforeAttributeName);
+                }
+            },
+            State::BogusComment => match c {
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_comment();
+                }
+                Some(ch) => self.comment.push(ch),
+                None => {
+                    self.emit_comment();
+                    self.emit(Token::Eof);
+                }
+            },
+            State::CommentStart => match c {
+                Some('-') => self.state = State::CommentStartDash,
+                Some('>') => {
+                    self.error("abrupt-closing-of-empty-comment");
+                    self.state = State::Data;
+                    self.emit_comment();
+                }
+                _ => self.reconsume(c, State::Comment),
+            },
+            State::CommentStartDash => match c {
+                Some('-') => self.state = State::CommentEnd,
+                Some('>') => {
+                    self.error("abrupt-closing-of-empty-comment");
+                    self.state = State::Data;
+                    self.emit_comment();
+                }
+                None => {
+                    self.emit_comment();
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.comment.push('-');
+                    self.reconsume(c, State::Comment);
+                }
+            },
+            State::Comment => match c {
+                Some('-') => self.state = State::CommentEndDash,
+                Some(ch) => self.comment.push(ch),
+                None => {
+                    self.emit_comment();
+                    self.emit(Token::Eof);
+                }
+            },
+            State::CommentEndDash => match c {
+                Some('-') => self.state = State::CommentEnd,
+                None => {
+                    self.emit_comment();
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.comment.push('-');
+                    self.reconsume(c, State::Comment);
+                }
+            },
+            State::CommentEnd => match c {
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_comment();
+                }
+                Some('!') => self.state = State::CommentEndBang,
+                Some('-') => self.comment.push('-'),
+                None => {
+                    self.emit_comment();
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.comment.push_str("--");
+                    self.reconsume(c, State::Comment);
+                }
+            },
+            State::CommentEndBang => match c {
+                Some('-') => {
+                    self.comment.push_str("--!");
+                    self.state = State::CommentEndDash;
+                }
+                Some('>') => {
+                    self.error("incorrectly-closed-comment");
+                    self.state = State::Data;
+                    self.emit_comment();
+                }
+                None => {
+                    self.emit_comment();
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.comment.push_str("--!");
+                    self.reconsume(c, State::Comment);
+                }
+            },
+            State::Doctype => {
+                self.doctype = Doctype::default();
+                match c {
+                    Some(ch) if is_whitespace(ch) => self.state = State::BeforeDoctypeName,
+                    None => {
+                        self.emit_quirky_doctype();
+                        self.emit(Token::Eof);
+                    }
+                    _ => self.reconsume(c, State::BeforeDoctypeName),
+                }
+            }
+            State::BeforeDoctypeName => match c {
+                Some(ch) if is_whitespace(ch) => {}
+                Some('>') => {
+                    self.error("missing-doctype-name");
+                    self.state = State::Data;
+                    self.emit_quirky_doctype();
+                }
+                Some(ch) => {
+                    self.doctype.data.name.push(ch.to_ascii_lowercase());
+                    self.state = State::DoctypeName;
+                }
+                None => {
+                    self.emit_quirky_doctype();
+                    self.emit(Token::Eof);
+                }
+            },
+            State::DoctypeName => match c {
+                Some(ch) if is_whitespace(ch) => self.state = State::AfterDoctypeName,
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_doctype();
+                }
+                Some(ch) => self.doctype.data.name.push(ch.to_ascii_lowercase()),
+                None => {
+                    self.emit_quirky_doctype();
+                    self.emit(Token::Eof);
+                }
+            },
+            State::AfterDoctypeName => match c {
+                Some(ch) if is_whitespace(ch) => {}
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_doctype();
+                }
+                None => {
+                    self.emit_quirky_doctype();
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.reconsume(c, State::AfterDoctypeName);
+                    if self.consume_keyword("PUBLIC", true) {
+                        self.state = State::BeforeDoctypePublicIdentifier;
+                    } else if self.consume_keyword("SYSTEM", true) {
+                        self.state = State::BeforeDoctypeSystemIdentifier;
+                    } else {
+                        self.error("invalid-character-sequence-after-doctype-name");
+                        self.doctype.force_quirks = true;
+                        self.state = State::BogusDoctype;
+                    }
+                }
+            },
+            State::BeforeDoctypePublicIdentifier | State::BeforeDoctypeSystemIdentifier => {
+                match c {
+                    Some(ch) if is_whitespace(ch) => {}
+                    Some(quote @ ('"' | '\'')) => {
+                        self.quote = quote;
+                        if self.state == State::BeforeDoctypePublicIdentifier {
+                            self.doctype.data.public_id = Some(String::new());
+                            self.state = State::DoctypePublicIdentifierQuoted;
+                        } else {
+                            self.doctype.data.system_id = Some(String::new());
+                            self.state = State::DoctypeSystemIdentifierQuoted;
+                        }
+                    }
+                    Some('>') => {
+                        self.error(match self.state {
+                            State::BeforeDoctypePublicIdentifier => {
+                                "missing-doctype-public-identifier"
+                            }
+                            _ => "missing-doctype-system-identifier",
+                        });
+                        self.state = State::Data;
+                        self.emit_quirky_doctype();
+                    }
+                    None => {
+                        self.emit_quirky_doctype();
+                        self.emit(Token::Eof);
+                    }
+                    Some(_) => {
+                        self.error(match self.state {
+                            State::BeforeDoctypePublicIdentifier => {
+                                "missing-quote-before-doctype-public-identifier"
+                            }
+                            _ => "missing-quote-before-doctype-system-identifier",
+                        });
+                        self.doctype.force_quirks = true;
+                        self.reconsume(c, State::BogusDoctype);
+                    }
+                }
+            }
+            State::DoctypePublicIdentifierQuoted | State::DoctypeSystemIdentifierQuoted => {
+                let public = self.state == State::DoctypePublicIdentifierQuoted;
+                match c {
+                    Some(ch) if ch == self.quote => {
+                        self.state = match public {
+                            true => State::AfterDoctypePublicIdentifier,
+                            false => State::AfterDoctypeSystemIdentifier,
+                        };
+                    }
+                    Some('>') => {
+                        self.error(match public {
+                            true => "abrupt-doctype-public-identifier",
+                            false => "abrupt-doctype-system-identifier",
+                        });
+                        self.state = State::Data;
+                        self.emit_quirky_doctype();
+                    }
+                    Some(ch) => {
+                        let id = match public {
+                            true => &mut self.doctype.data.public_id,
+                            false => &mut self.doctype.data.system_id,
+                        };
+                        id.get_or_insert_with(String::new).push(ch);
+                    }
+                    None => {
+                        self.emit_quirky_doctype();
+                        self.emit(Token::Eof);
+                    }
+                }
+            }
+            State::AfterDoctypePublicIdentifier => match c {
+                Some(ch) if is_whitespace(ch) => {}
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_doctype();
+                }
+                Some(quote @ ('"' | '\'')) => {
+                    self.quote = quote;
+                    self.doctype.data.system_id = Some(String::new());
+                    self.state = State::DoctypeSystemIdentifierQuoted;
+                }
+                None => {
+                    self.emit_quirky_doctype();
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.error("missing-quote-before-doctype-system-identifier");
+                    self.doctype.force_quirks = true;
+                    self.reconsume(c, State::BogusDoctype);
+                }
+            },
+            State::AfterDoctypeSystemIdentifier => match c {
+                Some(ch) if is_whitespace(ch) => {}
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_doctype();
+                }
+                None => {
+                    self.emit_quirky_doctype();
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.error("unexpected-character-after-doctype-system-identifier");
+                    self.reconsume(c, State::BogusDoctype);
+                }
+            },
+            State::BogusDoctype => match c {
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_doctype();
+                }
+                Some(_) => {}
+                None => {
+                    self.emit_doctype();
+                    self.emit(Token::Eof);
+                }
+            },
+            State::CdataSection => match c {
+                Some(']') => self.state = State::CdataSectionBracket,
+                Some(ch) => self.emit(Token::Character(ch)),
+                None => self.emit(Token::Eof),
+            },
+            State::CdataSectionBracket => match c {
+                Some(']') => self.state = State::CdataSectionEnd,
+                _ => {
+                    self.emit(Token::Character(']'));
+                    self.reconsume(c, State::CdataSection);
+                }
+            },
+            State::CdataSectionEnd => match c {
+                Some(']') => self.emit(Token::Character(']')),
+                Some('>') => self.state = State::Data,
+                _ => {
+                    self.emit_str("]]");
+                    self.reconsume(c, State::CdataSection);
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tokenize(input: &str) -> Vec<Token> {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.feed(input);
+        tokenizer.end();
+        let mut tokens = Vec::new();
+        while let Some((token, _)) = tokenizer.next_token() {
+            match token {
+                Token::Eof => break,
+                token => tokens.push(token),
+            }
+        }
+        tokens
+    }
+
+    #[test]
+    fn tokenizes_tags_and_text() {
+        let tokens = tokenize("<p class=\"a\">hi</p>");
+        assert_eq!(
+            tokens,
+            vec![
+                Token::StartTag(Tag {
+                    name: "p".to_string(),
+                    attrs: vec![("class".to_string(), "a".to_string())],
+                    self_closing: false,
+                }),
+                Token::Character('h'),
+                Token::Character('i'),
+                Token::EndTag(Tag::new("p")),
+            ]
+        );
+    }
+
+    #[test]
+    fn lowercases_tag_and_attribute_names() {
+        let tokens = tokenize("<DIV ID='x'/>");
+        assert_eq!(
+            tokens,
+            vec![Token::StartTag(Tag {
+                name: "div".to_string(),
+                attrs: vec![("id".to_string(), "x".to_string())],
+                self_closing: true,
+            })]
+        );
+    }
+
+    #[test]
+    fn treats_stray_less_than_as_text() {
+        let tokens = tokenize("a < b");
+        assert_eq!(
+            tokens,
+            "a < b".chars().map(Token::Character).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn drops_unterminated_tag_at_eof() {
+        assert_eq!(tokenize("x<div class=\"y"), vec![Token::Character('x')]);
+    }
+}
@@ -0,0 +1,2057 @@
+use crate::engine::dom::{
+    comment, doctype, elem, text, AttrMap, DoctypeData, Document, Node, ParseError, QuirksMode,
+    Span,
+};
+
+use super::tokenizer::{Doctype, State, Tag, Token};
+
+/// Elements nested deeper than this are attached to the ancestor at this
+/// depth instead, so hostile markup can't blow the stack in later passes.
+const MAX_TREE_DEPTH: usize = 512;
+
+static SPECIAL_TAGS: [&str; 83] = [
+    "address",
+    "applet",
+    "area",
+    "article",
+    "aside",
+    "base",
+    "basefont",
+    "bgsound",
+    "blockquote",
+    "body",
+    "br",
+    "button",
+    "caption",
+    "center",
+    "col",
+    "colgroup",
+    "dd",
+    "details",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "embed",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "frame",
+    "frameset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hgroup",
+    "hr",
+    "html",
+    "iframe",
+    "img",
+    "input",
+    "keygen",
+    "li",
+    "link",
+    "listing",
+    "main",
+    "marquee",
+    "menu",
+    "meta",
+    "nav",
+    "noembed",
+    "noframes",
+    "noscript",
+    "object",
+    "ol",
+    "p",
+    "param",
+    "plaintext",
+    "pre",
+    "script",
+    "search",
+    "section",
+    "select",
+    "source",
+    "style",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "template",
+    "textarea",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "track",
+    "ul",
+    "wbr",
+    "xmp",
+];
+
+static FORMATTING_TAGS: [&str; 14] = [
+    "a", "b", "big", "code", "em", "font", "i", "nobr", "s", "small", "strike", "strong", "tt", "u",
+];
+
+static BLOCK_TAGS: [&str; 28] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "center",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "header",
+    "hgroup",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "search",
+    "section",
+    "summary",
+    "ul",
+    "listing",
+    "pre",
+    "button",
+];
+
+static HEADING_TAGS: [&str; 6] = ["h1", "h2", "h3", "h4", "h5", "h6"];
+
+static IMPLIED_END_TAGS: [&str; 10] = [
+    "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc",
+];
+
+/// Elements that may still be open when the input ends.
+static OPTIONAL_END_TAGS: [&str; 17] = [
+    "body", "dd", "dt", "html", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc", "tbody",
+    "td", "tfoot", "th", "tr",
+];
+
+static DEFAULT_SCOPE: [&str; 9] = [
+    "applet", "caption", "html", "table", "td", "th", "marquee", "object", "template",
+];
+
+static TABLE_SECTION_TAGS: [&str; 3] = ["tbody", "tfoot", "thead"];
+
+static TABLE_TAGS: [&str; 9] = [
+    "caption", "col", "colgroup", "tbody", "td", "tfoot", "th", "thead", "tr",
+];
+
+/// Public identifiers of old doctypes that put pages into quirks mode.
+static QUIRKY_PUBLIC_ID_PREFIXES: [&str; 55] = [
+    "+//silmaril//dtd html pro v0r11 19970101//",
+    "-//as//dtd html 3.0 aswedit + extensions//",
+    "-//advasoft ltd//dtd html 3.0 aswedit + extensions//",
+    "-//ietf//dtd html 2.0 level 1//",
+    "-//ietf//dtd html 2.0 level 2//",
+    "-//ietf//dtd html 2.0 strict level 1//",
+    "-//ietf//dtd html 2.0 strict level 2//",
+    "-//ietf//dtd html 2.0 strict//",
+    "-//ietf//dtd html 2.0//",
+    "-//ietf//dtd html 2.1e//",
+    "-//ietf//dtd html 3.0//",
+    "-//ietf//dtd html 3.2 final//",
+    "-//ietf//dtd html 3.2//",
+    "-//ietf//dtd html 3//",
+    "-//ietf//dtd html level 0//",
+    "-//ietf//dtd html level 1//",
+    "-//ietf//dtd html level 2//",
+    "-//ietf//dtd html level 3//",
+    "-//ietf//dtd html strict level 0//",
+    "-//ietf//dtd html strict level 1//",
+    "-//ietf//dtd html strict level 2//",
+    "-//ietf//dtd html strict level 3//",
+    "-//ietf//dtd html strict//",
+    "-//ietf//dtd html//",
+    "-//metrius//dtd metrius presentational//",
+    "-//microsoft//dtd internet explorer 2.0 html strict//",
+    "-//microsoft//dtd internet explorer 2.0 html//",
+    "-//microsoft//dtd internet explorer 2.0 tables//",
+    "-//microsoft//dtd internet explorer 3.0 html strict//",
+    "-//microsoft//dtd internet explorer 3.0 html//",
+    "-//microsoft//dtd internet explorer 3.0 tables//",
+    "-//netscape comm. corp.//dtd html//",
+    "-//netscape comm. corp.//dtd strict html//",
+    "-//o'reilly and associates//dtd html 2.0//",
+    "-//o'reilly and associates//dtd html extended 1.0//",
+    "-//o'reilly and associates//dtd html extended relaxed 1.0//",
+    "-//sq//dtd html 2.0 hotmetal + extensions//",
+    "-//softquad software//dtd hotmetal pro 6.0::19990601::extensions to html 4.0//",
+    "-//softquad//dtd hotmetal pro 4.0::19970916::extensions to html 4.0//",
+    "-//spyglass//dtd html 2.0 extended//",
+    "-//sun microsystems corp.//dtd hotjava html//",
+    "-//sun microsystems corp.//dtd hotjava strict html//",
+    "-//w3c//dtd html 3 1995-03-24//",
+    "-//w3c//dtd html 3.2 draft//",
+    "-//w3c//dtd html 3.2 final//",
+    "-//w3c//dtd html 3.2//",
+    "-//w3c//dtd html 3.2s draft//",
+    "-//w3c//dtd html 4.0 frameset//",
+    "-//w3c//dtd html 4.0 transitional//",
+    "-//w3c//dtd html experimental 19960712//",
+    "-//w3c//dtd html experimental 970421//",
+    "-//w3c//dtd w3 html//",
+    "-//w3o//dtd w3 html 3.0//",
+    "-//webtechs//dtd mozilla html 2.0//",
+    "-//webtechs//dtd mozilla html//",
+];
+
+static HTML4_TRANSITIONAL_PUBLIC_ID_PREFIXES: [&str; 2] = [
+    "-//w3c//dtd html 4.01 frameset//",
+    "-//w3c//dtd html 4.01 transitional//",
+];
+
+static XHTML1_TRANSITIONAL_PUBLIC_ID_PREFIXES: [&str; 2] = [
+    "-//w3c//dtd xhtml 1.0 frameset//",
+    "-//w3c//dtd xhtml 1.0 transitional//",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum InsertionMode {
+    Initial,
+    BeforeHtml,
+    BeforeHead,
+    InHead,
+    InHeadNoscript,
+    AfterHead,
+    InBody,
+    Text,
+    InTable,
+    InTableText,
+    InCaption,
+    InColumnGroup,
+    InTableBody,
+    InRow,
+    InCell,
+    InSelect,
+    InSelectInTable,
+    AfterBody,
+    AfterAfterBody,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Scope {
+    Default,
+    ListItem,
+    Button,
+    Table,
+    Select,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Formatting {
+    Marker,
+    Element(usize),
+}
+
+/// Whether the current token has been handled or has to go through the
+/// (possibly switched) insertion mode again.
+enum Step {
+    Done,
+    Reprocess(Token),
+}
+
+enum NodeData {
+    Document,
+    Doctype(DoctypeData),
+    Element(Tag),
+    Text(String),
+    Comment(String),
+}
+
+struct ArenaNode {
+    data: NodeData,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    span: Span,
+}
+
+/// Builds a tree out of tokens following the HTML5 tree construction rules.
+///
+/// Nodes live in an arena while parsing, since the adoption agency algorithm
+/// and foster parenting need to move them around freely.
+pub struct TreeBuilder {
+    nodes: Vec<ArenaNode>,
+    open_elements: Vec<usize>,
+    active_formatting: Vec<Formatting>,
+    mode: InsertionMode,
+    original_mode: InsertionMode,
+    quirks_mode: QuirksMode,
+    head: Option<usize>,
+    form: Option<usize>,
+    frameset_ok: bool,
+    foster_parenting: bool,
+    skip_next_newline: bool,
+    pending_table_text: String,
+    pending_table_span: Span,
+    tokenizer_state: Option<State>,
+    /// The span of the token being processed.
+    span: Span,
+    /// The name of the token being processed if it is a start or end tag,
+    /// used to tell explicit elements from implied ones.
+    start_tag: Option<String>,
+    end_tag: Option<String>,
+    errors: Vec<ParseError>,
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' ')
+}
+
+fn has_prefix(id: &str, prefixes: &[&str]) -> bool {
+    prefixes.iter().any(|prefix| {
+        id.get(..prefix.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+    })
+}
+
+fn quirks_mode(doctype: &Doctype) -> QuirksMode {
+    let data = &doctype.data;
+    let public_id = data.public_id.as_deref().unwrap_or_default();
+    let system_id = data.system_id.as_deref();
+    let public_is = |id: &str| public_id.eq_ignore_ascii_case(id);
+
+    if doctype.force_quirks
+        || data.name != "html"
+        || public_is("-//w3o//dtd w3 html strict 3.0//en//")
+        || public_is("-/w3c/dtd html 4.0 transitional/en")
+        || public_is("html")
+        || system_id.is_some_and(|id| {
+            id.eq_ignore_ascii_case("http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd")
+        })
+        || has_prefix(public_id, &QUIRKY_PUBLIC_ID_PREFIXES)
+        || (system_id.is_none() && has_prefix(public_id, &HTML4_TRANSITIONAL_PUBLIC_ID_PREFIXES))
+    {
+        QuirksMode::Quirks
+    } else if has_prefix(public_id, &XHTML1_TRANSITIONAL_PUBLIC_ID_PREFIXES)
+        || (system_id.is_some() && has_prefix(public_id, &HTML4_TRANSITIONAL_PUBLIC_ID_PREFIXES))
+    {
+        QuirksMode::LimitedQuirks
+    } else {
+        QuirksMode::NoQuirks
+    }
+}
+
+impl TreeBuilder {
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![ArenaNode {
+                data: NodeData::Document,
+                parent: None,
+                children: Vec::new(),
+                span: Span::default(),
+            }],
+            open_elements: Vec::new(),
+            active_formatting: Vec::new(),
+            mode: InsertionMode::Initial,
+            original_mode: InsertionMode::Initial,
+            quirks_mode: QuirksMode::NoQuirks,
+            head: None,
+            form: None,
+            frameset_ok: true,
+            foster_parenting: false,
+            skip_next_newline: false,
+            pending_table_text: String::new(),
+            pending_table_span: Span::default(),
+            tokenizer_state: None,
+            span: Span::default(),
+            start_tag: None,
+            end_tag: None,
+            errors: Vec::new(),
+        }
+    }
+
+    /// The state the tokenizer has to switch to after the last token, if any.
+    pub fn take_tokenizer_state(&mut self) -> Option<State> {
+        self.tokenizer_state.take()
+    }
+
+    /// Whether the tokenizer is inside SVG or MathML content.
+    pub fn in_foreign_content(&self) -> bool {
+        self.open_elements
+            .iter()
+            .any(|&id| self.is(id, &["svg", "math"]))
+    }
+
+    /// Converts the arena into a [`Document`]. Elements still open are taken
+    /// to end where the input did.
+    pub fn finish(mut self) -> Document {
+        while self.pop().is_some() {}
+        self.snapshot()
+    }
+
+    /// The tree built so far, for showing a page that is still loading.
+    pub fn snapshot(&self) -> Document {
+        Document {
+            children: self.nodes[0]
+                .children
+                .iter()
+                .map(|&id| self.to_node(id))
+                .collect(),
+            mode: self.quirks_mode,
+            encoding: None,
+            errors: self.errors.clone(),
+        }
+    }
+
+    fn to_node(&self, id: usize) -> Node {
+        let node = &self.nodes[id];
+        let mut result = match &node.data {
+            NodeData::Text(data) => text(data.clone()),
+            NodeData::Comment(data) => comment(data.clone()),
+            NodeData::Doctype(data) => doctype(data.clone()),
+            NodeData::Element(tag) => {
+                let mut attrs = AttrMap::new();
+                for (name, value) in &tag.attrs {
+                    attrs.insert(name.clone(), value.clone());
+                }
+                let children = node.children.iter().map(|&c| self.to_node(c)).collect();
+                elem(tag.name.clone(), attrs, children)
+            }
+            NodeData::Document => elem("html".to_string(), AttrMap::new(), vec![]),
+        };
+        result.span = node.span;
+        // Implied end tags can close an element before its adopted children
+        if let Some(last) = result.children.last() {
+            result.span.end = result.span.end.max(last.span.end);
+        }
+        result
+    }
+
+    pub fn process_token(&mut self, token: Token, span: Span) {
+        self.span = span;
+        self.start_tag = None;
+        self.end_tag = None;
+        match &token {
+            Token::StartTag(tag) => self.start_tag = Some(tag.name.clone()),
+            Token::EndTag(tag) => self.end_tag = Some(tag.name.clone()),
+            _ => {}
+        }
+
+        if self.skip_next_newline {
+            self.skip_next_newline = false;
+            if token == Token::Character('\n') {
+                return;
+            }
+        }
+
+        let mut token = token;
+        while let Step::Reprocess(next) = self.step(self.mode, token) {
+            token = next;
+        }
+    }
+
+    fn step(&mut self, mode: InsertionMode, token: Token) -> Step {
+        // Comments and doctypes are handled the same way in almost every mode
+        if mode != InsertionMode::InTableText {
+            match token {
+                Token::Comment(data) => {
+                    self.insert_comment(data);
+                    return Step::Done;
+                }
+                Token::Doctype(doctype) => {
+                    if mode == InsertionMode::Initial {
+                        self.insert_doctype(doctype);
+                    }
+                    return Step::Done;
+                }
+                _ => {}
+            }
+        }
+
+        match mode {
+            InsertionMode::Initial => self.initial(token),
+            InsertionMode::BeforeHtml => self.before_html(token),
+            InsertionMode::BeforeHead => self.before_head(token),
+            InsertionMode::InHead => self.in_head(token),
+            InsertionMode::InHeadNoscript => self.in_head_noscript(token),
+            InsertionMode::AfterHead => self.after_head(token),
+            InsertionMode::InBody => self.in_body(token),
+            InsertionMode::Text => self.text(token),
+            InsertionMode::InTable => self.in_table(token),
+            InsertionMode::InTableText => self.in_table_text(token),
+            InsertionMode::InCaption => self.in_caption(token),
+            InsertionMode::InColumnGroup => self.in_column_group(token),
+            InsertionMode::InTableBody => self.in_table_body(token),
+            InsertionMode::InRow => self.in_row(token),
+            InsertionMode::InCell => self.in_cell(token),
+            InsertionMode::InSelect => self.in_select(token),
+            InsertionMode::InSelectInTable => self.in_select_in_table(token),
+            InsertionMode::AfterBody => self.after_body(token),
+            InsertionMode::AfterAfterBody => self.after_after_body(token),
+        }
+    }
+
+    // Tree helpers
+
+    fn tag_name(&self, id: usize) -> &str {
+        match &self.nodes[id].data {
+            NodeData::Element(tag) => &tag.name,
+            _ => "",
+        }
+    }
+
+    fn is(&self, id: usize, names: &[&str]) -> bool {
+        names.contains(&self.tag_name(id))
+    }
+
+    fn current_node(&self) -> usize {
+        *self.open_elements.last().unwrap_or(&0)
+    }
+
+    fn current_is(&self, names: &[&str]) -> bool {
+        !self.open_elements.is_empty() && self.is(self.current_node(), names)
+    }
+
+    fn error(&mut self, message: String) {
+        self.errors.push(ParseError {
+            message,
+            span: self.span,
+        });
+    }
+
+    fn new_node(&mut self, data: NodeData) -> usize {
+        self.nodes.push(ArenaNode {
+            data,
+            parent: None,
+            children: Vec::new(),
+            span: self.span,
+        });
+        self.nodes.len() - 1
+    }
+
+    fn detach(&mut self, id: usize) {
+        if let Some(parent) = self.nodes[id].parent.take() {
+            self.nodes[parent].children.retain(|&c| c != id);
+        }
+    }
+
+    fn insert_at(&mut self, parent: usize, before: Option<usize>, id: usize) {
+        self.detach(id);
+        self.nodes[id].parent = Some(parent);
+        let children = &mut self.nodes[parent].children;
+        match before.and_then(|b| children.iter().position(|&c| c == b)) {
+            Some(index) => children.insert(index, id),
+            None => children.push(id),
+        }
+    }
+
+    /// Finds where a new node goes, honouring foster parenting.
+    fn appropriate_place(&self, override_target: Option<usize>) -> (usize, Option<usize>) {
+        let target = override_target.unwrap_or_else(|| self.current_node());
+
+        if self.foster_parenting && self.is(target, &["table", "tbody", "tfoot", "thead", "tr"]) {
+            let last_table = self
+                .open_elements
+                .iter()
+                .rposition(|&id| self.is(id, &["table"]));
+            return match last_table {
+                None => (self.open_elements[0], None),
+                Some(index) => {
+                    let table = self.open_elements[index];
+                    match self.nodes[table].parent {
+                        Some(parent) => (parent, Some(table)),
+                        None => (self.open_elements[index.saturating_sub(1)], None),
+                    }
+                }
+            };
+        }
+        (target, None)
+    }
+
+    fn insert_comment(&mut self, data: String) {
+        let (parent, before) = match self.mode {
+            InsertionMode::Initial | InsertionMode::BeforeHtml | InsertionMode::AfterAfterBody => {
+                (0, None)
+            }
+            InsertionMode::AfterBody => (self.open_elements[0], None),
+            _ => self.appropriate_place(None),
+        };
+        let id = self.new_node(NodeData::Comment(data));
+        self.insert_at(parent, before, id);
+    }
+
+    fn insert_doctype(&mut self, doctype: Doctype) {
+        self.quirks_mode = quirks_mode(&doctype);
+        let id = self.new_node(NodeData::Doctype(doctype.data));
+        self.insert_at(0, None, id);
+        self.mode = InsertionMode::BeforeHtml;
+    }
+
+    fn create_element(&mut self, tag: Tag) -> usize {
+        let implied = self.start_tag.as_ref() != Some(&tag.name);
+        let id = self.new_node(NodeData::Element(tag));
+        if implied {
+            self.nodes[id].span.end = self.span.start;
+        }
+        id
+    }
+
+    fn insert_element(&mut self, tag: Tag) -> usize {
+        let id = self.create_element(tag);
+        let (parent, before) = match self.open_elements.len() >= MAX_TREE_DEPTH {
+            true => (self.open_elements[MAX_TREE_DEPTH - 1], None),
+            false => self.appropriate_place(None),
+        };
+        self.insert_at(parent, before, id);
+        self.open_elements.push(id);
+        id
+    }
+
+    fn insert_void_element(&mut self, tag: Tag) {
+        self.insert_element(tag);
+        self.pop();
+    }
+
+    fn insert_character(&mut self, c: char) {
+        let (parent, before) = self.appropriate_place(None);
+        if let NodeData::Document = self.nodes[parent].data {
+            return;
+        }
+        let siblings = &self.nodes[parent].children;
+        let previous = match before.and_then(|b| siblings.iter().position(|&s| s == b)) {
+            Some(index) => index.checked_sub(1).map(|i| siblings[i]),
+            None => siblings.last().copied(),
+        };
+        if let Some(previous) = previous {
+            if let NodeData::Text(data) = &mut self.nodes[previous].data {
+                data.push(c);
+                self.nodes[previous].span.end = self.span.end;
+                return;
+            }
+        }
+        let id = self.new_node(NodeData::Text(c.to_string()));
+        self.insert_at(parent, before, id);
+    }
+
+    /// Records where an element leaving the stack of open elements ends: after
+    /// its end tag if that closed it, or where the token that implied its end
+    /// starts.
+    fn close(&mut self, id: usize) {
+        let end = match self.end_tag.as_deref() == Some(self.tag_name(id)) {
+            true => self.span.end,
+            false => self.span.start,
+        };
+        let span = &mut self.nodes[id].span;
+        span.end = span.end.max(end);
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        let id = self.open_elements.pop()?;
+        self.close(id);
+        Some(id)
+    }
+
+    fn pop_until(&mut self, names: &[&str]) {
+        while let Some(id) = self.pop() {
+            if self.is(id, names) {
+                break;
+            }
+        }
+    }
+
+    fn remove_from_stack(&mut self, id: usize) {
+        if let Some(index) = self.stack_index(id) {
+            self.close(id);
+            self.open_elements.remove(index);
+        }
+    }
+
+    /// Reports an end tag that closes elements other than the current node.
+    fn check_current(&mut self, names: &[&str]) {
+        if !self.current_is(names) {
+            let name = self.tag_name(self.current_node()).to_string();
+            self.error(format!("end-tag-too-early, <{name}> is still open"));
+        }
+    }
+
+    fn in_scope_of(&self, names: &[&str], scope: Scope) -> bool {
+        for &id in self.open_elements.iter().rev() {
+            if self.is(id, names) {
+                return true;
+            }
+            let name = self.tag_name(id);
+            let boundary = match scope {
+                Scope::Default => DEFAULT_SCOPE.contains(&name),
+                Scope::ListItem => DEFAULT_SCOPE.contains(&name) || name == "ol" || name == "ul",
+                Scope::Button => DEFAULT_SCOPE.contains(&name) || name == "button",
+                Scope::Table => matches!(name, "html" | "table" | "template"),
+                Scope::Select => !matches!(name, "optgroup" | "option"),
+            };
+            if boundary {
+                return false;
+            }
+        }
+        false
+    }
+
+    fn in_scope(&self, name: &str) -> bool {
+        self.in_scope_of(&[name], Scope::Default)
+    }
+
+    fn element_in_scope(&self, target: usize) -> bool {
+        for &id in self.open_elements.iter().rev() {
+            if id == target {
+                return true;
+            }
+            if DEFAULT_SCOPE.contains(&self.tag_name(id)) {
+                return false;
+            }
+        }
+        false
+    }
+
+    fn generate_implied_end_tags(&mut self, except: Option<&str>) {
+        while self.current_is(&IMPLIED_END_TAGS)
+            && Some(self.tag_name(self.current_node())) != except
+        {
+            self.pop();
+        }
+    }
+
+    fn close_p(&mut self) {
+        self.generate_implied_end_tags(Some("p"));
+        self.pop_until(&["p"]);
+    }
+
+    fn close_p_in_button_scope(&mut self) {
+        if self.in_scope_of(&["p"], Scope::Button) {
+            self.close_p();
+        }
+    }
+
+    fn clear_formatting_to_marker(&mut self) {
+        while let Some(entry) = self.active_formatting.pop() {
+            if entry == Formatting::Marker {
+                break;
+            }
+        }
+    }
+
+    fn push_formatting(&mut self, id: usize) {
+        // Noah's Ark clause: keep at most three identical entries
+        let mut same = Vec::new();
+        for (index, entry) in self.active_formatting.iter().enumerate().rev() {
+            match entry {
+                Formatting::Marker => break,
+                Formatting::Element(other) => {
+                    if self.same_element(*other, id) {
+                        same.push(index);
+                    }
+                }
+            }
+        }
+        if same.len() >= 3 {
+            self.active_formatting.remove(*same.last().unwrap());
+        }
+        self.active_formatting.push(Formatting::Element(id));
+    }
+
+    fn same_element(&self, a: usize, b: usize) -> bool {
+        match (&self.nodes[a].data, &self.nodes[b].data) {
+            (NodeData::Element(a), NodeData::Element(b)) => {
+                let mut a_attrs = a.attrs.clone();
+                let mut b_attrs = b.attrs.clone();
+                a_attrs.sort();
+                b_attrs.sort();
+                a.name == b.name && a_attrs == b_attrs
+            }
+            _ => false,
+        }
+    }
+
+    fn clone_tag(&self, id: usize) -> Tag {
+        match &self.nodes[id].data {
+            NodeData::Element(tag) => Tag {
+                self_closing: false,
+                ..tag.clone()
+            },
+            _ => Tag::default(),
+        }
+    }
+
+    fn reconstruct_active_formatting(&mut self) {
+        let needs_reconstruction = |entry: &Formatting| match entry {
+            Formatting::Marker => false,
+            Formatting::Element(id) => !self.open_elements.contains(id),
+        };
+        let Some(last) = self.active_formatting.last() else {
+            return;
+        };
+        if !needs_reconstruction(last) {
+            return;
+        }
+
+        let mut index = self.active_formatting.len() - 1;
+        while index > 0 && needs_reconstruction(&self.active_formatting[index - 1]) {
+            index -= 1;
+        }
+
+        for i in index..self.active_formatting.len() {
+            if let Formatting::Element(id) = self.active_formatting[i] {
+                let tag = self.clone_tag(id);
+                let new = self.insert_element(tag);
+                self.active_formatting[i] = Formatting::Element(new);
+            }
+        }
+    }
+
+    fn formatting_element(&self, name: &str) -> Option<usize> {
+        for entry in self.active_formatting.iter().rev() {
+            match entry {
+                Formatting::Marker => return None,
+                Formatting::Element(id) if self.tag_name(*id) == name => return Some(*id),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn formatting_index(&self, id: usize) -> Option<usize> {
+        self.active_formatting
+            .iter()
+            .position(|&e| e == Formatting::Element(id))
+    }
+
+    fn stack_index(&self, id: usize) -> Option<usize> {
+        self.open_elements.iter().position(|&e| e == id)
+    }
+
+    /// The adoption agency algorithm, which untangles misnested formatting
+    /// elements like `<b><i></b></i>`.
+    fn adoption_agency(&mut self, tag: &Tag) {
+        let subject = tag.name.as_str();
+        let current = self.current_node();
+        if self.tag_name(current) == subject && self.formatting_index(current).is_none() {
+            self.pop();
+            return;
+        }
+
+        for _ in 0..8 {
+            let Some(formatting) = self.formatting_element(subject) else {
+                self.any_other_end_tag(tag);
+                return;
+            };
+            let Some(formatting_stack_index) = self.stack_index(formatting) else {
+                self.error(format!("unexpected-end-tag </{subject}>"));
+                let index = self.formatting_index(formatting).unwrap();
+                self.active_formatting.remove(index);
+                return;
+            };
+            if !self.element_in_scope(formatting) {
+                self.error(format!("unexpected-end-tag </{subject}>"));
+                return;
+            }
+            if formatting != self.current_node() {
+                self.error(format!("misnested-tag </{subject}>"));
+            }
+
+            let furthest_block = self.open_elements[formatting_stack_index + 1..]
+                .iter()
+                .copied()
+                .find(|&id| self.is(id, &SPECIAL_TAGS));
+            let Some(furthest_block) = furthest_block else {
+                while let Some(id) = self.pop() {
+                    if id == formatting {
+                        break;
+                    }
+                }
+                let index = self.formatting_index(formatting).unwrap();
+                self.active_formatting.remove(index);
+                return;
+            };
+
+            let common_ancestor = self.open_elements[formatting_stack_index - 1];
+            let mut bookmark = self.formatting_index(formatting).unwrap();
+            let mut node_index = self.stack_index(furthest_block).unwrap();
+            let mut last_node = furthest_block;
+            let mut inner = 0;
+            loop {
+                inner += 1;
+                node_index -= 1;
+                let mut node = self.open_elements[node_index];
+                if node == formatting {
+                    break;
+                }
+                if inner > 3 {
+                    if let Some(index) = self.formatting_index(node) {
+                        self.active_formatting.remove(index);
+                        if index < bookmark {
+                            bookmark -= 1;
+                        }
+                    }
+                }
+                let Some(node_formatting_index) = self.formatting_index(node) else {
+                    self.close(node);
+                    self.open_elements.remove(node_index);
+                    continue;
+                };
+
+                let new = self.create_element(self.clone_tag(node));
+                self.active_formatting[node_formatting_index] = Formatting::Element(new);
+                self.open_elements[node_index] = new;
+                node = new;
+
+                if last_node == furthest_block {
+                    bookmark = node_formatting_index + 1;
+                }
+                self.insert_at(node, None, last_node);
+                last_node = node;
+            }
+
+            self.detach(last_node);
+            let (parent, before) = self.appropriate_place(Some(common_ancestor));
+            self.insert_at(parent, before, last_node);
+
+            let new = self.create_element(self.clone_tag(formatting));
+            let children = std::mem::take(&mut self.nodes[furthest_block].children);
+            for child in children {
+                self.nodes[child].parent = None;
+                self.insert_at(new, None, child);
+            }
+            self.insert_at(furthest_block, None, new);
+
+            let index = self.formatting_index(formatting).unwrap();
+            self.active_formatting.remove(index);
+            if index < bookmark {
+                bookmark -= 1;
+            }
+            let bookmark = bookmark.min(self.active_formatting.len());
+            self.active_formatting
+                .insert(bookmark, Formatting::Element(new));
+
+            self.remove_from_stack(formatting);
+            let index = self.stack_index(furthest_block).unwrap();
+            self.open_elements.insert(index + 1, new);
+        }
+    }
+
+    fn any_other_end_tag(&mut self, tag: &Tag) {
+        for index in (0..self.open_elements.len()).rev() {
+            let id = self.open_elements[index];
+            if self.tag_name(id) == tag.name {
+                self.generate_implied_end_tags(Some(&tag.name));
+                self.check_current(&[&tag.name]);
+                while self.open_elements.len() > index {
+                    self.pop();
+                }
+                return;
+            }
+            if self.is(id, &SPECIAL_TAGS) {
+                break;
+            }
+        }
+        self.error(format!("unexpected-end-tag </{}>", tag.name));
+    }
+
+    fn reset_insertion_mode(&mut self) {
+        for (index, &id) in self.open_elements.iter().enumerate().rev() {
+            let last = index == 0;
+            let mode = match self.tag_name(id) {
+                "select" => {
+                    let in_table = self.open_elements[..index]
+                        .iter()
+                        .any(|&a| self.is(a, &["table"]));
+                    match in_table {
+                        true => InsertionMode::InSelectInTable,
+                        false => InsertionMode::InSelect,
+                    }
+                }
+                "td" | "th" if !last => InsertionMode::InCell,
+                "tr" => InsertionMode::InRow,
+                "tbody" | "thead" | "tfoot" => InsertionMode::InTableBody,
+                "caption" => InsertionMode::InCaption,
+                "colgroup" => InsertionMode::InColumnGroup,
+                "table" => InsertionMode::InTable,
+                "head" if !last => InsertionMode::InHead,
+                "body" => InsertionMode::InBody,
+                "html" => match self.head {
+                    None => InsertionMode::BeforeHead,
+                    Some(_) => InsertionMode::AfterHead,
+                },
+                _ if last => InsertionMode::InBody,
+                _ => continue,
+            };
+            self.mode = mode;
+            return;
+        }
+        self.mode = InsertionMode::InBody;
+    }
+
+    /// Elements whose content is RCDATA or raw text are inserted and the rest
+    /// of their content handled by the `Text` insertion mode.
+    fn parse_text_element(&mut self, tag: Tag, state: State) {
+        self.insert_element(tag);
+        self.tokenizer_state = Some(state);
+        self.original_mode = self.mode;
+        self.mode = InsertionMode::Text;
+    }
+
+    // Insertion modes
+
+    fn initial(&mut self, token: Token) -> Step {
+        match token {
+            Token::Character(c) if is_whitespace(c) => Step::Done,
+            token => {
+                // Pages without a doctype render in quirks mode
+                self.error("missing-doctype".to_string());
+                self.quirks_mode = QuirksMode::Quirks;
+                self.mode = InsertionMode::BeforeHtml;
+                Step::Reprocess(token)
+            }
+        }
+    }
+
+    fn before_html(&mut self, token: Token) -> Step {
+        match token {
+            Token::Character(c) if is_whitespace(c) => Step::Done,
+            Token::StartTag(tag) if tag.name == "html" => {
+                self.insert_element(tag);
+                self.mode = InsertionMode::BeforeHead;
+                Step::Done
+            }
+            Token::EndTag(tag) if !matches!(tag.name.as_str(), "head" | "body" | "html" | "br") => {
+                self.error(format!("unexpected-end-tag </{}>", tag.name));
+                Step::Done
+            }
+            token => {
+                self.insert_element(Tag::new("html"));
+                self.mode = InsertionMode::BeforeHead;
+                Step::Reprocess(token)
+            }
+        }
+    }
+
+    fn before_head(&mut self, token: Token) -> Step {
+        match token {
+            Token::Character(c) if is_whitespace(c) => Step::Done,
+            Token::StartTag(tag) if tag.name == "html" => self.in_body(Token::StartTag(tag)),
+            Token::StartTag(tag) if tag.name == "head" => {
+                self.head = Some(self.insert_element(tag));
+                self.mode = InsertionMode::InHead;
+                Step::Done
+            }
+            Token::EndTag(tag) if !matches!(tag.name.as_str(), "head" | "body" | "html" | "br") => {
+                self.error(format!("unexpected-end-tag </{}>", tag.name));
+                Step::Done
+            }
+            token => {
+                self.head = Some(self.insert_element(Tag::new("head")));
+                self.mode = InsertionMode::InHead;
+                Step::Reprocess(token)
+            }
+        }
+    }
+
+    fn in_head(&mut self, token: Token) -> Step {
+        match token {
+            Token::Character(c) if is_whitespace(c) => {
+                self.insert_character(c);
+                Step::Done
+            }
+            Token::StartTag(tag) => match tag.name.as_str() {
+                "html" => self.in_body(Token::StartTag(tag)),
+                "base" | "basefont" | "bgsound" | "link" | "meta" => {
+                    self.insert_void_element(tag);
+                    Step::Done
+                }
+                "title" => {
+                    self.parse_text_element(tag, State::RcData);
+                    Step::Done
+                }
+                "noframes" | "style" => {
+                    self.parse_text_element(tag, State::RawText);
+                    Step::Done
+                }
+                // Scripting is always disabled, so `noscript` holds markup
+                "noscript" => {
+                    self.insert_element(tag);
+                    self.mode = InsertionMode::InHeadNoscript;
+                    Step::Done
+                }
+                "script" => {
+                    self.parse_text_element(tag, State::ScriptData);
+                    Step::Done
+                }
+                "head" => Step::Done,
+                _ => self.anything_else_in_head(Token::StartTag(tag)),
+            },
+            Token::EndTag(tag) => match tag.name.as_str() {
+                "head" => {
+                    self.pop();
+                    self.mode = InsertionMode::AfterHead;
+                    Step::Done
+                }
+                "body" | "html" | "br" => self.anything_else_in_head(Token::EndTag(tag)),
+                _ => Step::Done,
+            },
+            token => self.anything_else_in_head(token),
+        }
+    }
+
+    fn anything_else_in_head(&mut self, token: Token) -> Step {
+        self.pop();
+        self.mode = InsertionMode::AfterHead;
+        Step::Reprocess(token)
+    }
+
+    fn in_head_noscript(&mut self, token: Token) -> Step {
+        match token {
+            Token::StartTag(tag) => match tag.name.as_str() {
+                "html" => self.in_body(Token::StartTag(tag)),
+                "basefont" | "bgsound" | "link" | "meta" | "noframes" | "style" => {
+                    self.in_head(Token::StartTag(tag))
+                }
+                "head" | "noscript" => Step::Done,
+                _ => self.anything_else_in_head_noscript(Token::StartTag(tag)),
+            },
+            Token::EndTag(tag) => match tag.name.as_str() {
+                "noscript" => {
+                    self.pop();
+                    self.mode = InsertionMode::InHead;
+                    Step::Done
+                }
+                "br" => self.anything_else_in_head_noscript(Token::EndTag(tag)),
+                _ => Step::Done,
+            },
+            Token::Character(c) if is_whitespace(c) => self.in_head(token),
+            token => self.anything_else_in_head_noscript(token),
+        }
+    }
+
+    fn anything_else_in_head_noscript(&mut self, token: Token) -> Step {
+        self.pop();
+        self.mode = InsertionMode::InHead;
+        Step::Reprocess(token)
+    }
+
+    fn after_head(&mut self, token: Token) -> Step {
+        match token {
+            Token::Character(c) if is_whitespace(c) => {
+                self.insert_character(c);
+                Step::Done
+            }
+            Token::StartTag(tag) => match tag.name.as_str() {
+                "html" => self.in_body(Token::StartTag(tag)),
+                "body" => {
+                    self.insert_element(tag);
+                    self.frameset_ok = false;
+                    self.mode = InsertionMode::InBody;
+                    Step::Done
+                }
+                "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script"
+                | "style" | "title" => {
+                    let head = self.head.unwrap_or(0);
+                    self.open_elements.push(head);
+                    let step = self.in_head(Token::StartTag(tag));
+                    self.remove_from_stack(head);
+                    step
+                }
+                "head" => Step::Done,
+                _ => self.anything_else_after_head(Token::StartTag(tag)),
+            },
+            Token::EndTag(tag) if !matches!(tag.name.as_str(), "body" | "html" | "br") => {
+                Step::Done
+            }
+            token => self.anything_else_after_head(token),
+        }
+    }
+
+    fn anything_else_after_head(&mut self, token: Token) -> Step {
+        self.insert_element(Tag::new("body"));
+        self.mode = InsertionMode::InBody;
+        Step::Reprocess(token)
+    }
+
+    fn in_body(&mut self, token: Token) -> Step {
+        match token {
+            Token::Character('\0') => {}
+            Token::Character(c) => {
+                self.reconstruct_active_formatting();
+                self.insert_character(c);
+                if !is_whitespace(c) {
+                    self.frameset_ok = false;
+                }
+            }
+            Token::Eof => self.report_unclosed(),
+            Token::Comment(_) | Token::Doctype(_) => {}
+            Token::StartTag(tag) => return self.in_body_start_tag(tag),
+            Token::EndTag(tag) => return self.in_body_end_tag(tag),
+        }
+        Step::Done
+    }
+
+    /// Reports the elements still open at the end of the input that need an
+    /// end tag, pointing at their start tags.
+    fn report_unclosed(&mut self) {
+        let unclosed: Vec<usize> = self
+            .open_elements
+            .iter()
+            .copied()
+            .filter(|&id| !self.is(id, &OPTIONAL_END_TAGS))
+            .collect();
+        for id in unclosed {
+            self.errors.push(ParseError {
+                message: format!("eof-in-element <{}>", self.tag_name(id)),
+                span: self.nodes[id].span,
+            });
+        }
+    }
+
+    fn in_body_start_tag(&mut self, mut tag: Tag) -> Step {
+        match tag.name.as_str() {
+            "html" => {
+                if let Some(&html) = self.open_elements.first() {
+                    self.merge_attrs(html, tag);
+                }
+            }
+            "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script" | "style"
+            | "title" => return self.in_head(Token::StartTag(tag)),
+            "body" => {
+                if self.open_elements.len() > 1 && self.is(self.open_elements[1], &["body"]) {
+                    self.frameset_ok = false;
+                    let body = self.open_elements[1];
+                    self.merge_attrs(body, tag);
+                }
+            }
+            "pre" | "listing" => {
+                self.close_p_in_button_scope();
+                self.insert_element(tag);
+                self.skip_next_newline = true;
+                self.frameset_ok = false;
+            }
+            "form" => {
+                if self.form.is_none() {
+                    self.close_p_in_button_scope();
+                    self.form = Some(self.insert_element(tag));
+                }
+            }
+            "li" => {
+                self.close_list_item(&["li"]);
+                self.close_p_in_button_scope();
+                self.insert_element(tag);
+            }
+            "dd" | "dt" => {
+                self.close_list_item(&["dd", "dt"]);
+                self.close_p_in_button_scope();
+                self.insert_element(tag);
+            }
+            "button" => {
+                if self.in_scope("button") {
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(&["button"]);
+                }
+                self.reconstruct_active_formatting();
+                self.insert_element(tag);
+                self.frameset_ok = false;
+            }
+            name if HEADING_TAGS.contains(&name) => {
+                self.close_p_in_button_scope();
+                if self.current_is(&HEADING_TAGS) {
+                    self.pop();
+                }
+                self.insert_element(tag);
+            }
+            name if BLOCK_TAGS.contains(&name) => {
+                self.close_p_in_button_scope();
+                self.insert_element(tag);
+            }
+            "plaintext" => {
+                self.close_p_in_button_scope();
+                self.insert_element(tag);
+                self.tokenizer_state = Some(State::Plaintext);
+            }
+            "a" => {
+                if let Some(a) = self.formatting_element("a") {
+                    self.adoption_agency(&Tag::new("a"));
+                    if let Some(index) = self.formatting_index(a) {
+                        self.active_formatting.remove(index);
+                    }
+                    self.remove_from_stack(a);
+                }
+                self.reconstruct_active_formatting();
+                let id = self.insert_element(tag);
+                self.push_formatting(id);
+            }
+            "nobr" => {
+                self.reconstruct_active_formatting();
+                if self.in_scope("nobr") {
+                    self.adoption_agency(&Tag::new("nobr"));
+                    self.reconstruct_active_formatting();
+                }
+                let id = self.insert_element(tag);
+                self.push_formatting(id);
+            }
+            name if FORMATTING_TAGS.contains(&name) => {
+                self.reconstruct_active_formatting();
+                let id = self.insert_element(tag);
+                self.push_formatting(id);
+            }
+            "applet" | "marquee" | "object" => {
+                self.reconstruct_active_formatting();
+                self.insert_element(tag);
+                self.active_formatting.push(Formatting::Marker);
+                self.frameset_ok = false;
+            }
+            "table" => {
+                if self.quirks_mode != QuirksMode::Quirks {
+                    self.close_p_in_button_scope();
+                }
+                self.insert_element(tag);
+                self.frameset_ok = false;
+                self.mode = InsertionMode::InTable;
+            }
+            "area" | "br" | "embed" | "img" | "keygen" | "wbr" => {
+                self.reconstruct_active_formatting();
+                self.insert_void_element(tag);
+                self.frameset_ok = false;
+            }
+            "input" => {
+                let hidden = tag
+                    .attr("type")
+                    .is_some_and(|t| t.eq_ignore_ascii_case("hidden"));
+                self.reconstruct_active_formatting();
+                self.insert_void_element(tag);
+                if !hidden {
+                    self.frameset_ok = false;
+                }
+            }
+            "param" | "source" | "track" => self.insert_void_element(tag),
+            "hr" => {
+                self.close_p_in_button_scope();
+                self.insert_void_element(tag);
+                self.frameset_ok = false;
+            }
+            "image" => {
+                tag.name = "img".to_string();
+                return Step::Reprocess(Token::StartTag(tag));
+            }
+            "textarea" => {
+                self.skip_next_newline = true;
+                self.frameset_ok = false;
+                self.parse_text_element(tag, State::RcData);
+            }
+            "xmp" => {
+                self.close_p_in_button_scope();
+                self.reconstruct_active_formatting();
+                self.frameset_ok = false;
+                self.parse_text_element(tag, State::RawText);
+            }
+            "iframe" | "noembed" => {
+                self.frameset_ok = false;
+                self.parse_text_element(tag, State::RawText);
+            }
+            "select" => {
+                self.reconstruct_active_formatting();
+                self.insert_element(tag);
+                self.frameset_ok = false;
+                self.mode = match self.mode {
+                    InsertionMode::InTable
+                    | InsertionMode::InCaption
+                    | InsertionMode::InTableBody
+                    | InsertionMode::InRow
+                    | InsertionMode::InCell => InsertionMode::InSelectInTable,
+                    _ => InsertionMode::InSelect,
+                };
+            }
+            "optgroup" | "option" => {
+                if self.current_is(&["option"]) {
+                    self.pop();
+                }
+                self.reconstruct_active_formatting();
+                self.insert_element(tag);
+            }
+            "rb" | "rtc" => {
+                if self.in_scope("ruby") {
+                    self.generate_implied_end_tags(None);
+                }
+                self.insert_element(tag);
+            }
+            "rp" | "rt" => {
+                if self.in_scope("ruby") {
+                    self.generate_implied_end_tags(Some("rtc"));
+                }
+                self.insert_element(tag);
+            }
+            "caption" | "col" | "colgroup" | "frame" | "head" | "tbody" | "td" | "tfoot" | "th"
+            | "thead" | "tr" => {}
+            _ => {
+                self.reconstruct_active_formatting();
+                let self_closing = tag.self_closing && matches!(tag.name.as_str(), "svg" | "math");
+                self.insert_element(tag);
+                if self_closing {
+                    self.pop();
+                }
+            }
+        }
+        Step::Done
+    }
+
+    fn merge_attrs(&mut self, id: usize, tag: Tag) {
+        if let NodeData::Element(existing) = &mut self.nodes[id].data {
+            for (name, value) in tag.attrs {
+                if existing.attr(&name).is_none() {
+                    existing.attrs.push((name, value));
+                }
+            }
+        }
+    }
+
+    /// Closes an open `li`, `dd` or `dt` when a new one starts.
+    fn close_list_item(&mut self, names: &[&str]) {
+        self.frameset_ok = false;
+        for &id in self.open_elements.iter().rev() {
+            if self.is(id, names) {
+                let name = self.tag_name(id).to_string();
+                self.generate_implied_end_tags(Some(&name));
+                self.pop_until(&[&name]);
+                return;
+            }
+            if self.is(id, &SPECIAL_TAGS) && !self.is(id, &["address", "div", "p"]) {
+                return;
+            }
+        }
+    }
+
+    fn in_body_end_tag(&mut self, tag: Tag) -> Step {
+        match tag.name.as_str() {
+            "body" => {
+                if self.in_scope("body") {
+                    self.mode = InsertionMode::AfterBody;
+                } else {
+                    self.error("unexpected-end-tag </body>".to_string());
+                }
+            }
+            "html" => {
+                if self.in_scope("body") {
+                    self.mode = InsertionMode::AfterBody;
+                    return Step::Reprocess(Token::EndTag(tag));
+                }
+                self.error("unexpected-end-tag </html>".to_string());
+            }
+            "form" => {
+                let node = self.form.take();
+                match node.filter(|&n| self.element_in_scope(n)) {
+                    Some(node) => {
+                        self.generate_implied_end_tags(None);
+                        if self.current_node() != node {
+                            self.error("end-tag-too-early </form>".to_string());
+                        }
+                        self.remove_from_stack(node);
+                    }
+                    None => self.error("unexpected-end-tag </form>".to_string()),
+                }
+            }
+            "p" => {
+                if !self.in_scope_of(&["p"], Scope::Button) {
+                    self.error("unexpected-end-tag </p>".to_string());
+                    self.insert_element(Tag::new("p"));
+                }
+                self.generate_implied_end_tags(Some("p"));
+                self.check_current(&["p"]);
+                self.pop_until(&["p"]);
+            }
+            "li" => {
+                if self.in_scope_of(&["li"], Scope::ListItem) {
+                    self.generate_implied_end_tags(Some("li"));
+                    self.check_current(&["li"]);
+                    self.pop_until(&["li"]);
+                } else {
+                    self.error("unexpected-end-tag </li>".to_string());
+                }
+            }
+            "dd" | "dt" => {
+                if self.in_scope(&tag.name) {
+                    self.generate_implied_end_tags(Some(&tag.name));
+                    self.check_current(&[&tag.name]);
+                    self.pop_until(&[&tag.name]);
+                } else {
+                    self.error(format!("unexpected-end-tag </{}>", tag.name));
+                }
+            }
+            name if HEADING_TAGS.contains(&name) => {
+                if self.in_scope_of(&HEADING_TAGS, Scope::Default) {
+                    self.generate_implied_end_tags(None);
+                    self.check_current(&[name]);
+                    self.pop_until(&HEADING_TAGS);
+                } else {
+                    self.error(format!("unexpected-end-tag </{name}>"));
+                }
+            }
+            name if BLOCK_TAGS.contains(&name) => {
+                if self.in_scope(name) {
+                    self.generate_implied_end_tags(None);
+                    self.check_current(&[name]);
+                    self.pop_until(&[name]);
+                } else {
+                    self.error(format!("unexpected-end-tag </{name}>"));
+                }
+            }
+            name if FORMATTING_TAGS.contains(&name) => self.adoption_agency(&tag),
+            "applet" | "marquee" | "object" => {
+                if self.in_scope(&tag.name) {
+                    self.generate_implied_end_tags(None);
+                    self.check_current(&[&tag.name]);
+                    self.pop_until(&[&tag.name]);
+                    self.clear_formatting_to_marker();
+                } else {
+                    self.error(format!("unexpected-end-tag </{}>", tag.name));
+                }
+            }
+            "br" => return self.in_body_start_tag(Tag::new("br")),
+            _ => self.any_other_end_tag(&tag),
+        }
+        Step::Done
+    }
+
+    fn text(&mut self, token: Token) -> Step {
+        match token {
+            Token::Character(c) => {
+                self.insert_character(c);
+                Step::Done
+            }
+            Token::Eof => {
+                self.pop();
+                self.mode = self.original_mode;
+                Step::Reprocess(Token::Eof)
+            }
+            Token::EndTag(_) => {
+                self.pop();
+                self.mode = self.original_mode;
+                Step::Done
+            }
+            Token::StartTag(_) | Token::Comment(_) | Token::Doctype(_) => Step::Done,
+        }
+    }
+
+    fn clear_stack_back_to(&mut self, names: &[&str]) {
+        while !self.current_is(names) && self.open_elements.len() > 1 {
+            self.pop();
+        }
+    }
+
+    fn clear_stack_back_to_table(&mut self) {
+        self.clear_stack_back_to(&["table", "template", "html"]);
+    }
+
+    fn clear_stack_back_to_table_body(&mut self) {
+        self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template", "html"]);
+    }
+
+    fn clear_stack_back_to_table_row(&mut self) {
+        self.clear_stack_back_to(&["tr", "template", "html"]);
+    }
+
+    fn in_table(&mut self, token: Token) -> Step {
+        match token {
+            Token::Character(_)
+                if self.current_is(&["table", "tbody", "template", "tfoot", "thead", "tr"]) =>
+            {
+                self.pending_table_text.clear();
+                self.original_mode = self.mode;
+                self.mode = InsertionMode::InTableText;
+                Step::Reprocess(token)
+            }
+            Token::StartTag(tag) => match tag.name.as_str() {
+                "caption" => {
+                    self.clear_stack_back_to_table();
+                    self.active_formatting.push(Formatting::Marker);
+                    self.insert_element(tag);
+                    self.mode = InsertionMode::InCaption;
+                    Step::Done
+                }
+                "colgroup" => {
+                    self.clear_stack_back_to_table();
+                    self.insert_element(tag);
+                    self.mode = InsertionMode::InColumnGroup;
+                    Step::Done
+                }
+                "col" => {
+                    self.clear_stack_back_to_table();
+                    self.insert_element(Tag::new("colgroup"));
+                    self.mode = InsertionMode::InColumnGroup;
+                    Step::Reprocess(Token::StartTag(tag))
+                }
+                "tbody" | "tfoot" | "thead" => {
+                    self.clear_stack_back_to_table();
+                    self.insert_element(tag);
+                    self.mode = InsertionMode::InTableBody;
+                    Step::Done
+                }
+                "td" | "th" | "tr" => {
+                    self.clear_stack_back_to_table();
+                    self.insert_element(Tag::new("tbody"));
+                    self.mode = InsertionMode::InTableBody;
+                    Step::Reprocess(Token::StartTag(tag))
+                }
+                "table" => {
+                    if !self.in_scope_of(&["table"], Scope::Table) {
+                        return Step::Done;
+                    }
+                    self.pop_until(&["table"]);
+                    self.reset_insertion_mode();
+                    Step::Reprocess(Token::StartTag(tag))
+                }
+                "style" | "script" => self.in_head(Token::StartTag(tag)),
+                "input"
+                    if tag
+                        .attr("type")
+                        .is_some_and(|t| t.eq_ignore_ascii_case("hidden")) =>
+                {
+                    self.insert_void_element(tag);
+                    Step::Done
+                }
+                "form" => {
+                    if self.form.is_none() {
+                        self.form = Some(self.insert_element(tag));
+                        self.pop();
+                    }
+                    Step::Done
+                }
+                _ => self.foster_parent(Token::StartTag(tag)),
+            },
+            Token::EndTag(tag) => match tag.name.as_str() {
+                "table" => {
+                    if self.in_scope_of(&["table"], Scope::Table) {
+                        self.pop_until(&["table"]);
+                        self.reset_insertion_mode();
+                    }
+                    Step::Done
+                }
+                "body" | "caption" | "col" | "colgroup" | "html" | "tbody" | "td" | "tfoot"
+                | "th" | "thead" | "tr" => Step::Done,
+                _ => self.foster_parent(Token::EndTag(tag)),
+            },
+            Token::Eof => self.in_body(Token::Eof),
+            token => self.foster_parent(token),
+        }
+    }
+
+    fn foster_parent(&mut self, token: Token) -> Step {
+        if let Token::StartTag(tag) | Token::EndTag(tag) = &token {
+            self.error(format!("foster-parented-tag <{}>", tag.name));
+        }
+        self.foster_parenting = true;
+        let step = self.in_body(token);
+        self.foster_parenting = false;
+        step
+    }
+
+    fn in_table_text(&mut self, token: Token) -> Step {
+        match token {
+            Token::Character('\0') => Step::Done,
+            Token::Character(c) => {
+                if self.pending_table_text.is_empty() {
+                    self.pending_table_span = self.span;
+                }
+                self.pending_table_text.push(c);
+                self.pending_table_span.end = self.span.end;
+                Step::Done
+            }
+            token => {
+                // Flush the text as if it had been processed when it was read
+                let span = std::mem::replace(&mut self.span, self.pending_table_span);
+                let pending = std::mem::take(&mut self.pending_table_text);
+                if pending.chars().all(is_whitespace) {
+                    pending.chars().for_each(|c| self.insert_character(c));
+                } else {
+                    self.error("foster-parented-text".to_string());
+                    for c in pending.chars() {
+                        self.foster_parent(Token::Character(c));
+                    }
+                }
+                self.span = span;
+                self.mode = self.original_mode;
+                Step::Reprocess(token)
+            }
+        }
+    }
+
+    fn close_caption(&mut self) -> bool {
+        if !self.in_scope_of(&["caption"], Scope::Table) {
+            return false;
+        }
+        self.generate_implied_end_tags(None);
+        self.pop_until(&["caption"]);
+        self.clear_formatting_to_marker();
+        self.mode = InsertionMode::InTable;
+        true
+    }
+
+    fn in_caption(&mut self, token: Token) -> Step {
+        match token {
+            Token::EndTag(tag) if tag.name == "caption" => {
+                self.close_caption();
+                Step::Done
+            }
+            Token::StartTag(tag) if TABLE_TAGS.contains(&tag.name.as_str()) => {
+                match self.close_caption() {
+                    true => Step::Reprocess(Token::StartTag(tag)),
+                    false => Step::Done,
+                }
+            }
+            Token::EndTag(tag) if tag.name == "table" => match self.close_caption() {
+                true => Step::Reprocess(Token::EndTag(tag)),
+                false => Step::Done,
+            },
+            Token::EndTag(tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "body"
+                        | "col"
+                        | "colgroup"
+                        | "html"
+                        | "tbody"
+                        | "td"
+                        | "tfoot"
+                        | "th"
+                        | "thead"
+                        | "tr"
+                ) =>
+            {
+                Step::Done
+            }
+            token => self.in_body(token),
+        }
+    }
+
+    fn in_column_group(&mut self, token: Token) -> Step {
+        match token {
+            Token::Character(c) if is_whitespace(c) => {
+                self.insert_character(c);
+                Step::Done
+            }
+            Token::StartTag(tag) if tag.name == "html" => self.in_body(Token::StartTag(tag)),
+            Token::StartTag(tag) if tag.name == "col" => {
+                self.insert_void_element(tag);
+                Step::Done
+            }
+            Token::EndTag(tag) if tag.name == "colgroup" => {
+                if self.current_is(&["colgroup"]) {
+                    self.pop();
+                    self.mode = InsertionMode::InTable;
+                }
+                Step::Done
+            }
+            Token::EndTag(tag) if tag.name == "col" => Step::Done,
+            Token::Eof => self.in_body(Token::Eof),
+            token => {
+                if !self.current_is(&["colgroup"]) {
+                    return Step::Done;
+                }
+                self.pop();
+                self.mode = InsertionMode::InTable;
+                Step::Reprocess(token)
+            }
+        }
+    }
+
+    fn close_table_section(&mut self) -> bool {
+        if !self.in_scope_of(&TABLE_SECTION_TAGS, Scope::Table) {
+            return false;
+        }
+        self.clear_stack_back_to_table_body();
+        self.pop();
+        self.mode = InsertionMode::InTable;
+        true
+    }
+
+    fn in_table_body(&mut self, token: Token) -> Step {
+        match token {
+            Token::StartTag(tag) if tag.name == "tr" => {
+                self.clear_stack_back_to_table_body();
+                self.insert_element(tag);
+                self.mode = InsertionMode::InRow;
+                Step::Done
+            }
+            Token::StartTag(tag) if matches!(tag.name.as_str(), "th" | "td") => {
+                self.clear_stack_back_to_table_body();
+                self.insert_element(Tag::new("tr"));
+                self.mode = InsertionMode::InRow;
+                Step::Reprocess(Token::StartTag(tag))
+            }
+            Token::EndTag(tag) if TABLE_SECTION_TAGS.contains(&tag.name.as_str()) => {
+                if self.in_scope_of(&[&tag.name], Scope::Table) {
+                    self.close_table_section();
+                }
+                Step::Done
+            }
+            Token::StartTag(tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "caption" | "col" | "colgroup" | "tbody" | "tfoot" | "thead"
+                ) =>
+            {
+                match self.close_table_section() {
+                    true => Step::Reprocess(Token::StartTag(tag)),
+                    false => Step::Done,
+                }
+            }
+            Token::EndTag(tag) if tag.name == "table" => match self.close_table_section() {
+                true => Step::Reprocess(Token::EndTag(tag)),
+                false => Step::Done,
+            },
+            Token::EndTag(tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "body" | "caption" | "col" | "colgroup" | "html" | "td" | "th" | "tr"
+                ) =>
+            {
+                Step::Done
+            }
+            token => self.in_table(token),
+        }
+    }
+
+    fn close_row(&mut self) -> bool {
+        if !self.in_scope_of(&["tr"], Scope::Table) {
+            return false;
+        }
+        self.clear_stack_back_to_table_row();
+        self.pop();
+        self.mode = InsertionMode::InTableBody;
+        true
+    }
+
+    fn in_row(&mut self, token: Token) -> Step {
+        match token {
+            Token::StartTag(tag) if matches!(tag.name.as_str(), "th" | "td") => {
+                self.clear_stack_back_to_table_row();
+                self.insert_element(tag);
+                self.mode = InsertionMode::InCell;
+                self.active_formatting.push(Formatting::Marker);
+                Step::Done
+            }
+            Token::EndTag(tag) if tag.name == "tr" => {
+                self.close_row();
+                Step::Done
+            }
+            Token::StartTag(tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "caption" | "col" | "colgroup" | "tbody" | "tfoot" | "thead" | "tr"
+                ) =>
+            {
+                match self.close_row() {
+                    true => Step::Reprocess(Token::StartTag(tag)),
+                    false => Step::Done,
+                }
+            }
+            Token::EndTag(tag) if tag.name == "table" => match self.close_row() {
+                true => Step::Reprocess(Token::EndTag(tag)),
+                false => Step::Done,
+            },
+            Token::EndTag(tag) if TABLE_SECTION_TAGS.contains(&tag.name.as_str()) => {
+                if self.in_scope_of(&[&tag.name], Scope::Table) && self.close_row() {
+                    return Step::Reprocess(Token::EndTag(tag));
+                }
+                Step::Done
+            }
+            Token::EndTag(tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "body" | "caption" | "col" | "colgroup" | "html" | "td" | "th"
+                ) =>
+            {
+                Step::Done
+            }
+            token => self.in_table(token),
+        }
+    }
+
+    fn close_cell(&mut self) {
+        self.generate_implied_end_tags(None);
+        self.pop_until(&["td", "th"]);
+        self.clear_formatting_to_marker();
+        self.mode = InsertionMode::InRow;
+    }
+
+    fn in_cell(&mut self, token: Token) -> Step {
+        match token {
+            Token::EndTag(tag) if matches!(tag.name.as_str(), "td" | "th") => {
+                if self.in_scope_of(&[&tag.name], Scope::Table) {
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(&[&tag.name]);
+                    self.clear_formatting_to_marker();
+                    self.mode = InsertionMode::InRow;
+                }
+                Step::Done
+            }
+            Token::StartTag(tag) if TABLE_TAGS.contains(&tag.name.as_str()) => {
+                if !self.in_scope_of(&["td", "th"], Scope::Table) {
+                    return Step::Done;
+                }
+                self.close_cell();
+                Step::Reprocess(Token::StartTag(tag))
+            }
+            Token::EndTag(tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "body" | "caption" | "col" | "colgroup" | "html"
+                ) =>
+            {
+                Step::Done
+            }
+            Token::EndTag(tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "table" | "tbody" | "tfoot" | "thead" | "tr"
+                ) =>
+            {
+                if !self.in_scope_of(&[&tag.name], Scope::Table) {
+                    return Step::Done;
+                }
+                self.close_cell();
+                Step::Reprocess(Token::EndTag(tag))
+            }
+            token => self.in_body(token),
+        }
+    }
+
+    fn close_select(&mut self) -> bool {
+        if !self.in_scope_of(&["select"], Scope::Select) {
+            return false;
+        }
+        self.pop_until(&["select"]);
+        self.reset_insertion_mode();
+        true
+    }
+
+    fn in_select(&mut self, token: Token) -> Step {
+        match token {
+            Token::Character('\0') => Step::Done,
+            Token::Character(c) => {
+                self.insert_character(c);
+                Step::Done
+            }
+            Token::StartTag(tag) => match tag.name.as_str() {
+                "html" => self.in_body(Token::StartTag(tag)),
+                "option" => {
+                    if self.current_is(&["option"]) {
+                        self.pop();
+                    }
+                    self.insert_element(tag);
+                    Step::Done
+                }
+                "optgroup" | "hr" => {
+                    if self.current_is(&["option"]) {
+                        self.pop();
+                    }
+                    if self.current_is(&["optgroup"]) {
+                        self.pop();
+                    }
+                    match tag.name == "hr" {
+                        true => self.insert_void_element(tag),
+                        false => {
+                            self.insert_element(tag);
+                        }
+                    }
+                    Step::Done
+                }
+                "select" => {
+                    self.close_select();
+                    Step::Done
+                }
+                "input" | "keygen" | "textarea" => match self.close_select() {
+                    true => Step::Reprocess(Token::StartTag(tag)),
+                    false => Step::Done,
+                },
+                "script" => self.in_head(Token::StartTag(tag)),
+                _ => Step::Done,
+            },
+            Token::EndTag(tag) => match tag.name.as_str() {
+                "optgroup" => {
+                    let len = self.open_elements.len();
+                    if self.current_is(&["option"])
+                        && len > 1
+                        && self.is(self.open_elements[len - 2], &["optgroup"])
+                    {
+                        self.pop();
+                    }
+                    if self.current_is(&["optgroup"]) {
+                        self.pop();
+                    }
+                    Step::Done
+                }
+                "option" => {
+                    if self.current_is(&["option"]) {
+                        self.pop();
+                    }
+                    Step::Done
+                }
+                "select" => {
+                    self.close_select();
+                    Step::Done
+                }
+                _ => Step::Done,
+            },
+            Token::Eof => self.in_body(Token::Eof),
+            Token::Comment(_) | Token::Doctype(_) => Step::Done,
+        }
+    }
+
+    fn in_select_in_table(&mut self, token: Token) -> Step {
+        static TABLE_CONTEXT: [&str; 8] = [
+            "caption", "table", "tbody", "tfoot", "thead", "tr", "td", "th",
+        ];
+        match token {
+            Token::StartTag(tag) if TABLE_CONTEXT.contains(&tag.name.as_str()) => {
+                self.pop_until(&["select"]);
+                self.reset_insertion_mode();
+                Step::Reprocess(Token::StartTag(tag))
+            }
+            Token::EndTag(tag) if TABLE_CONTEXT.contains(&tag.name.as_str()) => {
+                if !self.in_scope_of(&[&tag.name], Scope::Table) {
+                    return Step::Done;
+                }
+                self.pop_until(&["select"]);
+                self.reset_insertion_mode();
+                Step::Reprocess(Token::EndTag(tag))
+            }
+            token => self.in_select(token),
+        }
+    }
+
+    fn after_body(&mut self, token: Token) -> Step {
+        match token {
+            Token::Character(c) if is_whitespace(c) => self.in_body(token),
+            Token::StartTag(tag) if tag.name == "html" => self.in_body(Token::StartTag(tag)),
+            Token::EndTag(tag) if tag.name == "html" => {
+                self.mode = InsertionMode::AfterAfterBody;
+                Step::Done
+            }
+            Token::Eof => Step::Done,
+            token => {
+                self.mode = InsertionMode::InBody;
+                Step::Reprocess(token)
+            }
+        }
+    }
+
+    fn after_after_body(&mut self, token: Token) -> Step {
+        match token {
+            Token::Character(c) if is_whitespace(c) => self.in_body(token),
+            Token::StartTag(tag) if tag.name == "html" => self.in_body(Token::StartTag(tag)),
+            Token::Eof => Step::Done,
+            token => {
+                self.mode = InsertionMode::InBody;
+                Step::Reprocess(token)
+            }
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use std::ptr;
+
+use reqwest::Url;
+
+use crate::engine::{ElementData, Node, NodeType};
+
+/// How many characters a text field is drawn wide when the page doesn't
+/// give its `size`.
+const FIELD_SIZE: usize = 20;
+
+fn element(node: &Node) -> Option<&ElementData> {
+    match &node.node_type {
+        NodeType::Element(data) => Some(data),
+        _ => None,
+    }
+}
+
+/// The `type` of an `<input>`, which is a text field unless it says
+/// otherwise.
+fn input_type(data: &ElementData) -> String {
+    data.attrs
+        .get("type")
+        .map_or_else(|| "text".to_string(), |kind| kind.to_ascii_lowercase())
+}
+
+/// The text under `node`.
+fn text_content(node: &Node) -> String {
+    fn collect(node: &Node, text: &mut String) {
+        match &node.node_type {
+            NodeType::Text(data) => text.push_str(data),
+            _ => node.children.iter().for_each(|child| collect(child, text)),
+        }
+    }
+    let mut text = String::new();
+    collect(node, &mut text);
+    text
+}
+
+/// Whether `node` is a form control, to number along with the links.
+pub(super) fn is_control(node: &Node) -> bool {
+    element(node).is_some_and(|data| match data.tag.as_str() {
+        "input" => input_type(data) != "hidden",
+        "button" | "select" | "textarea" => true,
+        _ => false,
+    })
+}
+
+/// What `node` draws for itself if it's an `<input>`, which has no content
+/// to show: a field holding its value, a box to tick, or a button. Other
+/// controls show their content instead.
+pub(super) fn control_label(node: &Node) -> String {
+    let Some(data) = element(node).filter(|data| data.tag == "input") else {
+        return String::new();
+    };
+    let value = data.attrs.get("value").map(String::as_str);
+    let checked = data.attrs.contains_key("checked");
+    match input_type(data).as_str() {
+        "checkbox" => (if checked { "[x]" } else { "[ ]" }).to_string(),
+        "radio" => (if checked { "(*)" } else { "( )" }).to_string(),
+        "submit" => format!("[{}]", value.unwrap_or("Submit")),
+        "image" => format!(
+            "[{}]",
+            data.attrs.get("alt").map_or("Submit", String::as_str)
+        ),
+        "reset" => format!("[{}]", value.unwrap_or("Reset")),
+        "button" => format!("[{}]", value.unwrap_or_default()),
+        "file" => "[Browse…]".to_string(),
+        kind => {
+            let size = data
+                .attrs
+                .get("size")
+                .and_then(|size| size.trim().parse().ok())
+                .filter(|&size| size > 0)
+                .unwrap_or(FIELD_SIZE);
+            let value = value.unwrap_or_default();
+            let value = match kind {
+                "password" => "*".repeat(value.chars().count()),
+                _ => value.replace(|c: char| c.is_control(), " "),
+            };
+            format!("[{value:_<size$}]")
+        }
+    }
+}
+
+/// Where pressing `submitter` goes, if it's a submit button in `form` and
+/// the form is sent with GET: the action of the form with the values the
+/// page filled its controls in with as the query. Forms sent with POST
+/// can't be submitted yet.
+pub(super) fn submission(submitter: &Node, form: Option<&Node>) -> Option<String> {
+    let data = element(submitter)?;
+    let submits = match data.tag.as_str() {
+        "button" => !data.attrs.get("type").is_some_and(|kind| {
+            kind.eq_ignore_ascii_case("button") || kind.eq_ignore_ascii_case("reset")
+        }),
+        "input" => matches!(input_type(data).as_str(), "submit" | "image"),
+        _ => false,
+    };
+    let form = form.filter(|_| submits)?;
+    let form_data = element(form)?;
+    // The button can stand in for the attributes of the form
+    let attribute = |name: &str| {
+        data.attrs
+            .get(&format!("form{name}"))
+            .or_else(|| form_data.attrs.get(name))
+    };
+    // Methods other than these are taken as GET
+    let method = attribute("method");
+    if method.is_some_and(|method| {
+        method.eq_ignore_ascii_case("post") || method.eq_ignore_ascii_case("dialog")
+    }) {
+        return None;
+    }
+    let mut url = Url::parse(attribute("action")?).ok()?;
+    let mut entries = Vec::new();
+    collect_entries(form, submitter, &mut entries);
+    url.query_pairs_mut().clear().extend_pairs(entries);
+    Some(url.into())
+}
+
+/// Adds the names and values the controls under `node` submit with to
+/// `entries`. Of the buttons, only the `submitter` is sent.
+fn collect_entries(node: &Node, submitter: &Node, entries: &mut Vec<(String, String)>) {
+    for child in &node.children {
+        let Some(data) = element(child) else {
+            continue;
+        };
+        let name = data.attrs.get("name").filter(|name| !name.is_empty());
+        let (Some(name), false) = (name, data.attrs.contains_key("disabled")) else {
+            collect_entries(child, submitter, entries);
+            continue;
+        };
+        let value = data.attrs.get("value").cloned().unwrap_or_default();
+        let is_submitter = ptr::eq(child, submitter);
+        match data.tag.as_str() {
+            "input" => match input_type(data).as_str() {
+                "checkbox" | "radio" if data.attrs.contains_key("checked") => {
+                    let value = data.attrs.get("value").map_or("on", String::as_str);
+                    entries.push((name.clone(), value.to_string()));
+                }
+                "submit" if is_submitter => entries.push((name.clone(), value)),
+                "image" if is_submitter => {
+                    entries.push((format!("{name}.x"), "0".to_string()));
+                    entries.push((format!("{name}.y"), "0".to_string()));
+                }
+                "checkbox" | "radio" | "submit" | "image" | "reset" | "button" | "file" => {}
+                _ => entries.push((name.clone(), value)),
+            },
+            "button" if is_submitter => entries.push((name.clone(), value)),
+            "select" => {
+                let mut options = Vec::new();
+                collect_options(child, &mut options);
+                let selected: Vec<&Node> = options
+                    .iter()
+                    .copied()
+                    .filter(|option| {
+                        element(option).is_some_and(|data| data.attrs.contains_key("selected"))
+                    })
+                    .collect();
+                // Lists showing one option at a time always have one picked
+                let picked = match (data.attrs.contains_key("multiple"), selected.first()) {
+                    (true, _) => selected,
+                    (false, Some(&option)) => vec![option],
+                    (false, None) => options.into_iter().take(1).collect(),
+                };
+                for option in picked {
+                    let value = match element(option).and_then(|data| data.attrs.get("value")) {
+                        Some(value) => value.clone(),
+                        None => text_content(option)
+                            .split_ascii_whitespace()
+                            .collect::<Vec<_>>()
+                            .join(" "),
+                    };
+                    entries.push((name.clone(), value));
+                }
+            }
+            "textarea" => entries.push((name.clone(), text_content(child))),
+            _ => collect_entries(child, submitter, entries),
+        }
+    }
+}
+
+/// Adds the `<option>`s under `node`, including those in groups, to
+/// `options`.
+fn collect_options<'a>(node: &'a Node, options: &mut Vec<&'a Node>) {
+    for child in &node.children {
+        match element(child).map(|data| data.tag.as_str()) {
+            Some("option") => options.push(child),
+            Some(_) => collect_options(child, options),
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::layout;
+
+    #[test]
+    fn draws_and_numbers_controls() {
+        let page = layout(
+            "<p><a href=/a>a</a> <input value=cats size=6> <input type=password value=abc size=3>
+             <input type=checkbox checked> <input type=radio> <input type=hidden value=no>
+             <button>Go</button> <select><option>one</select> <textarea>hi</textarea>",
+            "",
+            80,
+        );
+        assert_eq!(
+            page.text(),
+            "[1]a [2][cats__] [3][***] [4][x] [5]( ) [6]Go [7]one [8]hi"
+        );
+        assert_eq!(page.links.len(), 8);
+        assert!(page.links[1..].iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn submits_forms_sent_with_get() {
+        let page = layout(
+            "<form action='https://example.com/find#results'>
+               <input name=q value='two words'> <input name=empty>
+               <input type=checkbox name=safe checked> <input type=checkbox name=off>
+               <input type=radio name=size value=big checked> <input type=hidden name=page value=2>
+               <input name=gone value=x disabled>
+               <fieldset><select name=sort><option value=new>New<option selected>Top rated</select></fieldset>
+               <select name=none multiple><option>a</select> <select name=first><option>a<option>b</select>
+               <textarea name=note>  a  note </textarea>
+               <button name=go value=1>Go</button> <input type=submit name=other value=Other>
+               <button type=button>Nothing</button>
+               <button formaction='https://example.com/else' formmethod=GET>Else</button>
+             </form>
+             <form method=post action='https://example.com/post'><button>Post</button></form>
+             <button>Formless</button>",
+            "",
+            80,
+        );
+        let links: Vec<Option<&str>> = page.links.iter().map(Option::as_deref).collect();
+        let fields =
+            "q=two+words&empty=&safe=on&size=big&page=2&sort=Top+rated&first=a&note=++a++note+";
+        assert_eq!(
+            links[10..],
+            [
+                Some(format!("https://example.com/find?{fields}&go=1#results").as_str()),
+                Some(format!("https://example.com/find?{fields}&other=Other#results").as_str()),
+                None,
+                Some(format!("https://example.com/else?{fields}").as_str()),
+                None,
+                None,
+            ]
+        );
+    }
+}
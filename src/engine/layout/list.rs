@@ -0,0 +1,225 @@
+use super::{attribute, LayoutBox, Marker};
+use crate::engine::css::Value;
+use crate::engine::Display;
+
+const LOWER_LATIN: [char; 26] = [
+    'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's',
+    't', 'u', 'v', 'w', 'x', 'y', 'z',
+];
+
+const LOWER_GREEK: [char; 24] = [
+    'α', 'β', 'γ', 'δ', 'ε', 'ζ', 'η', 'θ', 'ι', 'κ', 'λ', 'μ', 'ν', 'ξ', 'ο', 'π', 'ρ', 'σ', 'τ',
+    'υ', 'φ', 'χ', 'ψ', 'ω',
+];
+
+const ROMAN: [(i64, &str); 13] = [
+    (1000, "M"),
+    (900, "CM"),
+    (500, "D"),
+    (400, "CD"),
+    (100, "C"),
+    (90, "XC"),
+    (50, "L"),
+    (40, "XL"),
+    (10, "X"),
+    (9, "IX"),
+    (5, "V"),
+    (4, "IV"),
+    (1, "I"),
+];
+
+/// Counts with letters, going on to two letters after the last one like
+/// spreadsheet columns do. Only positive numbers have letters.
+fn alphabetic(mut number: i64, letters: &[char]) -> Option<String> {
+    if number < 1 {
+        return None;
+    }
+    let base = letters.len() as i64;
+    let mut out = Vec::new();
+    while number > 0 {
+        number -= 1;
+        out.push(letters[(number % base) as usize]);
+        number /= base;
+    }
+    Some(out.iter().rev().collect())
+}
+
+fn roman(mut number: i64) -> Option<String> {
+    if !(1..4000).contains(&number) {
+        return None;
+    }
+    let mut out = String::new();
+    for (value, numeral) in ROMAN {
+        while number >= value {
+            out.push_str(numeral);
+            number -= value;
+        }
+    }
+    Some(out)
+}
+
+/// The marker of the list item numbered `number`, for its
+/// `list-style-type`. Counter styles that aren't known, or that don't go
+/// as far as the number, fall back to decimal numbers as the spec asks.
+fn marker_text(style_type: Option<&[Value]>, number: i64) -> Option<String> {
+    let style_type = match style_type {
+        Some([Value::String(text)]) => return Some(text.clone()),
+        Some([Value::Keyword(style_type)]) => style_type.as_str(),
+        _ => "disc",
+    };
+    let counter = match style_type {
+        "none" => return None,
+        "disc" => return Some("•".to_string()),
+        "circle" => return Some("◦".to_string()),
+        "square" => return Some("▪".to_string()),
+        "disclosure-open" => return Some("▾".to_string()),
+        "disclosure-closed" => return Some("▸".to_string()),
+        "decimal-leading-zero" if number >= 0 => Some(format!("{number:02}")),
+        "lower-alpha" | "lower-latin" => alphabetic(number, &LOWER_LATIN),
+        "upper-alpha" | "upper-latin" => {
+            alphabetic(number, &LOWER_LATIN).map(|letters| letters.to_uppercase())
+        }
+        "lower-greek" => alphabetic(number, &LOWER_GREEK),
+        "lower-roman" => roman(number).map(|numerals| numerals.to_lowercase()),
+        "upper-roman" => roman(number),
+        _ => None,
+    };
+    Some(format!(
+        "{}.",
+        counter.unwrap_or_else(|| number.to_string())
+    ))
+}
+
+impl LayoutBox<'_> {
+    /// Gives the list items among the children their markers, numbering
+    /// them up from the `start` of the list, or down to one if it's
+    /// `reversed`. Items with a `value` carry on counting from it.
+    pub(super) fn mark_list_items(&mut self) {
+        let Some(node) = self.node() else {
+            return;
+        };
+        let is_item = |child: &LayoutBox| {
+            child
+                .node()
+                .is_some_and(|node| node.display() == Display::ListItem)
+        };
+        let count = self.children.iter().filter(|child| is_item(child)).count();
+        // Numbers are kept to the range browsers count in
+        let parse = |value: &str| {
+            let number = value.trim().parse::<i64>().ok()?;
+            Some(number.clamp(i32::MIN.into(), i32::MAX.into()) as i32)
+        };
+        let start = attribute(node, "start").and_then(parse);
+        let (mut number, step) = match attribute(node, "reversed") {
+            Some(_) => (start.unwrap_or(count.try_into().unwrap_or(i32::MAX)), -1),
+            None => (start.unwrap_or(1), 1),
+        };
+
+        for child in self.children.iter_mut().filter(|child| is_item(child)) {
+            let Some(item) = child.node() else {
+                continue;
+            };
+            if let Some(value) = attribute(item, "value").and_then(parse) {
+                number = value;
+            }
+            if let Some(text) = marker_text(item.value("list-style-type"), number.into()) {
+                let inside = item.keyword("list-style-position") == Some("inside");
+                child.set_marker(Marker { text, inside });
+            }
+            number = number.saturating_add(step);
+        }
+    }
+
+    pub(super) fn set_marker(&mut self, marker: Marker) {
+        // Markers inside an item go at the start of the text in it
+        if marker.inside {
+            if let Some(first) = self.children.first_mut().filter(|child| child.is_block()) {
+                return first.set_marker(marker);
+            }
+        }
+        self.marker = Some(marker);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::layout;
+    use super::marker_text;
+    use crate::engine::css::Value;
+
+    #[test]
+    fn counts_in_each_style() {
+        let marker =
+            |style: &str, number| marker_text(Some(&[Value::Keyword(style.to_string())]), number);
+        assert_eq!(marker("decimal", 7).as_deref(), Some("7."));
+        assert_eq!(marker("decimal-leading-zero", 7).as_deref(), Some("07."));
+        assert_eq!(marker("lower-alpha", 28).as_deref(), Some("ab."));
+        assert_eq!(marker("upper-latin", 26).as_deref(), Some("Z."));
+        assert_eq!(marker("lower-greek", 2).as_deref(), Some("β."));
+        assert_eq!(marker("upper-roman", 1994).as_deref(), Some("MCMXCIV."));
+        assert_eq!(marker("lower-roman", 4).as_deref(), Some("iv."));
+        assert_eq!(marker("lower-alpha", 0).as_deref(), Some("0."));
+        assert_eq!(marker("hebrew", 3).as_deref(), Some("3."));
+        assert_eq!(marker("square", 3).as_deref(), Some("▪"));
+        assert_eq!(marker("none", 3), None);
+        assert_eq!(
+            marker_text(Some(&[Value::String("-".to_string())]), 1).as_deref(),
+            Some("-")
+        );
+    }
+
+    #[test]
+    fn changes_bullets_with_depth() {
+        let page = layout("<ul><li>a<ul><li>b<ul><li>c</ul></ul><li>d</ul>", "", 40);
+        assert_eq!(page.text(), "   • a\n        ◦ b\n             ▪ c\n   • d");
+    }
+
+    #[test]
+    fn numbers_items() {
+        let page = layout(
+            "<ol start=9><li>a<li value=3>b<li>c</ol>
+             <ol reversed><li>x<li>y</ol>
+             <ol type=a><li>p<li>q</ol>
+             <ol type=I start=4><li>r</ol>",
+            "",
+            40,
+        );
+        assert_eq!(
+            page.text(),
+            "  9. a\n  3. b\n  4. c\n\n  2. x\n  1. y\n\n  a. p\n  b. q\n\n IV. r"
+        );
+    }
+
+    #[test]
+    fn keeps_numbers_in_range() {
+        let page = layout(
+            "<ol start=9223372036854775807><li>a<li>b</ol>
+             <ol reversed start=-99999999999><li value=-4294967296>c<li>d</ol>",
+            "ol { list-style-position: inside; padding-left: 0 }",
+            40,
+        );
+        assert_eq!(
+            page.text(),
+            "2147483647. a\n2147483647. b\n\n-2147483648. c\n-2147483648. d"
+        );
+    }
+
+    #[test]
+    fn follows_list_style() {
+        let page = layout(
+            "<ul class=inside><li><p>a</ul><ul class=plain><li>b</ul><ul><li>c</ul>",
+            ".inside { list-style: square inside }
+             .plain { list-style: none }
+             ul { padding-left: 0 }",
+            40,
+        );
+        // The last bullet has no room left of the item
+        assert_eq!(page.text(), "▪ a\n\nb\n\nc");
+    }
+
+    #[test]
+    fn lays_out_definition_lists() {
+        let page = layout("<dl><dt>Term<dd>Meaning<dt>Other<dd>More</dl>", "", 40);
+        assert_eq!(page.text(), "Term\n     Meaning\nOther\n     More");
+    }
+}
@@ -0,0 +1,1307 @@
+use std::{borrow::Cow, ops::Range};
+
+use unicode_width::UnicodeWidthChar;
+
+use super::css::{Media, Value, CELL_HEIGHT, CELL_WIDTH};
+use super::{Color, Display, Node, NodeType, StyledNode, TextStyle};
+
+mod form;
+mod list;
+mod table;
+
+/// The color line numbers are drawn in, which the palette makes readable
+/// against the background.
+const GUTTER_COLOR: Color = Color::rgb(128, 128, 128);
+
+/// The border styles that draw a line, rather than leaving it out.
+const LINE_STYLES: [&str; 8] = [
+    "solid", "dashed", "dotted", "double", "groove", "ridge", "inset", "outset",
+];
+
+/// A page laid out in terminal cells, as runs of styled text to draw.
+#[derive(Debug, Default)]
+pub struct Page {
+    /// How far the widest line reaches, in cells.
+    pub width: usize,
+    pub height: usize,
+    /// The color of the canvas, taken from the root element or the body.
+    pub background: Option<Color>,
+    /// Where each link and form control on the page goes, in the order
+    /// they are numbered: the `href` of a link, already resolved against
+    /// the base url of the page unless it couldn't be, or the url a submit
+    /// button sends its form to. Other controls go nowhere. Runs are part
+    /// of the link their style points to.
+    pub links: Vec<Option<String>>,
+    /// What to draw, line by line and in the order to draw it in, so later
+    /// runs go over earlier ones.
+    pub runs: Vec<Run>,
+    /// Where the runs of each line start in `runs`, so drawing what is in
+    /// view doesn't go through the whole page.
+    line_starts: Vec<usize>,
+}
+
+/// Text drawn in one style, starting at column `x` of line `y`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Run {
+    pub x: usize,
+    pub y: usize,
+    pub text: String,
+    pub style: TextStyle,
+}
+
+impl Run {
+    /// How many cells the text takes up.
+    pub fn width(&self) -> usize {
+        self.text.chars().map(char_width).sum()
+    }
+}
+
+impl Page {
+    /// The runs on `lines`, in the order to draw them in.
+    pub fn lines(&self, lines: Range<usize>) -> &[Run] {
+        let start = |line: usize| {
+            self.line_starts
+                .get(line)
+                .copied()
+                .unwrap_or(self.runs.len())
+        };
+        &self.runs[start(lines.start)..start(lines.end.max(lines.start))]
+    }
+
+    /// Puts the runs in order of line, keeping the order they are drawn in
+    /// on each line, and notes where each line starts.
+    fn index_lines(&mut self) {
+        self.runs.sort_by_key(|run| run.y);
+        if let Some(last) = self.runs.last() {
+            self.height = self.height.max(last.y + 1);
+        }
+        self.line_starts = (0..=self.height)
+            .map(|line| self.runs.partition_point(|run| run.y < line))
+            .collect();
+    }
+
+    /// The text of the page as drawn, without the spaces lines end in.
+    #[cfg(test)]
+    pub fn text(&self) -> String {
+        let mut lines = vec![Vec::new(); self.height];
+        for run in &self.runs {
+            if run.y >= lines.len() {
+                lines.resize(run.y + 1, Vec::new());
+            }
+            let line = &mut lines[run.y];
+            let mut x = run.x;
+            for c in run.text.chars() {
+                let width = char_width(c);
+                if width == 0 {
+                    continue;
+                }
+                if line.len() < x + width {
+                    line.resize(x + width, ' ');
+                }
+                line[x] = c;
+                // The cells a wide character covers are left out
+                line[x + 1..x + width].fill('\0');
+                x += width;
+            }
+        }
+        let lines: Vec<String> = lines
+            .iter()
+            .map(|line| {
+                let line: String = line.iter().filter(|c| **c != '\0').collect();
+                line.trim_end().to_string()
+            })
+            .collect();
+        lines.join("\n").trim_end().to_string()
+    }
+}
+
+fn char_width(c: char) -> usize {
+    c.width().unwrap_or(0)
+}
+
+/// A rectangle of cells.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// The sizes of the four sides of a margin or padding, in cells.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EdgeSizes {
+    pub top: usize,
+    pub right: usize,
+    pub bottom: usize,
+    pub left: usize,
+}
+
+/// Where a box goes. The content box is placed relative to the content box
+/// of the parent, so moving a box moves everything in it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Dimensions {
+    pub content: Rect,
+    pub padding: EdgeSizes,
+    pub margin: EdgeSizes,
+}
+
+#[derive(Debug)]
+pub enum BoxType<'a> {
+    Block(&'a StyledNode<'a>),
+    Inline(&'a StyledNode<'a>),
+    Table(&'a StyledNode<'a>),
+    /// A block made to hold the inline content between the blocks of its
+    /// parent, as blocks hold either blocks or inline content but not both.
+    Anonymous,
+}
+
+/// How the lines of a block line up.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug)]
+pub struct LayoutBox<'a> {
+    pub box_type: BoxType<'a>,
+    pub dimensions: Dimensions,
+    /// How the text in the box is drawn.
+    pub style: TextStyle,
+    /// The color the box is filled with, when it sets its own.
+    pub background: Option<Color>,
+    pub align: TextAlign,
+    pub children: Vec<LayoutBox<'a>>,
+    /// The lines of a block holding inline content, placed relative to its
+    /// content box.
+    pub runs: Vec<Run>,
+    /// The bullet or number of a list item, or the number of a link or form
+    /// control with what the control draws.
+    pub marker: Option<Marker>,
+    /// Whether the lines of the box are numbered down its left side, as
+    /// for code blocks.
+    pub gutter: bool,
+}
+
+/// The bullet or number of a list item, or the number of a link or form
+/// control. Markers go in the space left of the item, or when `inside`, at
+/// the start of its first line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Marker {
+    pub text: String,
+    pub inside: bool,
+}
+
+/// Lays out the page under `root` in the width of `media`. Content that
+/// isn't shown is left out unless `show_hidden` is set, which helps when
+/// debugging why something is missing. With `line_numbers`, the lines of
+/// preformatted blocks are numbered.
+pub fn layout_page(
+    root: &StyledNode,
+    media: &Media,
+    show_hidden: bool,
+    line_numbers: bool,
+) -> Page {
+    let Some(mut root) = build_layout_tree(root, &TextStyle::default(), show_hidden) else {
+        return Page::default();
+    };
+    if line_numbers {
+        root.number_lines();
+    }
+    let mut links = Vec::new();
+    root.number_links(&mut links, None);
+    let viewport = (media.width / CELL_WIDTH) as usize;
+    root.layout(viewport, media);
+
+    // The background of the root, or else the body, covers the whole canvas
+    let mut page = Page {
+        background: root.background.take(),
+        links,
+        ..Default::default()
+    };
+    if page.background.is_none() {
+        let body = root.children.iter_mut().find(|child| match child.box_type {
+            BoxType::Block(node) => is_element(node, "body"),
+            _ => false,
+        });
+        page.background = body.and_then(|body| body.background.take());
+    }
+
+    // The margins of the root are left out, as the terminal has its own
+    let dimensions = root.dimensions;
+    root.dimensions.content.x = dimensions.margin.left + dimensions.padding.left;
+    root.dimensions.content.y = dimensions.padding.top;
+    page.height = dimensions.content.height + dimensions.padding.top + dimensions.padding.bottom;
+    root.paint(0, 0, &mut page);
+    page.width = page
+        .runs
+        .iter()
+        .map(|run| run.x + run.width())
+        .max()
+        .unwrap_or(0);
+    page.index_lines();
+    page
+}
+
+/// Builds the boxes for `node` and what's under it, or `None` if it isn't
+/// shown.
+fn build_layout_tree<'a>(
+    node: &'a StyledNode<'a>,
+    parent: &TextStyle,
+    show_hidden: bool,
+) -> Option<LayoutBox<'a>> {
+    let box_type = match node.node.node_type {
+        NodeType::Element(_) if node.is_hidden() && !show_hidden => return None,
+        NodeType::Element(_) => match node.display() {
+            Display::Inline => BoxType::Inline(node),
+            Display::Table => BoxType::Table(node),
+            Display::TableColumn => return None,
+            // What would be left out is shown as blocks
+            Display::Block
+            | Display::ListItem
+            | Display::TableRowGroup
+            | Display::TableRow
+            | Display::TableCell
+            | Display::TableCaption
+            | Display::None => BoxType::Block(node),
+        },
+        NodeType::Text(_) if node.is_visible() || show_hidden => BoxType::Inline(node),
+        _ => return None,
+    };
+    let style = node.text_style(parent);
+    let align = match node.keyword("text-align") {
+        Some("center" | "-webkit-center") => TextAlign::Center,
+        Some("right" | "end" | "-webkit-right") => TextAlign::Right,
+        _ => TextAlign::Left,
+    };
+    let mut layout_box = LayoutBox {
+        box_type,
+        dimensions: Dimensions::default(),
+        style,
+        background: style
+            .background
+            .filter(|_| style.background != parent.background),
+        align,
+        children: Vec::new(),
+        runs: Vec::new(),
+        marker: None,
+        gutter: false,
+    };
+
+    let children: Vec<LayoutBox> = node
+        .children
+        .iter()
+        .filter_map(|child| build_layout_tree(child, &style, show_hidden))
+        .collect();
+    if !children.iter().any(LayoutBox::is_block) {
+        layout_box.children = children;
+        return Some(layout_box);
+    }
+
+    // Inline boxes holding blocks are laid out as blocks themselves
+    if let BoxType::Inline(node) = layout_box.box_type {
+        layout_box.box_type = BoxType::Block(node);
+    }
+    for child in children {
+        if child.is_block() {
+            layout_box.children.push(child);
+            continue;
+        }
+        match layout_box.children.last_mut() {
+            Some(last) if matches!(last.box_type, BoxType::Anonymous) => last.children.push(child),
+            _ => {
+                let mut anonymous = LayoutBox {
+                    box_type: BoxType::Anonymous,
+                    dimensions: Dimensions::default(),
+                    style,
+                    background: None,
+                    align,
+                    children: Vec::new(),
+                    runs: Vec::new(),
+                    marker: None,
+                    gutter: false,
+                };
+                anonymous.children.push(child);
+                layout_box.children.push(anonymous);
+            }
+        }
+    }
+    layout_box.mark_list_items();
+    Some(layout_box)
+}
+
+fn is_element(node: &StyledNode, tag: &str) -> bool {
+    matches!(&node.node.node_type, NodeType::Element(data) if data.tag == tag)
+}
+
+/// Whether the page asks for a line down the left side of `node`, like
+/// the bar beside a quote.
+fn has_left_border(node: &StyledNode) -> bool {
+    let is_line = |value: &Value| matches!(value, Value::Keyword(style) if LINE_STYLES.contains(&style.as_str()));
+    // The left side is the last of up to four sides, or the second of two
+    // or three
+    let left_style = match node.value("border-style") {
+        Some([left] | [_, left] | [_, left, _] | [_, _, _, left]) => is_line(left),
+        _ => false,
+    };
+    left_style
+        || ["border", "border-left", "border-left-style"]
+            .iter()
+            .filter_map(|name| node.value(name))
+            .flatten()
+            .any(is_line)
+}
+
+fn attribute<'a>(node: &'a StyledNode, name: &str) -> Option<&'a str> {
+    match &node.node.node_type {
+        NodeType::Element(data) => data.attrs.get(name).map(String::as_str),
+        _ => None,
+    }
+}
+
+/// Which way a length goes, as cells are twice as tall as they are wide.
+#[derive(Clone, Copy)]
+enum Axis {
+    Horizontal,
+    Vertical,
+}
+
+/// A length in whole cells, with percentages taken of the `containing`
+/// width. Vertical lengths under three quarters of a line round down, so
+/// the small gaps pages leave don't each take up a line. `None` is `auto`.
+fn cells(value: Option<&[Value]>, axis: Axis, containing: usize, media: &Media) -> Option<usize> {
+    let px = match value? {
+        [Value::Percentage(percentage)] => containing as f32 * CELL_WIDTH * percentage / 100.0,
+        [value] => value.to_px(media)?,
+        _ => return None,
+    };
+    let cells = match axis {
+        Axis::Horizontal => (px / CELL_WIDTH).round(),
+        Axis::Vertical => (px / CELL_HEIGHT + 0.25).floor(),
+    };
+    Some(cells.max(0.0) as usize)
+}
+
+impl<'a> LayoutBox<'a> {
+    fn is_block(&self) -> bool {
+        !matches!(self.box_type, BoxType::Inline(_))
+    }
+
+    fn node(&self) -> Option<&'a StyledNode<'a>> {
+        match self.box_type {
+            BoxType::Block(node) | BoxType::Inline(node) | BoxType::Table(node) => Some(node),
+            BoxType::Anonymous => None,
+        }
+    }
+
+    /// Lays out the box and what's in it in a containing block
+    /// `containing` cells wide. The box ends up at the top left of it, for
+    /// the parent to move down.
+    fn layout(&mut self, containing: usize, media: &Media) {
+        self.calculate_edges(containing, media);
+        self.calculate_width(containing, media);
+        self.layout_children(media);
+    }
+
+    /// Draws a thematic break as a line across the box.
+    fn layout_rule(&mut self) {
+        self.runs = vec![Run {
+            x: 0,
+            y: 0,
+            text: "─".repeat(self.dimensions.content.width),
+            style: self.style,
+        }];
+        self.dimensions.content.height = 1;
+    }
+
+    /// Gives the blocks that keep the lines of their text a gutter to
+    /// number them in.
+    fn number_lines(&mut self) {
+        let keeps_lines = |node: &StyledNode| {
+            let white_space = WhiteSpace::of(node);
+            white_space.keep_newlines && !white_space.collapse
+        };
+        let holds_text = !self.children.iter().any(LayoutBox::is_block);
+        match self.box_type {
+            BoxType::Block(node) if holds_text && keeps_lines(node) => self.gutter = true,
+            _ => self.children.iter_mut().for_each(LayoutBox::number_lines),
+        }
+    }
+
+    /// Numbers the links and form controls in and under the box in the
+    /// order they come in, adding where each goes to `links`. `form` is
+    /// the form the box is in, if any.
+    fn number_links(&mut self, links: &mut Vec<Option<String>>, form: Option<&'a Node>) {
+        let node = self.node();
+        let form = match node {
+            Some(node) if is_element(node, "form") => Some(node.node),
+            _ => form,
+        };
+        let target = node.and_then(|node| {
+            if is_element(node, "a") {
+                attribute(node, "href").map(|href| (Some(href.to_string()), String::new()))
+            } else if form::is_control(node.node) {
+                let url = form::submission(node.node, form);
+                Some((url, form::control_label(node.node)))
+            } else {
+                None
+            }
+        });
+        let Some((url, label)) = target else {
+            for child in &mut self.children {
+                child.number_links(links, form);
+            }
+            return;
+        };
+        let index = links.len();
+        links.push(url);
+        self.set_link(index);
+        self.set_marker(Marker {
+            text: format!("[{}]{label}", index + 1),
+            inside: true,
+        });
+    }
+
+    /// Makes the box and everything in it part of link `index`. Links in
+    /// links are part of the outer one, as they are when clicked.
+    fn set_link(&mut self, index: usize) {
+        self.style.link = Some(index);
+        for child in &mut self.children {
+            child.set_link(index);
+        }
+    }
+
+    /// Lays out what's in the box, once its width is known.
+    fn layout_children(&mut self, media: &Media) {
+        if let BoxType::Table(_) = self.box_type {
+            self.layout_table(media);
+        } else if self.node().is_some_and(|node| is_element(node, "hr")) {
+            self.layout_rule();
+        } else if self.children.iter().any(LayoutBox::is_block) {
+            self.layout_block_children(media);
+        } else {
+            self.layout_inline_children();
+        }
+    }
+
+    /// How narrow the box can get before its content overflows, and how
+    /// wide it is when nothing wraps, in cells and with its padding and
+    /// margins. Percentages are left out, as they are of a width that
+    /// isn't known yet.
+    fn content_widths(&self, media: &Media) -> (usize, usize) {
+        let (mut min, mut max) = if let BoxType::Table(_) = self.box_type {
+            self.table_widths(media)
+        } else if self.children.iter().any(LayoutBox::is_block) {
+            self.children
+                .iter()
+                .map(|child| child.content_widths(media))
+                .fold((0, 0), |(min, max), (child_min, child_max)| {
+                    (min.max(child_min), max.max(child_max))
+                })
+        } else {
+            let (min, max) = inline_widths(&self.inline_pieces());
+            let gutter = self.gutter_width();
+            (min + gutter, max + gutter)
+        };
+        let Some(node) = self.node() else {
+            return (min, max);
+        };
+
+        let length = |property: &str| cells(node.value(property), Axis::Horizontal, 0, media);
+        let padding = length("padding-left").unwrap_or(0) + length("padding-right").unwrap_or(0);
+        let is_percentage = matches!(node.value("width"), Some([Value::Percentage(_)]));
+        if let Some(width) = length("width").filter(|_| !is_percentage) {
+            let width = match node.keyword("box-sizing") {
+                Some("border-box") => width.saturating_sub(padding),
+                _ => width,
+            };
+            max = width.max(min);
+        }
+        let edges =
+            padding + length("margin-left").unwrap_or(0) + length("margin-right").unwrap_or(0);
+        min += edges;
+        max += edges;
+        (min, max)
+    }
+
+    fn calculate_edges(&mut self, containing: usize, media: &Media) {
+        let Some(node) = self.node() else {
+            return;
+        };
+        let side = |property: &str, axis| {
+            cells(node.value(property), axis, containing, media).unwrap_or(0)
+        };
+        let d = &mut self.dimensions;
+        d.padding = EdgeSizes {
+            top: side("padding-top", Axis::Vertical),
+            right: side("padding-right", Axis::Horizontal),
+            bottom: side("padding-bottom", Axis::Vertical),
+            left: side("padding-left", Axis::Horizontal),
+        };
+        d.margin.top = side("margin-top", Axis::Vertical);
+        d.margin.bottom = side("margin-bottom", Axis::Vertical);
+    }
+
+    /// Works out the width and the horizontal margins, after section 10.3.3
+    /// of CSS 2.
+    fn calculate_width(&mut self, containing: usize, media: &Media) {
+        let Some(node) = self.node() else {
+            self.dimensions.content.width = containing;
+            return;
+        };
+        let d = &mut self.dimensions;
+        let length =
+            |property: &str| cells(node.value(property), Axis::Horizontal, containing, media);
+        let padding = d.padding.left + d.padding.right;
+        let border_box = node.keyword("box-sizing") == Some("border-box");
+        let content_width = |width: usize| match border_box {
+            true => width.saturating_sub(padding),
+            false => width,
+        };
+
+        let mut width = length("width").map(content_width);
+        if let (Some(max), Some(current)) = (length("max-width").map(content_width), width) {
+            width = Some(current.min(max));
+        }
+        let margin_left = length("margin-left");
+        let margin_right = length("margin-right");
+
+        // The space left over goes to the margins that are `auto`
+        let (width, left, right) = match width {
+            None => {
+                let (left, right) = (margin_left.unwrap_or(0), margin_right.unwrap_or(0));
+                let mut width = containing.saturating_sub(left + right + padding);
+                // A maximum width narrower than the space leaves it to the
+                // `auto` margins, the way pages center their content
+                if let Some(max) = length("max-width").map(content_width) {
+                    width = width.min(max);
+                }
+                let width = width.max(length("min-width").map(content_width).unwrap_or(0));
+                let spare = containing.saturating_sub(width + left + right + padding);
+                match (margin_left, margin_right) {
+                    (None, None) if spare > 0 => (width, spare / 2, spare - spare / 2),
+                    (None, Some(right)) => (width, spare, right),
+                    (left, _) => (width, left.unwrap_or(0), spare + right),
+                }
+            }
+            Some(width) => {
+                let width = width.max(length("min-width").map(content_width).unwrap_or(0));
+                let spare = containing.saturating_sub(width + padding);
+                match (margin_left, margin_right) {
+                    (None, None) => (width, spare / 2, spare - spare / 2),
+                    (None, Some(right)) => (width, spare.saturating_sub(right), right),
+                    (Some(left), _) => (width, left, spare.saturating_sub(left)),
+                }
+            }
+        };
+        d.content.width = width;
+        d.margin.left = left;
+        d.margin.right = right;
+    }
+
+    /// Stacks the children one under the other. Margins that meet collapse
+    /// into the largest of them, including those of the first and last
+    /// children with the box's own when no padding is between them.
+    fn layout_block_children(&mut self, media: &Media) {
+        let d = &mut self.dimensions;
+        let mut cursor = 0;
+        let mut pending = 0;
+        let mut placed = false;
+        for child in &mut self.children {
+            child.layout(d.content.width, media);
+            let cd = &mut child.dimensions;
+            cd.content.x = cd.margin.left + cd.padding.left;
+
+            // Empty boxes let the margins around them collapse through
+            if cd.content.height + cd.padding.top + cd.padding.bottom == 0 {
+                pending = pending.max(cd.margin.top).max(cd.margin.bottom);
+                cd.content.y = cursor;
+                continue;
+            }
+            let margin = pending.max(cd.margin.top);
+            let margin = match placed || d.padding.top > 0 {
+                true => margin,
+                false => {
+                    d.margin.top = d.margin.top.max(margin);
+                    0
+                }
+            };
+            cd.content.y = cursor + margin + cd.padding.top;
+            cursor = cd.content.y + cd.content.height + cd.padding.bottom;
+            pending = cd.margin.bottom;
+            placed = true;
+        }
+        match d.padding.bottom > 0 && placed {
+            true => cursor += pending,
+            false => d.margin.bottom = d.margin.bottom.max(pending),
+        }
+        d.content.height = cursor;
+    }
+
+    /// How many cells the line numbers of the box take up, with the space
+    /// after them.
+    fn gutter_width(&self) -> usize {
+        if !self.gutter {
+            return 0;
+        }
+        let pieces = self.inline_pieces();
+        // A newline at the end doesn't start another line
+        let breaks = pieces
+            .iter()
+            .filter(|piece| matches!(piece, Piece::Break))
+            .count();
+        let lines = breaks + usize::from(!matches!(pieces.last(), Some(Piece::Break)));
+        lines.to_string().len() + 1
+    }
+
+    /// Breaks the inline content of the box into lines that fit its width.
+    fn layout_inline_children(&mut self) {
+        let pieces = self.inline_pieces();
+        let gutter = self.gutter_width();
+        let width = self.dimensions.content.width.saturating_sub(gutter);
+        let lines = break_lines(&pieces, width);
+
+        self.runs.clear();
+        let mut number = 0;
+        for (y, line) in lines.iter().enumerate() {
+            // Lines that wrapped go on from the number of the one before
+            if gutter > 0 && !line.wrapped {
+                number += 1;
+                self.runs.push(Run {
+                    x: 0,
+                    y,
+                    text: format!("{number:>0$} ", gutter - 1),
+                    style: TextStyle {
+                        color: Some(GUTTER_COLOR),
+                        ..self.style
+                    },
+                });
+            }
+            let mut x = gutter
+                + match self.align {
+                    TextAlign::Left => 0,
+                    TextAlign::Center => width.saturating_sub(line.width) / 2,
+                    TextAlign::Right => width.saturating_sub(line.width),
+                };
+            for (text, style) in &line.segments {
+                let run = Run {
+                    x,
+                    y,
+                    text: text.clone(),
+                    style: *style,
+                };
+                x += run.width();
+                self.runs.push(run);
+            }
+        }
+        self.dimensions.content.height = lines.len();
+    }
+
+    /// The inline content of a block, after the marker of a list item with
+    /// its marker inside.
+    fn inline_pieces(&self) -> Vec<Piece> {
+        let mut pieces = Vec::new();
+        if let Some(Marker { text, inside: true }) = &self.marker {
+            pieces.push(Piece::Word(text.clone(), self.style, true));
+            pieces.push(Piece::Space(self.style));
+        }
+        for child in &self.children {
+            child.collect_pieces(&mut pieces);
+        }
+        pieces
+    }
+
+    /// Adds the text under an inline box to `pieces`, following its
+    /// `white-space`.
+    fn collect_pieces(&self, pieces: &mut Vec<Piece>) {
+        let Some(node) = self.node() else {
+            return;
+        };
+        let NodeType::Text(ref text) = node.node.node_type else {
+            if is_element(node, "br") {
+                pieces.push(Piece::Break);
+            }
+            // The numbers of links go right before their text
+            if let Some(Marker { text, .. }) = &self.marker {
+                pieces.push(Piece::Word(text.clone(), self.style, true));
+            }
+            for child in &self.children {
+                child.collect_pieces(pieces);
+            }
+            return;
+        };
+
+        let white_space = WhiteSpace::of(node);
+        let style = self.style;
+        let text = transform(text, node.keyword("text-transform"));
+        for c in text.chars() {
+            match c {
+                '\n' if white_space.keep_newlines => pieces.push(Piece::Break),
+                ' ' | '\t' | '\n' | '\r' | '\x0c' if white_space.collapse => {
+                    // Runs of whitespace collapse into one space, and none
+                    // is kept at the start of a line
+                    if matches!(pieces.last(), Some(Piece::Word(..))) {
+                        pieces.push(Piece::Space(style));
+                    }
+                }
+                ' ' => pieces.push(Piece::KeptSpace(style, white_space.wrap)),
+                '\t' => pieces.push(Piece::Tab(style)),
+                '\r' => {}
+                c => match pieces.last_mut() {
+                    Some(Piece::Word(word, word_style, _)) if *word_style == style => word.push(c),
+                    _ => pieces.push(Piece::Word(c.to_string(), style, white_space.wrap)),
+                },
+            }
+        }
+    }
+
+    /// Adds what the box draws to `page`, for a parent whose content box
+    /// starts at column `x` of line `y`.
+    fn paint(&self, x: usize, y: usize, page: &mut Page) {
+        let d = &self.dimensions;
+        let (x, y) = (x + d.content.x, y + d.content.y);
+        if let Some(background) = self.background {
+            let style = TextStyle {
+                background: Some(background),
+                ..Default::default()
+            };
+            let width = d.padding.left + d.content.width + d.padding.right;
+            let height = d.padding.top + d.content.height + d.padding.bottom;
+            for line in 0..height {
+                page.runs.push(Run {
+                    x: x - d.padding.left,
+                    y: y - d.padding.top + line,
+                    text: " ".repeat(width),
+                    style,
+                });
+            }
+        }
+        // The bar goes just outside the padding, where the border would be
+        let bar = (x - d.padding.left).checked_sub(1);
+        if let Some(bar) = bar.filter(|_| self.node().is_some_and(has_left_border)) {
+            let height = d.padding.top + d.content.height + d.padding.bottom;
+            for line in 0..height {
+                page.runs.push(Run {
+                    x: bar,
+                    y: y - d.padding.top + line,
+                    text: "│".to_string(),
+                    style: self.style,
+                });
+            }
+        }
+        // Markers that don't fit left of the item are cut off, as they
+        // would be at the edge of a window
+        if let Some(Marker {
+            text,
+            inside: false,
+        }) = &self.marker
+        {
+            let marker = Run {
+                x: 0,
+                y,
+                text: text.clone(),
+                style: self.style,
+            };
+            if let Some(start) = x.checked_sub(marker.width() + 1) {
+                page.runs.push(Run { x: start, ..marker });
+            }
+        }
+        for run in &self.runs {
+            page.runs.push(Run {
+                x: x + run.x,
+                y: y + run.y,
+                ..run.clone()
+            });
+        }
+        for child in &self.children {
+            child.paint(x, y, page);
+        }
+    }
+}
+
+/// `text` in the case `text-transform` asks for.
+fn transform<'t>(text: &'t str, text_transform: Option<&str>) -> Cow<'t, str> {
+    match text_transform {
+        Some("uppercase") => Cow::Owned(text.to_uppercase()),
+        Some("lowercase") => Cow::Owned(text.to_lowercase()),
+        Some("capitalize") => {
+            let mut word_start = true;
+            let mut capitalized = String::with_capacity(text.len());
+            for c in text.chars() {
+                match word_start {
+                    true => capitalized.extend(c.to_uppercase()),
+                    false => capitalized.push(c),
+                }
+                word_start = c.is_whitespace();
+            }
+            Cow::Owned(capitalized)
+        }
+        _ => Cow::Borrowed(text),
+    }
+}
+
+/// How the whitespace in text is handled, from the `white-space` property.
+struct WhiteSpace {
+    collapse: bool,
+    wrap: bool,
+    keep_newlines: bool,
+}
+
+impl WhiteSpace {
+    fn of(node: &StyledNode) -> Self {
+        let (collapse, wrap, keep_newlines) = match node.keyword("white-space") {
+            Some("nowrap") => (true, false, false),
+            Some("pre") => (false, false, true),
+            Some("pre-wrap" | "break-spaces") => (false, true, true),
+            Some("pre-line") => (true, true, true),
+            _ => (true, true, false),
+        };
+        Self {
+            collapse,
+            wrap,
+            keep_newlines,
+        }
+    }
+}
+
+/// A piece of inline content, before it is broken into lines. The flags
+/// say whether lines may break there.
+#[derive(Debug)]
+enum Piece {
+    /// Text lines don't break inside of, unless it's too long for a line
+    /// by itself. Words with nothing between them are kept together.
+    Word(String, TextStyle, bool),
+    /// A collapsed space, which is dropped at the end of a line.
+    Space(TextStyle),
+    /// A space the page asked to keep.
+    KeptSpace(TextStyle, bool),
+    /// A tab that was kept, which moves on to the next multiple of eight.
+    Tab(TextStyle),
+    /// A newline that was kept.
+    Break,
+}
+
+/// The widest text in `pieces` that lines can't break inside of, and the
+/// widest line they make when only kept newlines break them.
+fn inline_widths(pieces: &[Piece]) -> (usize, usize) {
+    let (mut min, mut max) = (0, 0);
+    let (mut unbroken, mut line) = (0, 0);
+    let mut space = false;
+    for piece in pieces {
+        match piece {
+            Piece::Word(text, _, wrap) => {
+                if space {
+                    line += 1;
+                    unbroken = if *wrap { 0 } else { unbroken + 1 };
+                    space = false;
+                }
+                let width: usize = text.chars().map(char_width).sum();
+                unbroken += width;
+                line += width;
+            }
+            Piece::Space(_) => space = true,
+            Piece::KeptSpace(_, wrap) => {
+                line += 1;
+                unbroken = if *wrap { 0 } else { unbroken + 1 };
+            }
+            Piece::Tab(_) => {
+                let width = 8 - line % 8;
+                line += width;
+                unbroken += width;
+            }
+            Piece::Break => {
+                (unbroken, line) = (0, 0);
+                space = false;
+            }
+        }
+        min = min.max(unbroken);
+        max = max.max(line);
+    }
+    (min, max)
+}
+
+/// A line of inline content as styled segments.
+#[derive(Debug, Default)]
+struct Line {
+    segments: Vec<(String, TextStyle)>,
+    width: usize,
+    /// Whether the line goes on from the one before, rather than starting
+    /// after a newline.
+    wrapped: bool,
+}
+
+impl Line {
+    fn push(&mut self, text: &str, style: TextStyle) {
+        self.width += text.chars().map(char_width).sum::<usize>();
+        match self.segments.last_mut() {
+            Some((last, last_style)) if *last_style == style => last.push_str(text),
+            _ => self.segments.push((text.to_string(), style)),
+        }
+    }
+}
+
+/// Fills lines `width` cells wide with `pieces`, breaking them at spaces.
+fn break_lines(pieces: &[Piece], width: usize) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut line = Line::default();
+    // A collapsed space is only drawn once something follows it on the line
+    let mut space: Option<TextStyle> = None;
+
+    let mut i = 0;
+    while i < pieces.len() {
+        match &pieces[i] {
+            Piece::Break => {
+                lines.push(std::mem::take(&mut line));
+                space = None;
+            }
+            Piece::Space(style) => {
+                if line.width > 0 {
+                    space = Some(*style);
+                }
+            }
+            Piece::KeptSpace(style, wrap) => {
+                if *wrap && line.width + 1 > width {
+                    // Spaces at the end of a wrapped line hang past it
+                    lines.push(std::mem::take(&mut line));
+                    line.wrapped = true;
+                } else {
+                    line.push(" ", *style);
+                }
+            }
+            Piece::Tab(style) => {
+                let spaces = 8 - line.width % 8;
+                line.push(&" ".repeat(spaces), *style);
+            }
+            Piece::Word(_, _, wrap) => {
+                // Words with nothing between them go on the line together
+                let end = pieces[i..]
+                    .iter()
+                    .position(|piece| !matches!(piece, Piece::Word(..)))
+                    .map_or(pieces.len(), |len| i + len);
+                let words = &pieces[i..end];
+                let word_width: usize = words
+                    .iter()
+                    .map(|piece| match piece {
+                        Piece::Word(text, ..) => text.chars().map(char_width).sum(),
+                        _ => 0,
+                    })
+                    .sum();
+                let space_width = usize::from(space.is_some());
+                if *wrap && line.width > 0 && line.width + space_width + word_width > width {
+                    lines.push(std::mem::take(&mut line));
+                    line.wrapped = true;
+                    space = None;
+                }
+                if let Some(style) = space.take() {
+                    line.push(" ", style);
+                }
+                for piece in words {
+                    let Piece::Word(text, style, _) = piece else {
+                        continue;
+                    };
+                    if !wrap || word_width <= width {
+                        line.push(text, *style);
+                        continue;
+                    }
+                    // Words wider than the line are broken wherever they
+                    // need to be
+                    for c in text.chars() {
+                        if line.width > 0 && line.width + char_width(c) > width {
+                            lines.push(std::mem::take(&mut line));
+                            line.wrapped = true;
+                        }
+                        line.push(c.encode_utf8(&mut [0; 4]), *style);
+                    }
+                }
+                i = end;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    if line.width > 0 {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use super::{layout_page, Page, Run};
+    use crate::engine::css::{self, Color, Media};
+    use crate::engine::{style_tree, Parser, TextStyle};
+
+    pub(super) fn layout(source: &str, css: &str, columns: u16) -> Page {
+        let mut parser = Parser::new();
+        parser.feed_str(source);
+        let document = parser.finish();
+        let mode = document.mode;
+        let root = document.into_root();
+        let media = Media::for_terminal(columns, 24);
+        let styled = style_tree(&root, &[css::parse(css)], mode, &media);
+        layout_page(&styled, &media, false, false)
+    }
+
+    #[test]
+    fn breaks_lines_between_words() {
+        let page = layout("<p>The quick  brown\nfox jumps over", "", 10);
+        assert_eq!(page.text(), "The quick\nbrown fox\njumps over");
+        assert_eq!(page.height, 3);
+
+        // Words too long for a line are broken where they have to be
+        let page = layout("<p>a abcdefghijkl", "", 5);
+        assert_eq!(page.text(), "a\nabcde\nfghij\nkl");
+    }
+
+    #[test]
+    fn measures_wide_characters() {
+        let page = layout("<p>漢字 漢字漢字", "", 7);
+        assert_eq!(page.text(), "漢字\n漢字漢\n字");
+        assert_eq!(page.width, 6);
+    }
+
+    #[test]
+    fn collapses_margins() {
+        let page = layout("<div><p>one</p></div><p>two<div><p>three</p></div>", "", 40);
+        assert_eq!(page.text(), "one\n\ntwo\n\nthree");
+
+        // Padding keeps the margins of the children in
+        let page = layout(
+            "<p>one<div class=box><p>two</div>",
+            ".box { padding: 1em 2ch; margin: 0 }",
+            40,
+        );
+        assert_eq!(page.text(), "one\n\n\n\n  two");
+        assert_eq!(page.height, 7);
+    }
+
+    #[test]
+    fn sizes_boxes() {
+        let page = layout(
+            "<div class=column>centered</div><p class=right>right",
+            ".column { width: 80px; margin: 0 auto; text-align: center }
+             .right { text-align: right; margin-left: 50% }",
+            40,
+        );
+        assert_eq!(
+            page.text(),
+            format!("{}centered\n\n{}right", " ".repeat(16), " ".repeat(35))
+        );
+    }
+
+    #[test]
+    fn keeps_whitespace_when_asked() {
+        let page = layout(
+            "<div class=pre>  a  b\n\tc</div><div class=line>a  b\nc</div>",
+            ".pre { white-space: pre } .line { white-space: pre-line }",
+            40,
+        );
+        assert_eq!(page.text(), "  a  b\n        c\na b\nc");
+    }
+
+    #[test]
+    fn keeps_preformatted_lines_whole() {
+        let page = layout(
+            "<p>Code:</p><pre>\nfn main() {\n    println!(\"a long line of code\");\n}\n</pre>",
+            "p, pre { margin: 0 }",
+            20,
+        );
+        assert_eq!(
+            page.text(),
+            "Code:\nfn main() {\n    println!(\"a long line of code\");\n}"
+        );
+        assert_eq!(page.width, 36);
+    }
+
+    #[test]
+    fn numbers_preformatted_lines() {
+        let source = format!("<p>a b</p><pre>{}</pre>", "x\n".repeat(10));
+        let styled_page = |line_numbers| {
+            let mut parser = Parser::new();
+            parser.feed_str(&source);
+            let document = parser.finish();
+            let mode = document.mode;
+            let root = document.into_root();
+            let media = Media::for_terminal(40, 24);
+            let css = css::parse("p, pre { margin: 0 }");
+            let styled = style_tree(&root, &[css], mode, &media);
+            layout_page(&styled, &media, false, line_numbers)
+        };
+        let page = styled_page(true);
+        let lines: Vec<String> = page.text().lines().map(String::from).collect();
+        assert_eq!(lines.len(), 11);
+        assert_eq!(lines[0], "a b");
+        assert_eq!(lines[1], " 1 x");
+        assert_eq!(lines[10], "10 x");
+        assert!(page
+            .runs
+            .iter()
+            .any(|run| run.text == " 1 " && run.style.color == Some(super::GUTTER_COLOR)));
+        assert_eq!(styled_page(false).text().lines().nth(1), Some("x"));
+    }
+
+    #[test]
+    fn finds_the_runs_on_lines() {
+        let page = layout(
+            "<p>a <b>b</b></p><p style='background: navy'>c</p><p>d</p>",
+            "",
+            40,
+        );
+        let texts = |lines| -> Vec<&str> {
+            page.lines(lines)
+                .iter()
+                .map(|run| run.text.trim_end())
+                .collect()
+        };
+        assert_eq!(texts(0..1), ["a", "b"]);
+        assert_eq!(texts(1..3), ["", "c"]);
+        assert_eq!(texts(4..100), ["d"]);
+        assert!(texts(100..200).is_empty());
+    }
+
+    #[test]
+    fn numbers_links() {
+        let page = layout(
+            "<p>See <a href=/docs>the <b>docs</b></a> or <a>nothing</a>.</p>
+             <a href='#top' style='display: block'><p>Back up</p></a>",
+            "",
+            40,
+        );
+        assert_eq!(page.links, [Some("/docs".into()), Some("#top".into())]);
+        assert_eq!(page.text(), "See [1]the docs or nothing.\n\n[2] Back up");
+        let link = |text: &str| {
+            page.runs
+                .iter()
+                .find(|run| run.text == text)
+                .unwrap()
+                .style
+                .link
+        };
+        assert_eq!(link("docs"), Some(0));
+        assert_eq!(link(" or nothing."), None);
+        assert_eq!(link("[2] Back up"), Some(1));
+    }
+
+    #[test]
+    fn breaks_lines_and_draws_rules() {
+        let page = layout("<p>one<br>two<br><br>three</p><hr><p>four</p>", "", 10);
+        assert_eq!(page.text(), "one\ntwo\n\nthree\n\n──────────\n\nfour");
+        let rule = page.runs.iter().find(|run| run.text.starts_with('─'));
+        assert_eq!(rule.unwrap().style.color, Some(Color::rgb(128, 128, 128)));
+    }
+
+    #[test]
+    fn draws_a_bar_beside_quotes() {
+        let page = layout(
+            "<blockquote><p>To be or not to be</p></blockquote>",
+            "p { margin: 0 }",
+            40,
+        );
+        assert_eq!(page.text(), "    │ To be or not to be");
+    }
+
+    #[test]
+    fn tells_headings_apart() {
+        let page = layout(
+            "<h1>Title</h1><h2>a</h2><h3>b</h3><h4>c</h4><h5>d</h5><h6>e</h6>",
+            "h1, h2, h3, h4, h5, h6 { margin: 0 }",
+            40,
+        );
+        assert_eq!(page.text(), "TITLE\na\nb\nc\nd\ne");
+        let styles: Vec<_> = page
+            .runs
+            .iter()
+            .map(|run| {
+                let style = run.style;
+                (style.bold, style.italic, style.underline, style.dim)
+            })
+            .collect();
+        assert_eq!(
+            styles,
+            [
+                (true, false, true, false),
+                (true, false, true, false),
+                (true, false, false, false),
+                (true, true, false, false),
+                (false, true, false, false),
+                (false, true, false, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn sets_code_apart_from_text() {
+        let page = layout(
+            "<p>Run <code>ls</code> or press <kbd>q</kbd></p><pre><code>ls</code></pre>",
+            "",
+            40,
+        );
+        let backgrounds: Vec<_> = page
+            .runs
+            .iter()
+            .map(|run| (run.text.as_str(), run.style.background))
+            .collect();
+        let gray = Some(Color::rgb(105, 105, 105));
+        assert_eq!(
+            backgrounds,
+            [
+                ("Run ", None),
+                ("ls", gray),
+                (" or press ", None),
+                ("q", gray),
+                ("ls", None)
+            ]
+        );
+    }
+
+    #[test]
+    fn styles_runs() {
+        let page = layout(
+            "<body><p class=note>a <b>bold</b> move",
+            "body { background: white } .note { background: navy }",
+            12,
+        );
+        let navy = Color::rgb(0, 0, 128);
+        assert_eq!(page.background, Some(Color::rgb(255, 255, 255)));
+        let plain = TextStyle {
+            background: Some(navy),
+            ..Default::default()
+        };
+        let bold = TextStyle {
+            bold: true,
+            ..plain
+        };
+        assert_eq!(
+            page.runs,
+            vec![
+                Run {
+                    x: 0,
+                    y: 0,
+                    text: " ".repeat(12),
+                    style: plain
+                },
+                Run {
+                    x: 0,
+                    y: 0,
+                    text: "a ".to_string(),
+                    style: plain
+                },
+                Run {
+                    x: 2,
+                    y: 0,
+                    text: "bold".to_string(),
+                    style: bold
+                },
+                Run {
+                    x: 6,
+                    y: 0,
+                    text: " move".to_string(),
+                    style: plain
+                },
+            ]
+        );
+    }
+}
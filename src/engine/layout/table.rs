@@ -0,0 +1,602 @@
+use super::{attribute, cells, Axis, EdgeSizes, LayoutBox, Piece, Rect, Run, LINE_STYLES};
+use crate::engine::css::{Media, Value};
+use crate::engine::{Display, StyledNode, TextStyle};
+
+/// A row of a table, by where it is among the children of the table, or
+/// of the row group it is in.
+#[derive(Clone, Copy, Debug)]
+struct RowPath {
+    group: Option<usize>,
+    row: usize,
+}
+
+/// A cell placed on the grid, by the row it starts in and where it is
+/// among the children of that row.
+#[derive(Debug)]
+struct Slot {
+    row: usize,
+    cell: usize,
+    column: usize,
+    colspan: usize,
+    rowspan: usize,
+}
+
+/// The cells of a table laid out on rows and columns.
+#[derive(Debug)]
+struct Grid {
+    rows: Vec<RowPath>,
+    slots: Vec<Slot>,
+    columns: usize,
+    /// Which slot covers each row and column, if any.
+    owners: Vec<Vec<Option<usize>>>,
+}
+
+fn display(layout_box: &LayoutBox) -> Option<Display> {
+    layout_box.node().map(StyledNode::display)
+}
+
+/// Whether the page asks for lines around the cells of `node`.
+fn has_border(node: &StyledNode) -> bool {
+    let properties = [
+        "border",
+        "border-style",
+        "border-top",
+        "border-right",
+        "border-bottom",
+        "border-left",
+    ];
+    properties
+        .iter()
+        .filter_map(|name| node.value(name))
+        .flatten()
+        .any(
+            |value| matches!(value, Value::Keyword(style) if LINE_STYLES.contains(&style.as_str())),
+        )
+}
+
+/// Splits `total` into parts in proportion to `weights`, or evenly if they
+/// are all zero, adding up to `total` exactly.
+fn share(total: usize, weights: &[usize]) -> Vec<usize> {
+    let mut weights = weights.to_vec();
+    if weights.iter().all(|weight| *weight == 0) {
+        weights.fill(1);
+    }
+    let sum: usize = weights.iter().sum();
+    let mut before = 0;
+    weights
+        .iter()
+        .map(|weight| {
+            let start = total * before / sum;
+            before += weight;
+            total * before / sum - start
+        })
+        .collect()
+}
+
+/// Shares `width` out between columns that want the widths in `columns`.
+/// Each gets its narrowest, then what's left goes to them in proportion to
+/// how much wider they would like to be, and past their widest in
+/// proportion to that.
+fn distribute(columns: &[(usize, usize)], width: usize) -> Vec<usize> {
+    let min: usize = columns.iter().map(|(min, _)| min).sum();
+    let max: usize = columns.iter().map(|(_, max)| max).sum();
+    if width >= max {
+        let widest: Vec<usize> = columns.iter().map(|(_, max)| *max).collect();
+        let extra = share(width - max, &widest);
+        return widest
+            .iter()
+            .zip(extra)
+            .map(|(max, extra)| max + extra)
+            .collect();
+    }
+    let wanted: Vec<usize> = columns.iter().map(|(min, max)| max - min).collect();
+    let extra = share(width.saturating_sub(min), &wanted);
+    columns
+        .iter()
+        .zip(extra)
+        .map(|((min, _), extra)| min + extra)
+        .collect()
+}
+
+/// The space around the columns of a table, in cells.
+struct Spacing {
+    /// Between two columns.
+    gap: usize,
+    /// Before the first column and after the last.
+    edge: usize,
+    total: usize,
+}
+
+impl Spacing {
+    fn new(bordered: bool, columns: usize) -> Self {
+        // Lines between cells are padded with a space on each side
+        let (gap, edge) = match bordered {
+            true => (3, 2),
+            false => (1, 0),
+        };
+        let total = (columns - 1) * gap + 2 * edge;
+        Self { gap, edge, total }
+    }
+}
+
+/// The box-drawing character where lines going the given ways meet.
+fn junction(up: bool, down: bool, left: bool, right: bool) -> char {
+    match (up, down, left, right) {
+        (false, true, false, true) => '┌',
+        (false, true, true, false) => '┐',
+        (true, false, false, true) => '└',
+        (true, false, true, false) => '┘',
+        (true, true, false, true) => '├',
+        (true, true, true, false) => '┤',
+        (false, true, true, true) => '┬',
+        (true, false, true, true) => '┴',
+        (true, true, true, true) => '┼',
+        (true, _, false, false) | (_, true, false, false) => '│',
+        (false, false, true, _) | (false, false, _, true) => '─',
+        (false, false, false, false) => ' ',
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    /// Whether the box holds nothing but whitespace that collapses away.
+    fn is_blank(&self) -> bool {
+        let mut pieces: Vec<Piece> = Vec::new();
+        match display(self) {
+            Some(Display::Inline) => self.collect_pieces(&mut pieces),
+            None => self
+                .children
+                .iter()
+                .for_each(|child| child.collect_pieces(&mut pieces)),
+            Some(_) => return false,
+        }
+        pieces.is_empty()
+    }
+
+    fn row(&self, path: RowPath) -> &LayoutBox<'a> {
+        match path.group {
+            Some(group) => &self.children[group].children[path.row],
+            None => &self.children[path.row],
+        }
+    }
+
+    fn row_mut(&mut self, path: RowPath) -> &mut LayoutBox<'a> {
+        match path.group {
+            Some(group) => &mut self.children[group].children[path.row],
+            None => &mut self.children[path.row],
+        }
+    }
+
+    fn cell(&self, grid: &Grid, slot: &Slot) -> &LayoutBox<'a> {
+        &self.row(grid.rows[slot.row]).children[slot.cell]
+    }
+
+    /// Places the cells of the table on a grid, or gives `None` if it holds
+    /// content that doesn't go in a cell.
+    fn grid(&self) -> Option<Grid> {
+        let mut rows = Vec::new();
+        for (index, child) in self.children.iter().enumerate() {
+            match display(child) {
+                Some(Display::TableRow) => rows.push(RowPath {
+                    group: None,
+                    row: index,
+                }),
+                Some(Display::TableRowGroup) => {
+                    for (row, grandchild) in child.children.iter().enumerate() {
+                        match display(grandchild) {
+                            Some(Display::TableRow) => rows.push(RowPath {
+                                group: Some(index),
+                                row,
+                            }),
+                            _ if grandchild.is_blank() => {}
+                            _ => return None,
+                        }
+                    }
+                }
+                Some(Display::TableCaption) => {}
+                _ if child.is_blank() => {}
+                _ => return None,
+            }
+        }
+
+        let mut owners: Vec<Vec<Option<usize>>> = vec![Vec::new(); rows.len()];
+        let mut slots = Vec::new();
+        for (row, path) in rows.iter().enumerate() {
+            let mut column = 0;
+            for (index, cell) in self.row(*path).children.iter().enumerate() {
+                let Some(node) = cell
+                    .node()
+                    .filter(|_| display(cell) == Some(Display::TableCell))
+                else {
+                    match cell.is_blank() {
+                        true => continue,
+                        false => return None,
+                    }
+                };
+                // Columns taken by cells spanning down from rows above are
+                // skipped
+                while owners[row].get(column).is_some_and(Option::is_some) {
+                    column += 1;
+                }
+                let span = |name| attribute(node, name).and_then(|span| span.trim().parse().ok());
+                let colspan = span("colspan").unwrap_or(1).clamp(1, 1000);
+                // A row span of zero goes to the end of the table
+                let rowspan = match span("rowspan") {
+                    Some(0) => rows.len() - row,
+                    span => span.unwrap_or(1).clamp(1, rows.len() - row),
+                };
+                for line in &mut owners[row..row + rowspan] {
+                    if line.len() < column + colspan {
+                        line.resize(column + colspan, None);
+                    }
+                    line[column..column + colspan].fill(Some(slots.len()));
+                }
+                slots.push(Slot {
+                    row,
+                    cell: index,
+                    column,
+                    colspan,
+                    rowspan,
+                });
+                column += colspan;
+            }
+        }
+        let columns = owners.iter().map(Vec::len).max().unwrap_or(0);
+        for line in &mut owners {
+            line.resize(columns, None);
+        }
+        Some(Grid {
+            rows,
+            slots,
+            columns,
+            owners,
+        })
+    }
+
+    /// Whether lines are drawn around the cells, for tables with a
+    /// `border` attribute or a border style on them or their cells.
+    fn is_bordered(&self, grid: &Grid) -> bool {
+        let Some(node) = self.node() else {
+            return false;
+        };
+        let attribute = attribute(node, "border").is_some_and(|width| width.trim() != "0");
+        attribute
+            || has_border(node)
+            || grid
+                .slots
+                .iter()
+                .filter_map(|slot| self.cell(grid, slot).node())
+                .any(has_border)
+    }
+
+    /// The narrowest and widest each column would be, from the cells in it.
+    /// Cells spanning columns that don't fit widen them evenly.
+    fn column_widths(&self, grid: &Grid, gap: usize, media: &Media) -> Vec<(usize, usize)> {
+        let mut columns = vec![(0, 0); grid.columns];
+        let mut slots: Vec<(&Slot, (usize, usize))> = grid
+            .slots
+            .iter()
+            .map(|slot| (slot, self.cell(grid, slot).content_widths(media)))
+            .collect();
+        slots.sort_by_key(|(slot, _)| slot.colspan);
+        for (slot, (min, max)) in slots {
+            let spanned = &mut columns[slot.column..slot.column + slot.colspan];
+            let gaps = (slot.colspan - 1) * gap;
+            let have: usize = spanned.iter().map(|(min, _)| min).sum::<usize>() + gaps;
+            if min > have {
+                let extra = share(min - have, &vec![1; spanned.len()]);
+                for (column, extra) in spanned.iter_mut().zip(extra) {
+                    column.0 += extra;
+                }
+            }
+            let have: usize = spanned.iter().map(|(_, max)| max).sum::<usize>() + gaps;
+            if max > have {
+                let extra = share(max - have, &vec![1; spanned.len()]);
+                for (column, extra) in spanned.iter_mut().zip(extra) {
+                    column.1 += extra;
+                }
+            }
+            for column in spanned {
+                column.1 = column.1.max(column.0);
+            }
+        }
+        columns
+    }
+
+    /// How narrow and how wide the table can get, without its padding.
+    pub(super) fn table_widths(&self, media: &Media) -> (usize, usize) {
+        let Some(grid) = self.grid().filter(|grid| !grid.slots.is_empty()) else {
+            return self
+                .children
+                .iter()
+                .map(|child| child.content_widths(media))
+                .fold((0, 0), |(min, max), (child_min, child_max)| {
+                    (min.max(child_min), max.max(child_max))
+                });
+        };
+        let spacing = Spacing::new(self.is_bordered(&grid), grid.columns);
+        let columns = self.column_widths(&grid, spacing.gap, media);
+        let min: usize = columns.iter().map(|(min, _)| min).sum();
+        let max: usize = columns.iter().map(|(_, max)| max).sum();
+        (min + spacing.total, max + spacing.total)
+    }
+
+    /// Lays out a cell `width` cells wide, which the table worked out
+    /// rather than the cell.
+    fn layout_cell(&mut self, width: usize, media: &Media) {
+        self.calculate_edges(width, media);
+        let d = &mut self.dimensions;
+        d.margin = EdgeSizes::default();
+        d.content.width = width.saturating_sub(d.padding.left + d.padding.right);
+        self.layout_children(media);
+    }
+
+    /// Lays the table out on a grid, sharing the width out between the
+    /// columns. Tables too wide for the terminal are laid out one cell
+    /// under the other instead, which reads better than cells squeezed
+    /// into a few columns each.
+    pub(super) fn layout_table(&mut self, media: &Media) {
+        let Some(grid) = self.grid().filter(|grid| !grid.slots.is_empty()) else {
+            self.layout_block_children(media);
+            return;
+        };
+        let Some(node) = self.node() else {
+            return;
+        };
+        let bordered = self.is_bordered(&grid);
+        let Spacing { gap, edge, total } = Spacing::new(bordered, grid.columns);
+        let columns = self.column_widths(&grid, gap, media);
+        let min = columns.iter().map(|(min, _)| min).sum::<usize>() + total;
+        let max = columns.iter().map(|(_, max)| max).sum::<usize>() + total;
+        let available = self.dimensions.content.width;
+        if min > available {
+            self.layout_block_children(media);
+            return;
+        }
+
+        // Tables are as wide as their content unless they ask for a width
+        let width = match (node.value("width"), attribute(node, "width")) {
+            (Some(_), _) => available,
+            (None, Some(width)) => {
+                let width = match width.trim().strip_suffix('%') {
+                    Some(percentage) => percentage.parse().ok().map(Value::Percentage),
+                    None => width.trim().parse().ok().map(Value::Number),
+                };
+                cells(
+                    width.as_ref().map(std::slice::from_ref),
+                    Axis::Horizontal,
+                    available,
+                    media,
+                )
+                .map_or(max, |width| width.clamp(min, available))
+            }
+            (None, None) => max,
+        };
+        let widths = distribute(&columns, width.min(available) - total);
+
+        // Where the content of each column starts, and where it would for
+        // one more
+        let x: Vec<usize> = (0..=grid.columns)
+            .map(|column| edge + widths[..column].iter().sum::<usize>() + column * gap)
+            .collect();
+        let table_width = x[grid.columns] + edge - gap;
+
+        // Captions go above the grid
+        let mut y = 0;
+        for child in &mut self.children {
+            if display(child) != Some(Display::TableCaption) {
+                continue;
+            }
+            child.layout(table_width, media);
+            let d = &mut child.dimensions;
+            d.content.x = d.margin.left + d.padding.left;
+            d.content.y = y + d.margin.top + d.padding.top;
+            y = d.content.y + d.content.height + d.padding.bottom + d.margin.bottom;
+        }
+
+        for slot in &grid.slots {
+            let width = x[slot.column + slot.colspan] - x[slot.column] - gap;
+            let row = self.row_mut(grid.rows[slot.row]);
+            row.children[slot.cell].layout_cell(width, media);
+        }
+
+        // Rows are as tall as their tallest cell, with the cells spanning
+        // rows making the last of them taller if they need to
+        let line = usize::from(bordered);
+        let outer_height = |cell: &LayoutBox| {
+            let d = &cell.dimensions;
+            d.content.height + d.padding.top + d.padding.bottom
+        };
+        let mut heights = vec![0; grid.rows.len()];
+        let mut slots: Vec<&Slot> = grid.slots.iter().collect();
+        slots.sort_by_key(|slot| slot.rowspan);
+        for slot in slots {
+            let height = outer_height(self.cell(&grid, slot));
+            let spanned = &mut heights[slot.row..slot.row + slot.rowspan];
+            let have = spanned.iter().sum::<usize>() + (slot.rowspan - 1) * line;
+            if height > have {
+                *spanned.last_mut().unwrap() += height - have;
+            }
+        }
+        let top = y;
+        let row_y: Vec<usize> = (0..=grid.rows.len())
+            .map(|row| top + line + heights[..row].iter().sum::<usize>() + row * line)
+            .collect();
+        let height = row_y[grid.rows.len()];
+
+        // Rows and their groups are moved where the rows go, and the cells
+        // fill the rows they span
+        let mut group_y = vec![None; self.children.len()];
+        for (row, path) in grid.rows.iter().enumerate() {
+            let mut parent_y = 0;
+            if let Some(group) = path.group {
+                let start = *group_y[group].get_or_insert(row_y[row]);
+                let group = &mut self.children[group].dimensions.content;
+                *group = Rect {
+                    x: 0,
+                    y: start,
+                    width: table_width,
+                    height: row_y[row] + heights[row] - start,
+                };
+                parent_y = start;
+            }
+            self.row_mut(*path).dimensions.content = Rect {
+                x: 0,
+                y: row_y[row] - parent_y,
+                width: table_width,
+                height: heights[row],
+            };
+        }
+        for slot in &grid.slots {
+            let last = slot.row + slot.rowspan - 1;
+            let span_height = row_y[last] + heights[last] - row_y[slot.row];
+            let row = self.row_mut(grid.rows[slot.row]);
+            let d = &mut row.children[slot.cell].dimensions;
+            d.content.x = x[slot.column] + d.padding.left;
+            d.content.y = d.padding.top;
+            d.content.height = span_height.saturating_sub(d.padding.top + d.padding.bottom);
+        }
+
+        self.runs.clear();
+        if bordered {
+            self.draw_borders(&grid, &x, &row_y, &heights);
+        }
+
+        // What's left of the width goes to the margins
+        let d = &mut self.dimensions;
+        let spare = d.content.width - table_width;
+        let auto = |side: &str| matches!(node.keyword(side), Some("auto"));
+        let centered = auto("margin-left") && auto("margin-right")
+            || attribute(node, "align").is_some_and(|align| align.eq_ignore_ascii_case("center"));
+        match centered {
+            true => {
+                d.margin.left += spare / 2;
+                d.margin.right += spare - spare / 2;
+            }
+            false => d.margin.right += spare,
+        }
+        d.content.width = table_width;
+        d.content.height = height;
+    }
+
+    /// Draws the lines around the cells with box-drawing characters, given
+    /// where the columns and rows start.
+    fn draw_borders(&mut self, grid: &Grid, x: &[usize], row_y: &[usize], heights: &[usize]) {
+        let (rows, columns) = (grid.rows.len(), grid.columns);
+        let owner = |row: usize, column: usize| grid.owners[row][column];
+        // Lines go between the cells that differ on either side of them
+        let vertical = |row: usize, column: usize| {
+            column == 0 || column == columns || owner(row, column - 1) != owner(row, column)
+        };
+        let horizontal = |row: usize, column: usize| {
+            row == 0 || row == rows || owner(row - 1, column) != owner(row, column)
+        };
+        let style = TextStyle {
+            color: self.style.color,
+            background: self.style.background,
+            ..Default::default()
+        };
+
+        for (row, y) in row_y.iter().enumerate() {
+            let mut text = String::new();
+            for column in 0..=columns {
+                text.push(junction(
+                    row > 0 && vertical(row - 1, column),
+                    row < rows && vertical(row, column),
+                    column > 0 && horizontal(row, column - 1),
+                    column < columns && horizontal(row, column),
+                ));
+                if column < columns {
+                    let fill = if horizontal(row, column) { '─' } else { ' ' };
+                    let width = x[column + 1] - x[column] - 1;
+                    text.extend(std::iter::repeat_n(fill, width));
+                }
+            }
+            self.runs.push(Run {
+                x: 0,
+                y: y - 1,
+                text,
+                style,
+            });
+        }
+        for row in 0..rows {
+            for column in (0..=columns).filter(|column| vertical(row, *column)) {
+                for y in row_y[row]..row_y[row] + heights[row] {
+                    self.runs.push(Run {
+                        x: x[column] - 2,
+                        y,
+                        text: "│".to_string(),
+                        style,
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::layout;
+
+    #[test]
+    fn draws_borders() {
+        let page = layout(
+            "<table border=1><tr><th>Name<th>Age<tr><td>Ann<td>7</table>",
+            "",
+            40,
+        );
+        assert_eq!(
+            page.text(),
+            "┌──────┬─────┐
+│ Name │ Age │
+├──────┼─────┤
+│ Ann  │ 7   │
+└──────┴─────┘"
+        );
+    }
+
+    #[test]
+    fn spans_rows_and_columns() {
+        let page = layout(
+            "<table style='border: 1px solid'>
+               <tr><td colspan=2>wide<td rowspan=2>tall
+               <tr><td>a<td>b
+             </table>",
+            "",
+            40,
+        );
+        assert_eq!(
+            page.text(),
+            "┌───────┬──────┐
+│ wide  │ tall │
+├───┬───┤      │
+│ a │ b │      │
+└───┴───┴──────┘"
+        );
+    }
+
+    #[test]
+    fn lines_up_columns() {
+        let page = layout("<table><tr><td>a<td>bb<tr><td>ccc<td>d</table>", "", 40);
+        assert_eq!(page.text(), "a   bb\nccc d");
+    }
+
+    #[test]
+    fn shares_out_the_width() {
+        let page = layout("<table><tr><td>aaa bbb ccc<td>x</table>", "", 10);
+        assert_eq!(page.text(), "aaa bbb  x\nccc");
+
+        // Tables asking for a width spread their columns out
+        let page = layout("<table width=100%><tr><td>a<td>bbb</table>", "", 10);
+        assert_eq!(page.text(), "a  bbb");
+    }
+
+    #[test]
+    fn reads_tables_too_wide_cell_by_cell() {
+        let page = layout(
+            "<table><caption>Fruit</caption><tr><td>alpha beta<td>gamma</table>",
+            "",
+            8,
+        );
+        assert_eq!(page.text(), " Fruit\nalpha\nbeta\ngamma");
+    }
+}
@@ -2,7 +2,10 @@ mod css;
 mod dom;
 mod html;
 mod layout;
+mod style;
 
+pub use css::{Color, Media};
 pub use dom::*;
-pub use html::parse;
+pub use html::Parser;
 pub use layout::*;
+pub use style::*;
@@ -0,0 +1,1204 @@
+use std::{
+    collections::{HashMap, HashSet},
+    iter,
+    sync::OnceLock,
+};
+
+use reqwest::Url;
+
+use super::css::{
+    self, AttributeOperator, AttributeSelector, Color, Combinator, CompoundSelector, Declaration,
+    Media, MediaList, Selector, SimpleSelector, Specificity, Stylesheet, Value,
+};
+use super::{ElementData, Node, NodeType, QuirksMode};
+
+/// Properties by name, with the values they ended up with.
+pub type PropertyMap = HashMap<String, Vec<Value>>;
+
+/// A node of the DOM along with the values of its properties after the
+/// cascade and inheritance.
+#[derive(Debug)]
+pub struct StyledNode<'a> {
+    pub node: &'a Node,
+    pub specified_values: PropertyMap,
+    pub children: Vec<StyledNode<'a>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Display {
+    Inline,
+    Block,
+    ListItem,
+    Table,
+    /// The `thead`, `tbody` and `tfoot` of a table.
+    TableRowGroup,
+    TableRow,
+    TableCell,
+    TableCaption,
+    /// Columns and column groups, which only carry styles for the cells.
+    TableColumn,
+    None,
+}
+
+impl StyledNode<'_> {
+    pub fn value(&self, name: &str) -> Option<&[Value]> {
+        self.specified_values.get(name).map(Vec::as_slice)
+    }
+
+    /// The value of a property that is a single keyword.
+    pub fn keyword(&self, name: &str) -> Option<&str> {
+        match self.value(name)? {
+            [Value::Keyword(keyword)] => Some(keyword),
+            _ => None,
+        }
+    }
+
+    pub fn display(&self) -> Display {
+        match self.node.node_type {
+            NodeType::Element(_) => match self.keyword("display") {
+                Some("none") => Display::None,
+                Some("list-item") => Display::ListItem,
+                Some("table" | "inline-table") => Display::Table,
+                Some("table-row-group" | "table-header-group" | "table-footer-group") => {
+                    Display::TableRowGroup
+                }
+                Some("table-row") => Display::TableRow,
+                Some("table-cell") => Display::TableCell,
+                Some("table-caption") => Display::TableCaption,
+                Some("table-column" | "table-column-group") => Display::TableColumn,
+                Some("inline" | "inline-block" | "inline-flex" | "inline-grid") | None => {
+                    Display::Inline
+                }
+                Some(_) => Display::Block,
+            },
+            NodeType::Text(_) => Display::Inline,
+            NodeType::Comment(_) | NodeType::Doctype(_) => Display::None,
+        }
+    }
+
+    /// Whether the node and everything under it is left out of the page:
+    /// when it isn't displayed, or hidden from assistive technology with
+    /// `aria-hidden`, as a text browser reads pages much like a screen
+    /// reader does.
+    pub fn is_hidden(&self) -> bool {
+        let aria_hidden = match &self.node.node_type {
+            NodeType::Element(data) => data
+                .attrs
+                .get("aria-hidden")
+                .is_some_and(|value| value.trim().eq_ignore_ascii_case("true")),
+            _ => false,
+        };
+        aria_hidden || self.display() == Display::None
+    }
+
+    /// Whether the node's own content is drawn. Unlike `display: none`,
+    /// `visibility: hidden` can be undone by the children.
+    pub fn is_visible(&self) -> bool {
+        !matches!(self.keyword("visibility"), Some("hidden" | "collapse"))
+    }
+
+    pub fn color(&self, name: &str) -> Option<Color> {
+        Color::from_value(self.value(name)?)
+    }
+
+    /// How the text of the node is drawn, given that of its parent. Colors
+    /// and fonts are inherited, while backgrounds and decorations carry on
+    /// to the children as the boxes they are drawn on contain them.
+    pub fn text_style(&self, parent: &TextStyle) -> TextStyle {
+        let mut style = *parent;
+        style.color = self.color("color");
+        (style.bold, style.dim) = match self.value("font-weight") {
+            Some([Value::Keyword(weight)]) => (
+                matches!(weight.as_str(), "bold" | "bolder"),
+                weight == "lighter",
+            ),
+            Some([Value::Number(weight)]) => (*weight >= 600.0, *weight <= 300.0),
+            _ => (false, false),
+        };
+        style.italic = matches!(self.keyword("font-style"), Some("italic" | "oblique"));
+
+        // The `background` shorthand is used for plain colors as often as
+        // `background-color` is
+        let shorthand = self.value("background").and_then(|values| {
+            values
+                .iter()
+                .find_map(|value| Color::from_value(std::slice::from_ref(value)))
+        });
+        if let Some(background) = self.color("background-color").or(shorthand) {
+            // Backgrounds that can be seen through are left to the parent
+            if background.a >= 128 {
+                style.background = Some(background);
+            }
+        }
+        let decorations = ["text-decoration", "text-decoration-line"]
+            .iter()
+            .filter_map(|name| self.value(name))
+            .flatten();
+        for decoration in decorations {
+            match decoration {
+                Value::Keyword(line) if line == "underline" => style.underline = true,
+                Value::Keyword(line) if line == "line-through" => style.strikethrough = true,
+                _ => {}
+            }
+        }
+        style
+    }
+}
+
+/// The colors and font attributes text is drawn with. Colors are left out
+/// where the page doesn't set them.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TextStyle {
+    pub color: Option<Color>,
+    pub background: Option<Color>,
+    pub bold: bool,
+    /// Drawn faint, for light font weights.
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+    /// The link the text is part of, by its index on the page.
+    pub link: Option<usize>,
+}
+
+/// Where a stylesheet came from, which decides which of two declarations
+/// wins before specificity does.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Origin {
+    UserAgent,
+    Author,
+}
+
+/// Properties that children take from their parent unless they set them.
+const INHERITED: &[&str] = &[
+    "border-collapse",
+    "border-spacing",
+    "caption-side",
+    "color",
+    "cursor",
+    "direction",
+    "empty-cells",
+    "font",
+    "font-family",
+    "font-size",
+    "font-style",
+    "font-variant",
+    "font-weight",
+    "letter-spacing",
+    "line-height",
+    "list-style",
+    "list-style-image",
+    "list-style-position",
+    "list-style-type",
+    "quotes",
+    "text-align",
+    "text-indent",
+    "text-transform",
+    "visibility",
+    "white-space",
+    "word-spacing",
+];
+
+fn inherits(name: &str) -> bool {
+    // Custom properties always inherit
+    name.starts_with("--") || INHERITED.contains(&name)
+}
+
+/// Properties whose shorthand sets each side of a box, in the order its
+/// values are given.
+const BOX_SHORTHANDS: &[(&str, [&str; 4])] = &[
+    (
+        "margin",
+        ["margin-top", "margin-right", "margin-bottom", "margin-left"],
+    ),
+    (
+        "padding",
+        [
+            "padding-top",
+            "padding-right",
+            "padding-bottom",
+            "padding-left",
+        ],
+    ),
+];
+
+/// The properties `declaration` sets, with shorthands for the sides of a
+/// box split up so that they cascade with the properties of each side.
+fn longhands(declaration: &Declaration) -> Vec<(String, Vec<Value>)> {
+    let name = declaration.name.as_str();
+    let value = &declaration.value;
+    if name == "list-style" {
+        return list_style_longhands(value);
+    }
+    let Some((_, sides)) = BOX_SHORTHANDS
+        .iter()
+        .find(|(shorthand, _)| *shorthand == name)
+    else {
+        return vec![(declaration.name.clone(), value.clone())];
+    };
+    // One value sets every side, two the vertical and horizontal ones, and
+    // three leave the left to copy the right
+    let order: &[usize] = match value.len() {
+        1 => &[0, 0, 0, 0],
+        2 => &[0, 1, 0, 1],
+        3 => &[0, 1, 2, 1],
+        4 => &[0, 1, 2, 3],
+        _ => return Vec::new(),
+    };
+    sides
+        .iter()
+        .zip(order)
+        .map(|(side, &index)| (side.to_string(), vec![value[index].clone()]))
+        .collect()
+}
+
+/// Splits `list-style` into the type, position and image it sets, leaving
+/// the ones it doesn't name at their initial values.
+fn list_style_longhands(value: &[Value]) -> Vec<(String, Vec<Value>)> {
+    let keyword = |name: &str| vec![Value::Keyword(name.to_string())];
+    let (mut style_type, mut position, mut image) =
+        (keyword("disc"), keyword("outside"), keyword("none"));
+    match value {
+        [Value::Keyword(global)] if matches!(global.as_str(), "inherit" | "initial" | "unset") => {
+            (style_type, position, image) = (value.to_vec(), value.to_vec(), value.to_vec());
+        }
+        _ => {
+            for part in value {
+                match part {
+                    Value::Keyword(name) if name == "inside" || name == "outside" => {
+                        position = vec![part.clone()]
+                    }
+                    Value::Url(_) | Value::Function(..) => image = vec![part.clone()],
+                    // `none` sets the type, as there's no image anyway
+                    _ => style_type = vec![part.clone()],
+                }
+            }
+        }
+    }
+    vec![
+        ("list-style-type".to_string(), style_type),
+        ("list-style-position".to_string(), position),
+        ("list-style-image".to_string(), image),
+    ]
+}
+
+fn user_agent_stylesheet() -> &'static Stylesheet {
+    static STYLESHEET: OnceLock<Stylesheet> = OnceLock::new();
+    STYLESHEET.get_or_init(|| css::parse(include_str!("ua.css")))
+}
+
+/// Applies the user agent stylesheet and then `stylesheets`, in order, to
+/// the tree under `root`, leaving out the rules that aren't for `media`.
+pub fn style_tree<'a>(
+    root: &'a Node,
+    stylesheets: &[Stylesheet],
+    mode: QuirksMode,
+    media: &Media,
+) -> StyledNode<'a> {
+    let sheets: Vec<(Origin, &Stylesheet)> =
+        iter::once((Origin::UserAgent, user_agent_stylesheet()))
+            .chain(stylesheets.iter().map(|sheet| (Origin::Author, sheet)))
+            .collect();
+    let cascade = Cascade {
+        sheets,
+        mode,
+        media: *media,
+    };
+    cascade.style(
+        root,
+        std::slice::from_ref(root),
+        0,
+        None,
+        &PropertyMap::new(),
+    )
+}
+
+/// How deep `@import`s are followed, in case a page finds a way around the
+/// checks for import cycles.
+const MAX_IMPORT_DEPTH: usize = 8;
+
+/// Gathers the stylesheets of a page in document order: the contents of its
+/// `<style>` elements and the `<link rel=stylesheet>` resources, each after
+/// the sheets it imports. Urls are resolved against `base`, or the url of
+/// the importing sheet, and fetched with `fetch`. Sheets that can't be
+/// fetched are left out, and so are sheets already added once, which also
+/// stops import cycles.
+pub fn author_stylesheets(
+    root: &Node,
+    base: Option<&Url>,
+    fetch: &mut dyn FnMut(&Url) -> Option<String>,
+) -> Vec<Stylesheet> {
+    let mut collector = Collector {
+        fetch,
+        sheets: Vec::new(),
+        loaded: HashSet::new(),
+    };
+    collector.collect(root, base);
+    collector.sheets
+}
+
+/// Whether the `type` of a `<style>` or `<link>` allows CSS.
+fn is_css(data: &ElementData) -> bool {
+    data.attrs
+        .get("type")
+        .is_none_or(|value| value.is_empty() || value.eq_ignore_ascii_case("text/css"))
+}
+
+/// Resolves a url from a page against its `base`, which absolute urls
+/// don't need.
+pub fn resolve_url(base: Option<&Url>, url: &str) -> Option<Url> {
+    match base {
+        Some(base) => base.join(url.trim()).ok(),
+        None => Url::parse(url.trim()).ok(),
+    }
+}
+
+/// The attributes that hold urls, to resolve up front for whatever follows
+/// or fetches them later.
+const URL_ATTRIBUTES: [&str; 4] = ["href", "src", "action", "formaction"];
+
+/// Resolves the links, sources and form actions of the elements under
+/// `node` against `base`, so they no longer depend on where the page came
+/// from. Urls that don't resolve are left as they are, and so is the
+/// `<base href>` the base came from. Forms without an action are given
+/// the base url to submit to.
+pub fn resolve_urls(node: &mut Node, base: Option<&Url>) {
+    if let NodeType::Element(data) = &mut node.node_type {
+        if data.tag == "form" {
+            data.attrs.entry("action".to_string()).or_default();
+        }
+        if data.tag != "base" {
+            for name in URL_ATTRIBUTES {
+                if let Some(value) = data.attrs.get_mut(name) {
+                    if let Some(url) = resolve_url(base, value) {
+                        *value = url.into();
+                    }
+                }
+            }
+        }
+    }
+    for child in &mut node.children {
+        resolve_urls(child, base);
+    }
+}
+
+struct Collector<'f> {
+    fetch: &'f mut dyn FnMut(&Url) -> Option<String>,
+    sheets: Vec<Stylesheet>,
+    /// The urls of the sheets loaded so far.
+    loaded: HashSet<Url>,
+}
+
+impl Collector<'_> {
+    fn collect(&mut self, node: &Node, base: Option<&Url>) {
+        if let NodeType::Element(data) = &node.node_type {
+            let media: Vec<MediaList> = data
+                .attrs
+                .get("media")
+                .map(|media| MediaList::parse(media))
+                .into_iter()
+                .collect();
+            match data.tag.as_str() {
+                "style" if is_css(data) => {
+                    let source: String = node
+                        .children
+                        .iter()
+                        .filter_map(|child| match &child.node_type {
+                            NodeType::Text(text) => Some(text.as_str()),
+                            _ => None,
+                        })
+                        .collect();
+                    self.add(css::parse(&source), base, &[], &media);
+                }
+                "link" if is_css(data) => {
+                    let rel = data.attrs.get("rel").map(|rel| rel.to_ascii_lowercase());
+                    let rel: Vec<&str> = rel
+                        .iter()
+                        .flat_map(|rel| rel.split_ascii_whitespace())
+                        .collect();
+                    // Alternate stylesheets are only used when picked
+                    if rel.contains(&"stylesheet") && !rel.contains(&"alternate") {
+                        let href = data.attrs.get("href");
+                        if let Some(url) = href.and_then(|href| resolve_url(base, href)) {
+                            self.load(url, &[], &media);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        for child in &node.children {
+            self.collect(child, base);
+        }
+    }
+
+    /// Loads the sheet at `url`, imported through the sheets at `chain`,
+    /// unless it's one of them or was loaded before.
+    fn load(&mut self, url: Url, chain: &[Url], media: &[MediaList]) {
+        if chain.contains(&url) || !self.loaded.insert(url.clone()) {
+            return;
+        }
+        if let Some(source) = (self.fetch)(&url) {
+            let mut chain = chain.to_vec();
+            chain.push(url);
+            let base = chain.last().cloned();
+            self.add(css::parse(&source), base.as_ref(), &chain, media);
+        }
+    }
+
+    /// Adds `sheet` after the sheets it imports, resolved against `base`.
+    /// `chain` are the urls of the sheets it was imported through, itself
+    /// included. `media` are the media lists the sheet was linked or
+    /// imported with, which all of its rules get.
+    fn add(
+        &mut self,
+        mut sheet: Stylesheet,
+        base: Option<&Url>,
+        chain: &[Url],
+        media: &[MediaList],
+    ) {
+        if chain.len() <= MAX_IMPORT_DEPTH {
+            for import in &sheet.imports {
+                if let Some(url) = resolve_url(base, &import.url) {
+                    let mut media = media.to_vec();
+                    media.push(import.media.clone());
+                    self.load(url, chain, &media);
+                }
+            }
+        }
+        for rule in &mut sheet.rules {
+            rule.media.splice(0..0, media.iter().cloned());
+        }
+        self.sheets.push(sheet);
+    }
+}
+
+/// What decides between two declarations of a property, in order: origin and
+/// importance, whether it's from a `style` attribute, specificity and then
+/// source order.
+type CascadeKey = (u8, bool, Specificity, usize);
+
+struct Cascade<'s> {
+    sheets: Vec<(Origin, &'s Stylesheet)>,
+    mode: QuirksMode,
+    media: Media,
+}
+
+impl Cascade<'_> {
+    fn style<'a>(
+        &self,
+        node: &'a Node,
+        siblings: &'a [Node],
+        index: usize,
+        parent: Option<&Element<'a, '_>>,
+        parent_values: &PropertyMap,
+    ) -> StyledNode<'a> {
+        let mut values: PropertyMap = parent_values
+            .iter()
+            .filter(|(name, _)| inherits(name))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+
+        let NodeType::Element(data) = &node.node_type else {
+            return StyledNode {
+                node,
+                specified_values: match node.node_type {
+                    NodeType::Text(_) => values,
+                    _ => PropertyMap::new(),
+                },
+                children: Vec::new(),
+            };
+        };
+
+        let element = Element {
+            data,
+            siblings,
+            index,
+            parent,
+        };
+        let inline = data
+            .attrs
+            .get("style")
+            .map(|style| css::parse_declarations(style))
+            .unwrap_or_default();
+        let declarations = self.matching_declarations(&element, &inline);
+        for (name, value) in declarations.into_iter().flat_map(longhands) {
+            match value.as_slice() {
+                [Value::Keyword(keyword)]
+                    if keyword == "inherit" || keyword == "unset" && inherits(&name) =>
+                {
+                    match parent_values.get(&name) {
+                        Some(value) => values.insert(name, value.clone()),
+                        None => values.remove(&name),
+                    };
+                }
+                [Value::Keyword(keyword)] if keyword == "initial" || keyword == "unset" => {
+                    values.remove(&name);
+                }
+                _ => {
+                    values.insert(name, value);
+                }
+            }
+        }
+
+        let children = node
+            .children
+            .iter()
+            .enumerate()
+            .map(|(index, child)| self.style(child, &node.children, index, Some(&element), &values))
+            .collect();
+        StyledNode {
+            node,
+            specified_values: values,
+            children,
+        }
+    }
+
+    /// The declarations that apply to `element`, from the one that loses
+    /// the cascade to the one that wins it. Those of its `style` attribute,
+    /// `inline`, beat any selector with the same importance.
+    fn matching_declarations<'d>(
+        &'d self,
+        element: &Element,
+        inline: &'d [Declaration],
+    ) -> Vec<&'d Declaration> {
+        let precedence = |origin, important| match (origin, important) {
+            (Origin::UserAgent, false) => 0,
+            (Origin::Author, false) => 1,
+            (Origin::Author, true) => 2,
+            (Origin::UserAgent, true) => 3,
+        };
+        let mut matched: Vec<(CascadeKey, &Declaration)> = Vec::new();
+        let rules = self
+            .sheets
+            .iter()
+            .flat_map(|(origin, sheet)| sheet.rules.iter().map(move |rule| (*origin, rule)));
+        for (order, (origin, rule)) in rules.enumerate() {
+            if !rule.media.iter().all(|media| media.matches(&self.media)) {
+                continue;
+            }
+            let specificity = rule
+                .selectors
+                .iter()
+                .filter(|selector| element.matches(selector, self.mode))
+                .map(Selector::specificity)
+                .max();
+            let Some(specificity) = specificity else {
+                continue;
+            };
+            for declaration in &rule.declarations {
+                let key = (
+                    precedence(origin, declaration.important),
+                    false,
+                    specificity,
+                    order,
+                );
+                matched.push((key, declaration));
+            }
+        }
+        for declaration in inline {
+            let key = (
+                precedence(Origin::Author, declaration.important),
+                true,
+                (0, 0, 0),
+                0,
+            );
+            matched.push((key, declaration));
+        }
+        // The sort is stable, so later declarations in a rule still win
+        matched.sort_by_key(|(key, _)| *key);
+        matched
+            .into_iter()
+            .map(|(_, declaration)| declaration)
+            .collect()
+    }
+}
+
+/// An element being matched against selectors, with what's needed to walk
+/// to its parent and siblings.
+#[derive(Clone, Copy)]
+struct Element<'a, 'b> {
+    data: &'a ElementData,
+    siblings: &'a [Node],
+    index: usize,
+    parent: Option<&'b Element<'a, 'b>>,
+}
+
+impl<'a, 'b> Element<'a, 'b> {
+    fn sibling(&self, index: usize) -> Option<Self> {
+        match &self.siblings[index].node_type {
+            NodeType::Element(data) => Some(Self {
+                data,
+                index,
+                ..*self
+            }),
+            _ => None,
+        }
+    }
+
+    /// The element siblings before this one, closest first.
+    fn previous_siblings(&self) -> impl Iterator<Item = Self> + '_ {
+        (0..self.index)
+            .rev()
+            .filter_map(|index| self.sibling(index))
+    }
+
+    fn next_siblings(&self) -> impl Iterator<Item = Self> + '_ {
+        (self.index + 1..self.siblings.len()).filter_map(|index| self.sibling(index))
+    }
+
+    fn matches(&self, selector: &Selector, mode: QuirksMode) -> bool {
+        self.matches_from(selector, selector.compounds.len() - 1, mode)
+    }
+
+    /// Whether this element matches the compound selector `i` of `selector`
+    /// and the ones before it match the elements around it.
+    fn matches_from(&self, selector: &Selector, i: usize, mode: QuirksMode) -> bool {
+        if !self.matches_compound(&selector.compounds[i], mode) {
+            return false;
+        }
+        if i == 0 {
+            return true;
+        }
+        let matches = |element: &Element| element.matches_from(selector, i - 1, mode);
+        match selector.combinators[i - 1] {
+            Combinator::Descendant => iter::successors(self.parent, |e| e.parent).any(matches),
+            Combinator::Child => self.parent.is_some_and(matches),
+            Combinator::NextSibling => self.previous_siblings().next().is_some_and(|e| matches(&e)),
+            Combinator::SubsequentSibling => self.previous_siblings().any(|e| matches(&e)),
+        }
+    }
+
+    fn matches_compound(&self, compound: &CompoundSelector, mode: QuirksMode) -> bool {
+        // Ids and classes ignore case in quirks mode
+        let same = |a: &str, b: &str| match mode {
+            QuirksMode::Quirks => a.eq_ignore_ascii_case(b),
+            _ => a == b,
+        };
+        compound.parts.iter().all(|part| match part {
+            SimpleSelector::Universal => true,
+            SimpleSelector::Type(tag) => self.data.tag == *tag,
+            SimpleSelector::Id(id) => self.data.id().is_some_and(|value| same(value, id)),
+            SimpleSelector::Class(class) => self.data.classes().any(|c| same(c, class)),
+            SimpleSelector::Attribute(attribute) => self.matches_attribute(attribute),
+            SimpleSelector::PseudoClass(name) => self.matches_pseudo_class(name),
+            // Pseudo-elements aren't elements, so rules for them never apply
+            SimpleSelector::PseudoElement(_) => false,
+        })
+    }
+
+    fn matches_attribute(&self, attribute: &AttributeSelector) -> bool {
+        let Some(actual) = self.data.attrs.get(&attribute.name) else {
+            return false;
+        };
+        let Some((operator, expected)) = &attribute.value else {
+            return true;
+        };
+        let (actual, expected) = (actual.as_str(), expected.as_str());
+        let same = |a: &str, b: &str| match attribute.case_insensitive {
+            true => a.eq_ignore_ascii_case(b),
+            false => a == b,
+        };
+        // Slicing by the expected length can land inside a character, which
+        // never matches
+        let part_at = |start: usize| actual.get(start..start + expected.len());
+        let starts_with = || part_at(0).is_some_and(|start| same(start, expected));
+        match operator {
+            AttributeOperator::Equals => same(actual, expected),
+            AttributeOperator::Includes => actual
+                .split_ascii_whitespace()
+                .any(|word| same(word, expected)),
+            AttributeOperator::DashMatch => {
+                same(actual, expected) || starts_with() && actual[expected.len()..].starts_with('-')
+            }
+            AttributeOperator::Prefix => !expected.is_empty() && starts_with(),
+            AttributeOperator::Suffix => {
+                let start = actual.len().checked_sub(expected.len());
+                !expected.is_empty()
+                    && start
+                        .and_then(part_at)
+                        .is_some_and(|end| same(end, expected))
+            }
+            AttributeOperator::Substring => match attribute.case_insensitive {
+                _ if expected.is_empty() => false,
+                false => actual.contains(expected),
+                true => (0..=actual.len().saturating_sub(expected.len()))
+                    .filter_map(part_at)
+                    .any(|part| same(part, expected)),
+            },
+        }
+    }
+
+    /// Matches the structural pseudo-classes. The others, which depend on
+    /// user interaction or aren't supported, never match.
+    fn matches_pseudo_class(&self, name: &str) -> bool {
+        let same_type = |e: &Element| e.data.tag == self.data.tag;
+        match name {
+            "root" => self.parent.is_none(),
+            "first-child" => self.previous_siblings().next().is_none(),
+            "last-child" => self.next_siblings().next().is_none(),
+            "only-child" => self
+                .previous_siblings()
+                .chain(self.next_siblings())
+                .next()
+                .is_none(),
+            "first-of-type" => !self.previous_siblings().any(|e| same_type(&e)),
+            "last-of-type" => !self.next_siblings().any(|e| same_type(&e)),
+            "only-of-type" => !self
+                .previous_siblings()
+                .chain(self.next_siblings())
+                .any(|e| same_type(&e)),
+            "empty" => {
+                self.siblings[self.index]
+                    .children
+                    .iter()
+                    .all(|child| match &child.node_type {
+                        NodeType::Element(_) => false,
+                        NodeType::Text(text) => text.is_empty(),
+                        NodeType::Comment(_) | NodeType::Doctype(_) => true,
+                    })
+            }
+            "link" | "any-link" => {
+                matches!(self.data.tag.as_str(), "a" | "area")
+                    && self.data.attrs.contains_key("href")
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use reqwest::Url;
+
+    use super::{
+        author_stylesheets, resolve_urls, style_tree, Display, Media, StyledNode, TextStyle,
+        URL_ATTRIBUTES,
+    };
+    use crate::engine::css::{self, Color, Unit, Value};
+    use crate::engine::{Document, Node, NodeType, Parser, QuirksMode};
+
+    fn parse_document(source: &str) -> Document {
+        let mut parser = Parser::new();
+        parser.feed_str(source);
+        parser.finish()
+    }
+
+    /// The first element with `tag` in document order.
+    fn find<'a>(node: &'a StyledNode<'a>, tag: &str) -> Option<&'a StyledNode<'a>> {
+        if let NodeType::Element(data) = &node.node.node_type {
+            if data.tag == tag {
+                return Some(node);
+            }
+        }
+        node.children.iter().find_map(|child| find(child, tag))
+    }
+
+    fn keyword(name: &str) -> Vec<Value> {
+        vec![Value::Keyword(name.to_string())]
+    }
+
+    fn color_of(root: &Node, css: &str, mode: QuirksMode, tag: &str) -> Option<String> {
+        let styled = style_tree(root, &[css::parse(css)], mode, &Media::default());
+        find(&styled, tag)?.keyword("color").map(str::to_string)
+    }
+
+    #[test]
+    fn matches_selectors() {
+        let root = parse_document(
+            r#"<div id=main class="a b"><p lang=en-GB>one</p><ul><li>x<li class=last>y</ul><em>e</em></div>"#,
+        )
+        .into_root();
+        let color = |css: &str, tag: &str| color_of(&root, css, QuirksMode::NoQuirks, tag);
+        assert_eq!(color("#main { color: red }", "div").as_deref(), Some("red"));
+        assert_eq!(color(".a.b { color: red }", "div").as_deref(), Some("red"));
+        assert_eq!(color(".a.c { color: red }", "div"), None);
+        assert_eq!(
+            color("[lang|=en] { color: red }", "p").as_deref(),
+            Some("red")
+        );
+        assert_eq!(color("div > p { color: red }", "p").as_deref(), Some("red"));
+        assert_eq!(color("html > p { color: red }", "p"), None);
+        assert_eq!(
+            color("body li { color: red }", "li").as_deref(),
+            Some("red")
+        );
+        assert_eq!(color("li:last-child { color: red }", "li"), None);
+        assert_eq!(
+            color("li:first-child { color: red }", "li").as_deref(),
+            Some("red")
+        );
+        assert_eq!(color("p + ul { color: red }", "ul").as_deref(), Some("red"));
+        assert_eq!(color("p + em { color: red }", "em"), None);
+        assert_eq!(color("p ~ em { color: red }", "em").as_deref(), Some("red"));
+        assert_eq!(color("p::before { color: red }", "p"), None);
+    }
+
+    #[test]
+    fn matches_attributes() {
+        let root = parse_document(r#"<p lang=EN-gb title="Ünïcode Title">x"#).into_root();
+        let matches = |selector: &str| {
+            let css = format!("{selector} {{ color: red }}");
+            color_of(&root, &css, QuirksMode::NoQuirks, "p").is_some()
+        };
+        assert!(matches("[lang|=EN]"));
+        assert!(!matches("[lang|=en]"));
+        assert!(matches("[lang|=en i]"));
+        assert!(matches("[lang^=en- i]"));
+        assert!(matches("[lang$=GB i]"));
+        assert!(!matches("[lang$=GB]"));
+        assert!(matches("[title*=code]"));
+        assert!(matches("[title*=TITLE i]"));
+        assert!(!matches("[title*=TITLE]"));
+        // Only ASCII letters ignore case
+        assert!(matches("[title~=ÜnïCODE i]"));
+        assert!(!matches("[title~=ünïcode i]"));
+        assert!(matches("[title^=Ü]"));
+        assert!(matches("[title*=ï i]"));
+        assert!(matches("[title$=e]"));
+        assert!(!matches("[title$=\"\"]"));
+    }
+
+    #[test]
+    fn splits_classes_on_any_whitespace() {
+        let root = parse_document("<p class=\"a\n\tb  c\">x").into_root();
+        for css in [
+            ".a { color: red }",
+            ".b { color: red }",
+            ".c { color: red }",
+        ] {
+            assert_eq!(
+                color_of(&root, css, QuirksMode::NoQuirks, "p").as_deref(),
+                Some("red")
+            );
+        }
+    }
+
+    #[test]
+    fn ignores_case_of_ids_and_classes_in_quirks_mode() {
+        let root = parse_document("<p id=Intro class=Note>x").into_root();
+        for css in ["#intro { color: red }", ".note { color: red }"] {
+            assert_eq!(color_of(&root, css, QuirksMode::NoQuirks, "p"), None);
+            assert_eq!(
+                color_of(&root, css, QuirksMode::Quirks, "p").as_deref(),
+                Some("red")
+            );
+        }
+    }
+
+    #[test]
+    fn cascades_by_importance_specificity_and_order() {
+        let root = parse_document("<p id=x class=y>text</p>").into_root();
+        let color = |css: &str| color_of(&root, css, QuirksMode::NoQuirks, "p").unwrap();
+        assert_eq!(color("p { color: red } p { color: blue }"), "blue");
+        assert_eq!(color(".y { color: red } p { color: blue }"), "red");
+        assert_eq!(color("#x { color: red } p.y { color: blue }"), "red");
+        assert_eq!(
+            color("p { color: red !important } #x { color: blue }"),
+            "red"
+        );
+        assert_eq!(color("p { color: red; color: blue }"), "blue");
+        assert_eq!(color("p, #x { color: red } .y { color: blue }"), "red");
+    }
+
+    #[test]
+    fn inherits_properties() {
+        let root = parse_document("<div><p>text <b>bold</b></p></div>").into_root();
+        let styled = style_tree(
+            &root,
+            &[css::parse(
+                "div { color: red; border: 1px } p { font-weight: inherit; border: inherit }",
+            )],
+            QuirksMode::NoQuirks,
+            &Media::default(),
+        );
+        let p = find(&styled, "p").unwrap();
+        assert_eq!(p.keyword("color"), Some("red"));
+        assert_eq!(
+            p.value("border"),
+            find(&styled, "div").unwrap().value("border")
+        );
+        assert_eq!(p.value("font-weight"), None);
+        // Text nodes carry the inherited values too
+        assert_eq!(p.children[0].keyword("color"), Some("red"));
+
+        let b = find(&styled, "b").unwrap();
+        assert_eq!(b.keyword("color"), Some("red"));
+        assert_eq!(b.value("border"), None);
+        assert_eq!(b.value("font-weight"), Some(keyword("bold").as_slice()));
+    }
+
+    #[test]
+    fn applies_the_user_agent_stylesheet() {
+        let root = parse_document(
+            "<title>t</title><p>a <span>b</span><ul><li>c</ul><table><tr><td>d</table>",
+        )
+        .into_root();
+        let styled = style_tree(
+            &root,
+            &[css::parse("span { display: block }")],
+            QuirksMode::NoQuirks,
+            &Media::default(),
+        );
+        let display = |tag| find(&styled, tag).unwrap().display();
+        assert_eq!(display("head"), Display::None);
+        assert_eq!(display("title"), Display::None);
+        assert_eq!(display("p"), Display::Block);
+        assert_eq!(display("li"), Display::ListItem);
+        assert_eq!(display("table"), Display::Table);
+        assert_eq!(display("tbody"), Display::TableRowGroup);
+        assert_eq!(display("td"), Display::TableCell);
+        assert_eq!(display("span"), Display::Block);
+        let styled = style_tree(
+            &root,
+            &[css::parse("p { display: initial }")],
+            QuirksMode::NoQuirks,
+            &Media::default(),
+        );
+        assert_eq!(find(&styled, "p").unwrap().display(), Display::Inline);
+    }
+
+    #[test]
+    fn applies_style_attributes() {
+        let root = parse_document(r#"<p id=x style="color: blue; font-weight: bold !important">"#)
+            .into_root();
+        let css = "#x { color: red } p { font-weight: normal !important }";
+        let styled = style_tree(
+            &root,
+            &[css::parse(css)],
+            QuirksMode::NoQuirks,
+            &Media::default(),
+        );
+        let p = find(&styled, "p").unwrap();
+        assert_eq!(p.keyword("color"), Some("blue"));
+        assert_eq!(p.keyword("font-weight"), Some("bold"));
+
+        let css = "#x { color: red !important }";
+        let styled = style_tree(
+            &root,
+            &[css::parse(css)],
+            QuirksMode::NoQuirks,
+            &Media::default(),
+        );
+        assert_eq!(find(&styled, "p").unwrap().keyword("color"), Some("red"));
+    }
+
+    #[test]
+    fn expands_box_shorthands() {
+        let root = parse_document("<p>").into_root();
+        let css = "p { margin: 1px auto 3px; padding: 0 } p { margin-bottom: 4px }";
+        let styled = style_tree(
+            &root,
+            &[css::parse(css)],
+            QuirksMode::NoQuirks,
+            &Media::default(),
+        );
+        let p = find(&styled, "p").unwrap();
+        let px = |n| Some(vec![Value::Length(n, Unit::Px)]);
+        assert_eq!(p.value("margin-top").map(<[_]>::to_vec), px(1.0));
+        assert_eq!(
+            p.value("margin-left").map(<[_]>::to_vec),
+            Some(keyword("auto"))
+        );
+        assert_eq!(
+            p.value("margin-right").map(<[_]>::to_vec),
+            Some(keyword("auto"))
+        );
+        assert_eq!(p.value("margin-bottom").map(<[_]>::to_vec), px(4.0));
+        assert_eq!(
+            p.value("padding-left").map(<[_]>::to_vec),
+            Some(vec![Value::Number(0.0)])
+        );
+    }
+
+    #[test]
+    fn collects_stylesheets_in_document_order() {
+        let root = parse_document(
+            r#"<link rel="Stylesheet" href="/a.css">
+               <style>@import "c.css"; p { color: style }</style>
+               <link rel="alternate stylesheet" href="/alt.css">
+               <link rel=stylesheet href="/missing.css">
+               <style type="text/plain">p { color: plain }</style>
+               <link rel=stylesheet href="css/b.css"><p>"#,
+        )
+        .into_root();
+        let files = HashMap::from([
+            ("https://example.com/a.css", "p { color: a }"),
+            (
+                "https://example.com/css/b.css",
+                "@import url(d.css); p { color: b }",
+            ),
+            ("https://example.com/css/d.css", "p { color: d }"),
+            (
+                "https://example.com/c.css",
+                "@import 'c.css'; p { color: c }",
+            ),
+            ("https://example.com/alt.css", "p { color: alt }"),
+        ]);
+        let base = Url::parse("https://example.com/index.html").unwrap();
+        let mut fetched = Vec::new();
+        let sheets = author_stylesheets(&root, Some(&base), &mut |url| {
+            fetched.push(url.to_string());
+            files.get(url.as_str()).map(|source| source.to_string())
+        });
+
+        let colors: Vec<String> = sheets
+            .iter()
+            .map(|sheet| match &sheet.rules[0].declarations[0].value[0] {
+                Value::Keyword(color) => color.clone(),
+                value => panic!("{value:?}"),
+            })
+            .collect();
+        // c.css imports itself, which is skipped
+        assert_eq!(colors, ["a", "c", "style", "d", "b"]);
+        assert!(fetched.contains(&"https://example.com/missing.css".to_string()));
+        assert!(!fetched.contains(&"https://example.com/alt.css".to_string()));
+    }
+
+    #[test]
+    fn resolves_urls_in_attributes() {
+        let mut root = parse_document(
+            r#"<base href="/docs/"><a href="intro">a</a><img src="../logo.png" alt="">
+               <form action="?q"><button formaction="/search">go</button></form><form></form>
+               <iframe src="https://other.org/frame"></iframe><a href="http://[">bad</a>"#,
+        )
+        .into_root();
+        let base = Url::parse("https://example.com/docs/").unwrap();
+        resolve_urls(&mut root, Some(&base));
+
+        let mut urls = Vec::new();
+        let mut stack = vec![&root];
+        while let Some(node) = stack.pop() {
+            if let NodeType::Element(data) = &node.node_type {
+                for name in URL_ATTRIBUTES {
+                    if let Some(value) = data.attrs.get(name) {
+                        urls.push(format!("{} {name}={value}", data.tag));
+                    }
+                }
+            }
+            stack.extend(node.children.iter().rev());
+        }
+        assert_eq!(
+            urls,
+            [
+                "base href=/docs/",
+                "a href=https://example.com/docs/intro",
+                "img src=https://example.com/logo.png",
+                "form action=https://example.com/docs/?q",
+                "button formaction=https://example.com/search",
+                "form action=https://example.com/docs/",
+                "iframe src=https://other.org/frame",
+                "a href=http://[",
+            ]
+        );
+    }
+
+    #[test]
+    fn loads_each_stylesheet_once() {
+        let root = parse_document(
+            r#"<link rel=stylesheet href="/a.css"><link rel=stylesheet href="/b.css"><p>"#,
+        )
+        .into_root();
+        let imports = "@import 'a.css';".repeat(10);
+        let files = HashMap::from([
+            (
+                "https://example.com/a.css",
+                format!("{imports} @import 'b.css'; p {{ color: a }}"),
+            ),
+            (
+                "https://example.com/b.css",
+                "@import 'a.css'; p { color: b }".to_string(),
+            ),
+        ]);
+        let base = Url::parse("https://example.com/").unwrap();
+        let mut fetched = Vec::new();
+        let sheets = author_stylesheets(&root, Some(&base), &mut |url| {
+            fetched.push(url.to_string());
+            files.get(url.as_str()).cloned()
+        });
+        assert_eq!(sheets.len(), 2);
+        assert_eq!(
+            fetched,
+            ["https://example.com/a.css", "https://example.com/b.css"]
+        );
+    }
+
+    #[test]
+    fn hides_content() {
+        let root = parse_document(
+            r#"<template><p>t</p></template><p hidden>h</p><p aria-hidden=TRUE>a</p>
+               <div class=v><p>v</p><p class=shown>s</p></div><p aria-hidden=false>f</p>"#,
+        )
+        .into_root();
+        let css = ".v { visibility: hidden } .shown { visibility: visible }";
+        let styled = style_tree(
+            &root,
+            &[css::parse(css)],
+            QuirksMode::NoQuirks,
+            &Media::default(),
+        );
+        let shown = |node: &StyledNode| {
+            let NodeType::Element(data) = &node.node.node_type else {
+                unreachable!()
+            };
+            let hidden = node.is_hidden() || !node.is_visible();
+            format!("{}{}", data.tag, if hidden { " hidden" } else { "" })
+        };
+        let body = find(&styled, "body").unwrap();
+        let children: Vec<String> = body
+            .children
+            .iter()
+            .filter(|child| matches!(child.node.node_type, NodeType::Element(_)))
+            .map(shown)
+            .collect();
+        assert_eq!(
+            children,
+            ["template hidden", "p hidden", "p hidden", "div hidden", "p"]
+        );
+        let div = find(&styled, "div").unwrap();
+        let paragraphs: Vec<String> = div.children.iter().map(shown).collect();
+        assert_eq!(paragraphs, ["p hidden", "p"]);
+    }
+
+    #[test]
+    fn computes_text_styles() {
+        let root = parse_document("<div><p>a <a href=x>b <b>c</b></a></p></div>").into_root();
+        let css = "div { background: url(x.png) #fff; color: rgb(0, 0, 128) }
+                   a { text-decoration: none underline } b { font-weight: 700; color: red }";
+        let styled = style_tree(
+            &root,
+            &[css::parse(css)],
+            QuirksMode::NoQuirks,
+            &Media::default(),
+        );
+        let style = |tags: &[&str]| {
+            let mut style = TextStyle::default();
+            for tag in tags {
+                style = find(&styled, tag).unwrap().text_style(&style);
+            }
+            style
+        };
+        let white = Some(Color::rgb(255, 255, 255));
+        let navy = Some(Color::rgb(0, 0, 128));
+        assert_eq!(
+            style(&["div", "p"]),
+            TextStyle {
+                color: navy,
+                background: white,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            style(&["div", "p", "a", "b"]),
+            TextStyle {
+                color: Some(Color::rgb(255, 0, 0)),
+                background: white,
+                bold: true,
+                underline: true,
+                ..Default::default()
+            }
+        );
+    }
+}
@@ -7,12 +7,19 @@ use std::{
 use anyhow::Result;
 use crossterm::event::{self, Event as CrosstermEvent, KeyEvent, MouseEvent};
 
-#[derive(Clone, Copy, Debug)]
+use crate::loader::PageProgress;
+
+#[derive(Debug)]
 pub enum Event {
     Tick,
     Key(KeyEvent),
     Mouse(MouseEvent),
     Resize(u16, u16),
+    /// What a worker has made so far of the page it's loading, by the id
+    /// of the load.
+    PageProgress(u64, Box<PageProgress>),
+    /// Why a worker couldn't load a page, by the id of the load.
+    PageFailed(u64, String),
 }
 
 #[derive(Debug)]
@@ -66,6 +73,11 @@ impl EventHandler {
         }
     }
 
+    /// Where workers send what they make of the pages they load.
+    pub fn sender(&self) -> mpsc::Sender<Event> {
+        self.sender.clone()
+    }
+
     pub fn next(&self) -> Result<Event> {
         Ok(self.receiver.recv()?)
     }
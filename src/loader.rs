@@ -0,0 +1,344 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use encoding_rs::Encoding;
+use reqwest::Url;
+
+use crate::{
+    engine::{
+        author_stylesheets, layout_page, resolve_url, resolve_urls, style_tree, Document,
+        EncodingSource, Media, Page, Parser,
+    },
+    event::Event,
+    network,
+};
+
+/// How often at most a worker shows what it has of a page while more of it
+/// is coming in.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Whether a worker that has read `read` bytes of a page shows what it has
+/// again, given when it last did and how much it had then. Each time lays
+/// out everything read so far, so it waits for the page to double first,
+/// which keeps the work over the whole page linear in its length.
+fn progress_due(read: usize, shown: Option<(Instant, usize)>) -> bool {
+    match shown {
+        None => true,
+        Some((at, len)) => at.elapsed() >= PROGRESS_INTERVAL && read >= len.saturating_mul(2),
+    }
+}
+
+/// The stack of a worker, enough for styling and laying out a tree as
+/// deep as the parser lets it get in a debug build, with room to spare.
+/// Most of it is only ever reserved, not used.
+const WORKER_STACK_SIZE: usize = 16 * 1024 * 1024;
+
+/// How much of a page is read at a time.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Numbers page loads, so what a worker sends only goes to the tab that
+/// is still waiting for it.
+static NEXT_LOAD: AtomicU64 = AtomicU64::new(0);
+
+/// How a page is styled and laid out.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct View {
+    /// What the media queries of the page are matched against.
+    pub media: Media,
+    /// Whether content the page hides is shown anyway.
+    pub show_hidden: bool,
+    /// Whether the lines of code blocks are numbered.
+    pub line_numbers: bool,
+}
+
+/// A page laid out from a document, with what was learned from it on the
+/// way.
+#[derive(Debug, Default)]
+pub struct Rendered {
+    pub page: Page,
+    /// What urls in the page are relative to: its `<base href>`, or else
+    /// where it was loaded from.
+    pub base_url: Option<Url>,
+    pub encoding: Option<(&'static Encoding, EncodingSource)>,
+}
+
+/// Styles and lays out `document` as `view` asks. Linked stylesheets are
+/// taken from `stylesheets`, and the ones not in it yet are fetched into it
+/// if `fetch` is set, until the load is `cancelled`.
+fn render(
+    document: Document,
+    document_url: Option<&Url>,
+    stylesheets: &mut HashMap<Url, Option<String>>,
+    fetch: bool,
+    cancelled: &AtomicBool,
+    view: View,
+) -> Rendered {
+    let encoding = document.encoding;
+    let mode = document.mode;
+    let mut root = document.into_root();
+    let base_url = match root.base_href() {
+        Some(href) => resolve_url(document_url, href).or(document_url.cloned()),
+        None => document_url.cloned(),
+    };
+    resolve_urls(&mut root, base_url.as_ref());
+
+    let mut stylesheet = |url: &Url| {
+        if fetch && !cancelled.load(Ordering::Relaxed) && !stylesheets.contains_key(url) {
+            stylesheets.insert(url.clone(), network::fetch_text(url).ok());
+        }
+        stylesheets.get(url).cloned().flatten()
+    };
+    let sheets = author_stylesheets(&root, base_url.as_ref(), &mut stylesheet);
+    let styled = style_tree(&root, &sheets, mode, &view.media);
+    Rendered {
+        page: layout_page(&styled, &view.media, view.show_hidden, view.line_numbers),
+        base_url,
+        encoding,
+    }
+}
+
+/// Where a page comes from: a url for the worker to download, a body that
+/// is already open with its `Content-Type` and the url it came from, or a
+/// page that is already in and only has to be laid out again.
+pub enum Source {
+    Url(String),
+    #[cfg(test)]
+    Body(Box<dyn Read + Send>, Option<String>, Option<Url>),
+    Downloaded(Box<Downloaded>),
+}
+
+/// A page that is already in, to decode and lay out again after something
+/// changed how it's shown.
+pub struct Downloaded {
+    pub source: Vec<u8>,
+    pub content_type: Option<String>,
+    pub document_url: Option<Url>,
+    /// The stylesheets fetched for the page before.
+    pub stylesheets: HashMap<Url, Option<String>>,
+    /// The encoding to force the page into, or `None` to sniff it again.
+    pub encoding: Option<&'static Encoding>,
+}
+
+/// What a worker has made of a page so far.
+#[derive(Debug)]
+pub struct PageProgress {
+    /// Where the page came from, after any redirects.
+    pub document_url: Option<Url>,
+    pub content_type: Option<String>,
+    /// The bytes of the page read since the last progress.
+    pub source: Vec<u8>,
+    /// The page laid out with what is in so far.
+    pub rendered: Rendered,
+    /// The linked and imported stylesheets of the page, fetched once all
+    /// of it is in.
+    pub stylesheets: HashMap<Url, Option<String>>,
+    /// Whether all of the page is in.
+    pub done: bool,
+}
+
+/// A page loading on a worker. Dropping it cancels the load: the tab stops
+/// waiting for it right away, and the worker stops at its next read or
+/// stylesheet fetch. A worker blocked connecting, reading or fetching keeps
+/// going until the network timeouts give up on it, but nothing it sends is
+/// shown.
+#[derive(Debug)]
+pub struct Loading {
+    pub id: u64,
+    /// How the worker lays the page out, to lay it out again once it's in
+    /// if the tab is shown differently by then.
+    pub view: View,
+    /// Whether the page is only being laid out again, rather than
+    /// downloaded.
+    pub relayout: bool,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Drop for Loading {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Starts loading a page from `source` on a worker thread, which sends
+/// what it makes of it to `events` as it goes.
+pub fn start(source: Source, view: View, events: &mpsc::Sender<Event>) -> Loading {
+    let id = NEXT_LOAD.fetch_add(1, Ordering::Relaxed);
+    let relayout = matches!(source, Source::Downloaded(_));
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let worker = Worker {
+        id,
+        view,
+        events: events.clone(),
+        cancelled: cancelled.clone(),
+    };
+    thread::Builder::new()
+        .name("page loader".to_string())
+        .stack_size(WORKER_STACK_SIZE)
+        .spawn(move || worker.run(source))
+        .expect("failed to start a page loader");
+    Loading {
+        id,
+        view,
+        relayout,
+        cancelled,
+    }
+}
+
+struct Worker {
+    id: u64,
+    view: View,
+    events: mpsc::Sender<Event>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Worker {
+    /// Downloads, parses and lays out the page, showing the top of it while
+    /// the rest comes in.
+    fn run(self, source: Source) {
+        let (mut body, content_type, document_url) = match source {
+            Source::Url(url) => match network::request(&url) {
+                Ok(response) => {
+                    let content_type = network::content_type(&response);
+                    let document_url = Some(response.url().clone());
+                    let body: Box<dyn Read + Send> = Box::new(response);
+                    (body, content_type, document_url)
+                }
+                Err(e) => {
+                    self.send(Event::PageFailed(self.id, e.to_string()));
+                    return;
+                }
+            },
+            #[cfg(test)]
+            Source::Body(body, content_type, document_url) => (body, content_type, document_url),
+            Source::Downloaded(page) => return self.relayout(*page),
+        };
+
+        let mut parser = Parser::new();
+        if let Some(content_type) = &content_type {
+            parser.set_content_type(content_type);
+        }
+        let mut stylesheets = HashMap::new();
+        let mut unsent = Vec::new();
+        let mut read = 0;
+        let mut shown = None;
+        let mut chunk = [0; CHUNK_SIZE];
+        loop {
+            if self.cancelled.load(Ordering::Relaxed) {
+                return;
+            }
+            match body.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(len) => {
+                    read += len;
+                    unsent.extend_from_slice(&chunk[..len]);
+                    parser.feed(&chunk[..len]);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                // Show whatever arrived before the connection failed
+                Err(_) => break,
+            }
+            if !progress_due(read, shown) {
+                continue;
+            }
+            // Linked stylesheets are fetched once the whole page is in, so
+            // they don't hold up showing the top of it
+            let rendered = render(
+                parser.document(),
+                document_url.as_ref(),
+                &mut stylesheets,
+                false,
+                &self.cancelled,
+                self.view,
+            );
+            let progress = PageProgress {
+                document_url: document_url.clone(),
+                content_type: content_type.clone(),
+                source: std::mem::take(&mut unsent),
+                rendered,
+                stylesheets: HashMap::new(),
+                done: false,
+            };
+            if !self.send(Event::PageProgress(self.id, Box::new(progress))) {
+                return;
+            }
+            shown = Some((Instant::now(), read));
+        }
+
+        let rendered = render(
+            parser.finish(),
+            document_url.as_ref(),
+            &mut stylesheets,
+            true,
+            &self.cancelled,
+            self.view,
+        );
+        let progress = PageProgress {
+            document_url,
+            content_type,
+            source: unsent,
+            rendered,
+            stylesheets,
+            done: true,
+        };
+        self.send(Event::PageProgress(self.id, Box::new(progress)));
+    }
+
+    /// Decodes and lays out a page that is already in, all at once.
+    fn relayout(self, page: Downloaded) {
+        let mut parser = Parser::new();
+        if let Some(content_type) = &page.content_type {
+            parser.set_content_type(content_type);
+        }
+        if let Some(encoding) = page.encoding {
+            parser.set_encoding(encoding);
+        }
+        parser.feed(&page.source);
+        let mut stylesheets = page.stylesheets;
+        let rendered = render(
+            parser.finish(),
+            page.document_url.as_ref(),
+            &mut stylesheets,
+            true,
+            &self.cancelled,
+            self.view,
+        );
+        let progress = PageProgress {
+            document_url: page.document_url,
+            content_type: page.content_type,
+            source: Vec::new(),
+            rendered,
+            stylesheets,
+            done: true,
+        };
+        self.send(Event::PageProgress(self.id, Box::new(progress)));
+    }
+
+    /// Sends `event` to the UI, unless the load was cancelled. Returns
+    /// whether it's worth going on.
+    fn send(&self, event: Event) -> bool {
+        !self.cancelled.load(Ordering::Relaxed) && self.events.send(event).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shows_progress_less_often_as_the_page_grows() {
+        assert!(progress_due(10, None));
+        let just_now = Some((Instant::now(), 1000));
+        assert!(!progress_due(5000, just_now));
+        let a_while_ago = Instant::now() - PROGRESS_INTERVAL;
+        assert!(!progress_due(1999, Some((a_while_ago, 1000))));
+        assert!(progress_due(2000, Some((a_while_ago, 1000))));
+    }
+}
@@ -1,39 +1,52 @@
 use anyhow::Result;
 use browser::Browser;
 use event::{Event, EventHandler};
-use ratatui::{backend::CrosstermBackend, Terminal};
+use ratatui::{backend::CrosstermBackend, layout::Rect, Terminal};
 use tui::Tui;
 use update::update;
 
 mod browser;
 mod engine;
 mod event;
+mod loader;
 mod network;
+mod palette;
 mod tui;
 mod ui;
 mod update;
 
 fn main() -> Result<()> {
-    let mut browser = Browser::new();
+    let events = EventHandler::new(100);
+    let mut browser = Browser::new(events.sender());
 
     let backend = CrosstermBackend::new(std::io::stderr());
     let terminal = Terminal::new(backend)?;
-    let events = EventHandler::new(100);
     let mut tui = Tui::new(terminal, events);
 
     tui.enter()?;
+    let (width, height) = crossterm::terminal::size()?;
+    resize(&mut browser, width, height);
 
     while !browser.should_exit {
         tui.draw(&mut browser)?;
 
         match tui.events.next()? {
-            Event::Tick => {}
+            Event::Tick => browser.tick(),
             Event::Key(key_event) => update(&mut browser, key_event),
             Event::Mouse(_) => {}
-            Event::Resize(_, _) => {}
+            Event::Resize(width, height) => resize(&mut browser, width, height),
+            Event::PageProgress(id, progress) => browser.page_progress(id, *progress),
+            Event::PageFailed(id, error) => browser.page_failed(id, error),
         };
     }
 
     tui.exit()?;
     Ok(())
 }
+
+/// Restyles the pages for the space a terminal of `width` by `height` cells
+/// leaves them.
+fn resize(browser: &mut Browser, width: u16, height: u16) {
+    let area = ui::content_area(Rect::new(0, 0, width, height));
+    browser.resize(area.width, area.height);
+}
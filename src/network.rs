@@ -1,14 +1,56 @@
+use std::{sync::OnceLock, time::Duration};
+
 use anyhow::Result;
-use reqwest::Url;
+use reqwest::{
+    blocking::{Client, Response},
+    header::CONTENT_TYPE,
+    Url,
+};
+
+/// How long connecting to a server may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a whole request may take, body included, before the worker
+/// making it gives up.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+fn client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(TIMEOUT)
+            .build()
+            .expect("failed to build the http client")
+    })
+}
 
-/// Temporary solution for network requests
-pub fn request(url: &str) -> Result<String> {
+/// Parses a url as typed into the url bar, which may leave out the scheme.
+pub fn parse_url(url: &str) -> Result<Url> {
     let url = match url.starts_with("http") {
         true => url.parse::<Url>()?,
         false => format!("https://{}", url).parse::<Url>()?,
     };
+    Ok(url)
+}
+
+/// Temporary solution for network requests. The body is left unread so the
+/// page can be parsed as it downloads.
+pub fn request(url: &str) -> Result<Response> {
+    let response = client().get(parse_url(url)?).send()?;
+    Ok(response)
+}
+
+/// Fetches a subresource like a stylesheet as text, decoded with the charset
+/// of its `Content-Type`.
+pub fn fetch_text(url: &Url) -> Result<String> {
+    let response = client().get(url.clone()).send()?.error_for_status()?;
+    Ok(response.text()?)
+}
 
-    let response = reqwest::blocking::get(url)?;
-    let html = response.text()?;
-    Ok(html)
+/// The `Content-Type` header of a response, which may name the encoding of
+/// the body.
+pub fn content_type(response: &Response) -> Option<String> {
+    let value = response.headers().get(CONTENT_TYPE)?;
+    value.to_str().ok().map(str::to_string)
 }
@@ -0,0 +1,247 @@
+use std::env;
+
+use ratatui::style::{Color, Modifier, Style};
+
+use crate::engine::{self, TextStyle};
+
+/// Text drawn with less contrast than this against its background gets a
+/// readable color instead.
+const MIN_CONTRAST: f32 = 3.0;
+
+/// How many colors the terminal can show.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorSupport {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+impl ColorSupport {
+    /// Guesses from `COLORTERM` and `TERM`, as terminals don't say.
+    pub fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            Self::TrueColor
+        } else if term.contains("256color") {
+            Self::Ansi256
+        } else {
+            Self::Ansi16
+        }
+    }
+}
+
+/// The 16 ANSI colors, with the values xterm gives them.
+const ANSI_16: [(Color, engine::Color); 16] = [
+    (Color::Black, engine::Color::rgb(0, 0, 0)),
+    (Color::Red, engine::Color::rgb(205, 0, 0)),
+    (Color::Green, engine::Color::rgb(0, 205, 0)),
+    (Color::Yellow, engine::Color::rgb(205, 205, 0)),
+    (Color::Blue, engine::Color::rgb(0, 0, 238)),
+    (Color::Magenta, engine::Color::rgb(205, 0, 205)),
+    (Color::Cyan, engine::Color::rgb(0, 205, 205)),
+    (Color::Gray, engine::Color::rgb(229, 229, 229)),
+    (Color::DarkGray, engine::Color::rgb(127, 127, 127)),
+    (Color::LightRed, engine::Color::rgb(255, 0, 0)),
+    (Color::LightGreen, engine::Color::rgb(0, 255, 0)),
+    (Color::LightYellow, engine::Color::rgb(255, 255, 0)),
+    (Color::LightBlue, engine::Color::rgb(92, 92, 255)),
+    (Color::LightMagenta, engine::Color::rgb(255, 0, 255)),
+    (Color::LightCyan, engine::Color::rgb(0, 255, 255)),
+    (Color::White, engine::Color::rgb(255, 255, 255)),
+];
+
+/// The levels of each channel in the 6x6x6 color cube of 256 color
+/// terminals.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance(a: engine::Color, b: engine::Color) -> u32 {
+    // Weighted for how sensitive the eye is to each channel
+    let channel = |x: u8, y: u8, weight: u32| weight * (x.abs_diff(y) as u32).pow(2);
+    channel(a.r, b.r, 2) + channel(a.g, b.g, 4) + channel(a.b, b.b, 3)
+}
+
+fn nearest_level(channel: u8) -> usize {
+    (0..CUBE_LEVELS.len())
+        .min_by_key(|&i| CUBE_LEVELS[i].abs_diff(channel))
+        .unwrap()
+}
+
+/// Turns the colors and fonts pages ask for into terminal styles.
+#[derive(Clone, Copy, Debug)]
+pub struct Palette {
+    pub support: ColorSupport,
+    /// The background of the terminal, which pages draw on unless they set
+    /// their own.
+    pub background: engine::Color,
+    /// The color of text the page leaves alone.
+    pub foreground: engine::Color,
+}
+
+impl Palette {
+    pub fn new(support: ColorSupport, background: engine::Color) -> Self {
+        Self {
+            support,
+            background,
+            foreground: engine::Color::rgb(205, 205, 0),
+        }
+    }
+
+    /// Looks at the environment to tell what the terminal can show. The
+    /// background is taken from `COLORFGBG` if set, and assumed to be dark
+    /// otherwise.
+    pub fn detect() -> Self {
+        let light = env::var("COLORFGBG").is_ok_and(|value| {
+            let background = value.rsplit(';').next().unwrap_or_default();
+            matches!(background, "7" | "15")
+        });
+        let background = match light {
+            true => engine::Color::rgb(255, 255, 255),
+            false => engine::Color::rgb(0, 0, 0),
+        };
+        Self::new(ColorSupport::detect(), background)
+    }
+
+    pub fn color(&self, color: engine::Color) -> Color {
+        match self.support {
+            ColorSupport::TrueColor => Color::Rgb(color.r, color.g, color.b),
+            ColorSupport::Ansi256 => {
+                let (r, g, b) = (
+                    nearest_level(color.r),
+                    nearest_level(color.g),
+                    nearest_level(color.b),
+                );
+                let cube = engine::Color::rgb(CUBE_LEVELS[r], CUBE_LEVELS[g], CUBE_LEVELS[b]);
+                let cube_index = 16 + 36 * r + 6 * g + b;
+
+                // The grays run from 8 to 238 in steps of 10
+                let average = (color.r as u32 + color.g as u32 + color.b as u32) / 3;
+                let gray_step = (average.saturating_sub(3) / 10).min(23) as u8;
+                let level = 8 + 10 * gray_step;
+                let gray = engine::Color::rgb(level, level, level);
+
+                match distance(color, gray) < distance(color, cube) {
+                    true => Color::Indexed(232 + gray_step),
+                    false => Color::Indexed(cube_index as u8),
+                }
+            }
+            ColorSupport::Ansi16 => {
+                ANSI_16
+                    .iter()
+                    .min_by_key(|(_, value)| distance(color, *value))
+                    .unwrap()
+                    .0
+            }
+        }
+    }
+
+    /// The terminal style for `style`. Text that would be hard to read
+    /// against its background is drawn in black or white instead.
+    pub fn style(&self, style: &TextStyle) -> Style {
+        let mut out = Style::default();
+        let background = style.background;
+        let foreground = style.color.filter(|color| color.a > 0);
+
+        let against = background.unwrap_or(self.background);
+        let shown = foreground.unwrap_or(self.foreground);
+        let foreground = match shown.contrast(&against) < MIN_CONTRAST {
+            true => {
+                let black = engine::Color::rgb(0, 0, 0);
+                let white = engine::Color::rgb(255, 255, 255);
+                match black.contrast(&against) > white.contrast(&against) {
+                    true => Some(black),
+                    false => Some(white),
+                }
+            }
+            false => foreground,
+        };
+
+        if let Some(color) = foreground {
+            out = out.fg(self.color(color));
+        }
+        if let Some(color) = background {
+            out = out.bg(self.color(color));
+        }
+        let modifiers = [
+            (style.bold, Modifier::BOLD),
+            (style.dim, Modifier::DIM),
+            (style.italic, Modifier::ITALIC),
+            (style.underline, Modifier::UNDERLINED),
+            (style.strikethrough, Modifier::CROSSED_OUT),
+        ];
+        for (set, modifier) in modifiers {
+            if set {
+                out = out.add_modifier(modifier);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ratatui::style::{Color, Modifier, Style};
+
+    use super::{ColorSupport, Palette};
+    use crate::engine::{self, TextStyle};
+
+    const BLACK: engine::Color = engine::Color::rgb(0, 0, 0);
+    const WHITE: engine::Color = engine::Color::rgb(255, 255, 255);
+
+    #[test]
+    fn downsamples_colors() {
+        let orange = engine::Color::rgb(255, 135, 0);
+        let gray = engine::Color::rgb(128, 128, 128);
+        let palette = |support| Palette::new(support, BLACK);
+        assert_eq!(
+            palette(ColorSupport::TrueColor).color(orange),
+            Color::Rgb(255, 135, 0)
+        );
+        assert_eq!(
+            palette(ColorSupport::Ansi256).color(orange),
+            Color::Indexed(208)
+        );
+        assert_eq!(
+            palette(ColorSupport::Ansi256).color(gray),
+            Color::Indexed(244)
+        );
+        assert_eq!(palette(ColorSupport::Ansi16).color(orange), Color::Yellow);
+        assert_eq!(palette(ColorSupport::Ansi16).color(gray), Color::DarkGray);
+        assert_eq!(
+            palette(ColorSupport::Ansi16).color(engine::Color::rgb(0, 0, 128)),
+            Color::Blue
+        );
+    }
+
+    #[test]
+    fn keeps_text_readable() {
+        let dark = Palette::new(ColorSupport::TrueColor, BLACK);
+        let light = Palette::new(ColorSupport::TrueColor, WHITE);
+        let navy = TextStyle {
+            color: Some(engine::Color::rgb(0, 0, 128)),
+            ..Default::default()
+        };
+        assert_eq!(
+            dark.style(&navy),
+            Style::default().fg(Color::Rgb(255, 255, 255))
+        );
+        assert_eq!(
+            light.style(&navy),
+            Style::default().fg(Color::Rgb(0, 0, 128))
+        );
+
+        // Text the page leaves alone on a light background the page sets
+        let on_white = TextStyle {
+            background: Some(WHITE),
+            bold: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            dark.style(&on_white),
+            Style::default()
+                .fg(Color::Rgb(0, 0, 0))
+                .bg(Color::Rgb(255, 255, 255))
+                .add_modifier(Modifier::BOLD)
+        );
+    }
+}
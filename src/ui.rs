@@ -1,50 +1,177 @@
+use std::{borrow::Cow, rc::Rc};
+
 use ratatui::{
+    buffer::Buffer,
     layout::{Alignment, Constraint, Direction, Layout, Rect},
-    style::{Color, Style},
+    style::{Color, Modifier, Style},
     text::{Line, Span, Text},
-    widgets::{Block, BorderType, Borders, Clear, Padding, Paragraph, Tabs, Wrap},
+    widgets::{
+        block::{Position, Title},
+        Block, BorderType, Borders, Clear, Padding, Paragraph, Tabs, Widget,
+    },
     Frame,
 };
+use unicode_width::UnicodeWidthChar;
 
-use crate::browser::{Browser, Screen};
+use crate::{
+    browser::{Browser, Screen},
+    engine::{Page, Run},
+    palette::Palette,
+};
 
-pub fn render(browser: &mut Browser, f: &mut Frame) {
-    let chunks = Layout::default()
+/// The frames of the spinner shown by tabs that are loading a page.
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+fn split(area: Rect) -> Rc<[Rect]> {
+    Layout::default()
         .direction(Direction::Vertical)
         .constraints([Constraint::Min(1), Constraint::Length(3)])
-        .split(f.size());
-
-    browser.set_content_area_height(chunks[0].height);
+        .split(area)
+}
 
-    // Content
-    let content_block = Block::default()
+fn content_block<'a>() -> Block<'a> {
+    Block::default()
         .title("Tuist")
         .title_alignment(Alignment::Center)
         .borders(Borders::ALL)
         .border_type(BorderType::Rounded)
-        .padding(Padding::horizontal(2));
+        .padding(Padding::horizontal(2))
+}
 
-    let content = match browser.has_content() {
-        false => Paragraph::new("New Tab")
-            .block(content_block)
-            .style(Style::default().fg(Color::Yellow))
-            .alignment(Alignment::Center),
-        true => Paragraph::new(&*browser.active_tab().content)
-            .block(content_block)
-            .style(Style::default().fg(Color::Yellow))
-            .alignment(Alignment::Left)
-            .wrap(Wrap { trim: false })
-            .scroll((*browser.scroll(), 0)),
-    };
+/// Where the page goes on a terminal of `size`, inside the borders.
+pub fn content_area(size: Rect) -> Rect {
+    content_block().inner(split(size)[0])
+}
+
+pub fn render(browser: &mut Browser, f: &mut Frame) {
+    let chunks = split(f.size());
+
+    let area = content_area(f.size());
+    browser.set_content_area_height(area.height);
+    browser.set_content_area_width(area.width);
+
+    // Content
+    let mut content_block = content_block();
 
-    f.render_widget(content, chunks[0]);
+    if let Some((encoding, source)) = browser.active_tab().encoding {
+        let encoding = format!(" {} ({}) ", encoding.name(), source);
+        content_block = content_block.title(
+            Title::from(encoding)
+                .position(Position::Bottom)
+                .alignment(Alignment::Right),
+        );
+    }
+
+    if browser.prefer_print {
+        content_block = content_block.title(
+            Title::from(" print styles ")
+                .position(Position::Top)
+                .alignment(Alignment::Right),
+        );
+    }
+
+    if browser.active_tab().show_hidden {
+        content_block = content_block.title(
+            Title::from(" showing hidden content ")
+                .position(Position::Bottom)
+                .alignment(Alignment::Left),
+        );
+    }
+
+    if browser.active_tab().line_numbers {
+        content_block = content_block.title(
+            Title::from(" line numbers ")
+                .position(Position::Top)
+                .alignment(Alignment::Left),
+        );
+    }
+
+    if !browser.link_number.is_empty() {
+        content_block = content_block.title(
+            Title::from(format!(" link {} ", browser.link_number))
+                .position(Position::Bottom)
+                .alignment(Alignment::Center),
+        );
+    }
+
+    let loading = browser.active_tab().loading.as_ref();
+    if let Some(loading) = loading {
+        let status = match loading.relayout {
+            true => " laying out, Esc to stop ",
+            false => " loading, Esc to stop ",
+        };
+        content_block = content_block.title(
+            Title::from(status)
+                .position(Position::Bottom)
+                .alignment(Alignment::Center),
+        );
+    }
+
+    let content_block = content_block.style(Style::default().fg(Color::Yellow));
+    match browser.has_content() {
+        false => {
+            let placeholder = if loading.is_some() {
+                "Loading…"
+            } else {
+                "New Tab"
+            };
+            let content = Paragraph::new(placeholder)
+                .block(content_block)
+                .alignment(Alignment::Center);
+            f.render_widget(content, chunks[0]);
+        }
+        true => {
+            let area = content_block.inner(chunks[0]);
+            f.render_widget(content_block, chunks[0]);
+            let page = PageView {
+                page: &browser.active_tab().page,
+                palette: &browser.palette,
+                scroll: *browser.scroll(),
+                scroll_x: browser.active_tab().scroll_x,
+                focused_link: browser.active_tab().focused_link,
+            };
+            f.render_widget(page, area);
+        }
+    }
+
+    // Link hints
+    if let Screen::Hint = browser.current_screen {
+        let area = content_area(f.size());
+        let style = Style::default()
+            .fg(Color::Black)
+            .bg(Color::Yellow)
+            .add_modifier(Modifier::BOLD);
+        let hints = browser.hints();
+        let shown = hints
+            .iter()
+            .filter(|hint| hint.label.starts_with(&browser.hint_keys));
+        for hint in shown {
+            let Some(width) = (area.width as usize).checked_sub(hint.x) else {
+                continue;
+            };
+            if hint.y < area.height as usize {
+                f.buffer_mut().set_stringn(
+                    area.x + hint.x as u16,
+                    area.y + hint.y as u16,
+                    &hint.label,
+                    width,
+                    style,
+                );
+            }
+        }
+    }
 
     // Tab bar
     let mut tab_items = Vec::<Line>::new();
 
+    let spinner = SPINNER[browser.ticks % SPINNER.len()];
     for tab in &browser.tabs {
+        let title = match tab.loading {
+            Some(_) => Cow::Owned(format!("{spinner} {}", tab.url)),
+            None => Cow::Borrowed(tab.url.as_str()),
+        };
         tab_items.push(Line::from(Span::styled(
-            &tab.url,
+            title,
             Style::default().fg(Color::Yellow),
         )));
     }
@@ -96,6 +223,61 @@ pub fn render(browser: &mut Browser, f: &mut Frame) {
     }
 }
 
+/// The part of a laid out page that is scrolled into view.
+struct PageView<'a> {
+    page: &'a Page,
+    palette: &'a Palette,
+    scroll: usize,
+    scroll_x: usize,
+    focused_link: Option<usize>,
+}
+
+impl Widget for PageView<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if let Some(background) = self.page.background {
+            buf.set_style(area, Style::default().bg(self.palette.color(background)));
+        }
+        let lines = self.scroll..self.scroll + area.height as usize;
+        for run in self.page.lines(lines) {
+            let Some((x, text)) = scroll_run(run, self.scroll_x) else {
+                continue;
+            };
+            let Some(width) = (area.width as usize).checked_sub(x) else {
+                continue;
+            };
+            let mut style = self.palette.style(&run.style);
+            if run.style.link.is_some() && run.style.link == self.focused_link {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            buf.set_stringn(
+                area.x + x as u16,
+                area.y + (run.y - self.scroll) as u16,
+                text,
+                width,
+                style,
+            );
+        }
+    }
+}
+
+/// Where `run` starts and what of it is left once the page is scrolled
+/// `scroll_x` columns to the right, or `None` if all of it is scrolled past.
+fn scroll_run(run: &Run, scroll_x: usize) -> Option<(usize, Cow<'_, str>)> {
+    if run.x >= scroll_x {
+        return Some((run.x - scroll_x, Cow::Borrowed(&run.text)));
+    }
+    let mut x = run.x;
+    for (i, c) in run.text.char_indices() {
+        if x >= scroll_x {
+            // A wide character cut in half leaves a blank where it was
+            let text = format!("{}{}", " ".repeat(x - scroll_x), &run.text[i..]);
+            return Some((0, Cow::Owned(text)));
+        }
+        x += c.width().unwrap_or(0);
+    }
+    None
+}
+
 fn create_centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let layout = Layout::default()
         .direction(Direction::Vertical)
@@ -1,34 +1,47 @@
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
-use crate::{
-    browser::{Browser, Screen},
-    engine::get_text_content,
-    network,
-};
+use crate::browser::{Browser, Screen};
 
 pub fn update(browser: &mut Browser, key_event: KeyEvent) {
     match browser.current_screen {
         Screen::Main => match key_event.code {
             KeyCode::Char('j') => browser.scroll_down(),
+            KeyCode::Char('e') => browser.cycle_encoding(),
+            KeyCode::Char('z') => browser.toggle_hidden(),
+            KeyCode::Char('m') => browser.toggle_print(),
             KeyCode::Char('k') => browser.scroll_up(),
-            KeyCode::Char('p') => {
-                if key_event.modifiers == KeyModifiers::CONTROL {
-                    browser.current_screen = Screen::Edit;
-                    browser.currently_typing = true;
-                }
+            KeyCode::Char('h') | KeyCode::Left => browser.scroll_left(),
+            KeyCode::Char('l') | KeyCode::Right => browser.scroll_right(),
+            KeyCode::Char('n') => browser.toggle_line_numbers(),
+            KeyCode::Char('f') => browser.start_hints(false),
+            KeyCode::Char('F') => browser.start_hints(true),
+            KeyCode::Down => browser.next_link(),
+            KeyCode::Up => browser.prev_link(),
+            KeyCode::Enter => browser.follow_link(),
+            KeyCode::Char(digit) if digit.is_ascii_digit() => browser.link_number.push(digit),
+            KeyCode::Backspace if !browser.link_number.is_empty() => {
+                browser.link_number.pop();
+            }
+            KeyCode::Esc if !browser.link_number.is_empty() => browser.link_number.clear(),
+            KeyCode::Esc if browser.active_tab().loading.is_some() => browser.stop_loading(),
+            KeyCode::Backspace | KeyCode::Char('H') => browser.back(),
+            KeyCode::Char('L') => browser.forward(),
+            KeyCode::Char('p') if key_event.modifiers == KeyModifiers::CONTROL => {
+                browser.current_screen = Screen::Edit;
+                browser.currently_typing = true;
             }
             KeyCode::Esc | KeyCode::Char('q') => {
                 browser.current_screen = Screen::Exit;
             }
-            KeyCode::Char('c') | KeyCode::Char('C') => {
-                if key_event.modifiers == KeyModifiers::CONTROL {
-                    browser.current_screen = Screen::Exit;
-                }
+            KeyCode::Char('c') | KeyCode::Char('C')
+                if key_event.modifiers == KeyModifiers::CONTROL =>
+            {
+                browser.current_screen = Screen::Exit;
             }
-            KeyCode::Char('t') | KeyCode::Char('T') => {
-                if key_event.modifiers == KeyModifiers::CONTROL {
-                    browser.new_tab();
-                }
+            KeyCode::Char('t') | KeyCode::Char('T')
+                if key_event.modifiers == KeyModifiers::CONTROL =>
+            {
+                browser.new_tab();
             }
             KeyCode::Tab => {
                 if key_event.modifiers == KeyModifiers::ALT {
@@ -44,31 +57,30 @@ pub fn update(browser: &mut Browser, key_event: KeyEvent) {
             _ => {}
         },
         Screen::Edit => match key_event.code {
-            KeyCode::Enter => {
-                if browser.currently_typing {
-                    browser.set_url();
-                    let response = network::request(&browser.active_tab().url).unwrap();
-                    let root = crate::engine::parse(response);
-                    let content = get_text_content(&root, &mut String::new());
-                    browser.set_content(content);
-                    browser.current_screen = Screen::Main;
-                    browser.toggle_typing();
-                }
+            KeyCode::Enter if browser.currently_typing => {
+                let url = browser.active_tab().url_field.clone();
+                browser.open(url);
+                browser.current_screen = Screen::Main;
+                browser.toggle_typing();
             }
-            KeyCode::Backspace => {
-                if browser.currently_typing {
-                    browser.active_tab_mut().url_field.pop();
-                }
+            KeyCode::Backspace if browser.currently_typing => {
+                browser.active_tab_mut().url_field.pop();
             }
             KeyCode::Esc => {
                 browser.current_screen = Screen::Main;
                 browser.toggle_typing();
             }
-            KeyCode::Char(value) => {
-                if browser.currently_typing {
-                    browser.active_tab_mut().url_field.push(value);
-                }
+            KeyCode::Char(value) if browser.currently_typing => {
+                browser.active_tab_mut().url_field.push(value);
+            }
+            _ => {}
+        },
+        Screen::Hint => match key_event.code {
+            KeyCode::Esc => browser.stop_hints(),
+            KeyCode::Backspace => {
+                browser.hint_keys.pop();
             }
+            KeyCode::Char(key) => browser.type_hint(key),
             _ => {}
         },
         Screen::Exit => match key_event.code {